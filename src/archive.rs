@@ -0,0 +1,151 @@
+use crate::config::ArchiveFormat;
+use crate::writer;
+use anyhow::{Context, Result};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+const BLOCK: usize = 512;
+
+/// Bundle every player file under `<output_dir>/players` into
+/// `<output_dir>/output.tar` (or `output.tar.zst`), with entries named by
+/// their path relative to `output_dir` (`players/ab/Name.pgn.zst`), in
+/// path order. The archive is rebuilt from scratch and replaced atomically.
+/// Returns the archive path and the number of files in it.
+pub fn archive_players(output_dir: &Path, format: ArchiveFormat) -> Result<(PathBuf, usize)> {
+    let path = output_dir.join(match format {
+        ArchiveFormat::Tar => "output.tar",
+        ArchiveFormat::TarZst => "output.tar.zst",
+    });
+    let tmp = path.with_extension("part");
+    let mut files = writer::player_files(&output_dir.join("players"))?;
+    files.sort();
+
+    let file = BufWriter::new(File::create(&tmp).with_context(|| format!("Cannot create {}", tmp.display()))?);
+    match format {
+        ArchiveFormat::Tar => write_tar(file, output_dir, &files)?.flush()?,
+        ArchiveFormat::TarZst => {
+            let encoder = zstd::stream::write::Encoder::new(file, 3)?;
+            write_tar(encoder, output_dir, &files)?.finish()?.flush()?
+        }
+    }
+    fs::rename(&tmp, &path)?;
+    Ok((path, files.len()))
+}
+
+/// Write `files` as a ustar stream (plus the end-of-archive marker) to `out`.
+fn write_tar<W: Write>(mut out: W, base: &Path, files: &[PathBuf]) -> Result<W> {
+    for path in files {
+        let name = path.strip_prefix(base)?.to_string_lossy().replace('\\', "/");
+        let meta = fs::metadata(path)?;
+        let mtime = meta.modified()?.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        out.write_all(&header(&name, meta.len(), mtime)?)?;
+        let copied = io::copy(&mut File::open(path)?, &mut out)?;
+        if copied != meta.len() {
+            anyhow::bail!("{} changed while archiving", path.display());
+        }
+        let pad = (BLOCK - copied as usize % BLOCK) % BLOCK;
+        out.write_all(&[0; BLOCK][..pad])?;
+    }
+    out.write_all(&[0; 2 * BLOCK])?;
+    Ok(out)
+}
+
+/// A ustar header for a regular file. Names over 100 bytes are split into
+/// the 155-byte prefix field at a `/`.
+fn header(name: &str, size: u64, mtime: u64) -> Result<[u8; BLOCK]> {
+    let (prefix, name) = match name.len() {
+        0..=100 => ("", name),
+        _ => name
+            .char_indices()
+            .filter(|&(i, c)| c == '/' && i <= 155 && name.len() - i - 1 <= 100)
+            .map(|(i, _)| (&name[..i], &name[i + 1..]))
+            .next()
+            .with_context(|| format!("Path too long for a tar entry: {}", name))?,
+    };
+    let mut h = [0u8; BLOCK];
+    h[..name.len()].copy_from_slice(name.as_bytes());
+    octal(&mut h[100..108], 0o644);
+    octal(&mut h[108..116], 0);
+    octal(&mut h[116..124], 0);
+    octal(&mut h[124..136], size);
+    octal(&mut h[136..148], mtime);
+    h[156] = b'0';
+    h[257..263].copy_from_slice(b"ustar\0");
+    h[263..265].copy_from_slice(b"00");
+    h[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+    // The checksum is taken with its own field set to spaces
+    h[148..156].fill(b' ');
+    let sum: u64 = h.iter().map(|&b| b as u64).sum();
+    octal(&mut h[148..155], sum);
+    Ok(h)
+}
+
+/// Zero-padded octal digits followed by a NUL, filling `field`.
+fn octal(field: &mut [u8], value: u64) {
+    let digits = field.len() - 1;
+    field[..digits].copy_from_slice(format!("{:0width$o}", value, width = digits).as_bytes());
+    field[digits] = 0;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    /// Read back (name, contents) pairs from a ustar stream.
+    fn read_tar(data: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let mut entries = Vec::new();
+        let mut pos = 0;
+        while data[pos..pos + BLOCK].iter().any(|&b| b != 0) {
+            let h = &data[pos..pos + BLOCK];
+            let field = |r: std::ops::Range<usize>| {
+                String::from_utf8(h[r].iter().take_while(|&&b| b != 0).copied().collect()).unwrap()
+            };
+            let sum: u64 = h
+                .iter()
+                .enumerate()
+                .map(|(i, &b)| if (148..156).contains(&i) { 32 } else { b as u64 })
+                .sum();
+            assert_eq!(u64::from_str_radix(field(148..155).trim(), 8).unwrap(), sum);
+            let prefix = field(345..500);
+            let name = if prefix.is_empty() { field(0..100) } else { format!("{}/{}", prefix, field(0..100)) };
+            let size = u64::from_str_radix(&field(124..135), 8).unwrap() as usize;
+            pos += BLOCK;
+            entries.push((name, data[pos..pos + size].to_vec()));
+            pos += size.div_ceil(BLOCK) * BLOCK;
+        }
+        entries
+    }
+
+    #[test]
+    fn test_archive_players_round_trip() {
+        let dir = std::env::temp_dir().join(format!("rookt_test_archive_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let long = format!("players/{}/{}.pgn.zst", "m".repeat(80), "N".repeat(40));
+        let files = [
+            ("players/pl/PlayerB.pgn.zst", &b"b"[..]),
+            ("players/ab/Aaron.pgn.zst", &[7; 700]),
+            (&long, b"long"),
+        ];
+        for (path, contents) in files {
+            fs::create_dir_all(dir.join(path).parent().unwrap()).unwrap();
+            fs::write(dir.join(path), contents).unwrap();
+        }
+
+        let (path, count) = archive_players(&dir, ArchiveFormat::Tar).unwrap();
+        assert_eq!((path.file_name().unwrap().to_str().unwrap(), count), ("output.tar", 3));
+        let entries = read_tar(&fs::read(&path).unwrap());
+        let names: Vec<&str> = entries.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, ["players/ab/Aaron.pgn.zst", long.as_str(), "players/pl/PlayerB.pgn.zst"]);
+        assert_eq!(entries[0].1, [7; 700]);
+
+        let (path, _) = archive_players(&dir, ArchiveFormat::TarZst).unwrap();
+        let mut tar = Vec::new();
+        zstd::stream::read::Decoder::new(File::open(&path).unwrap()).unwrap().read_to_end(&mut tar).unwrap();
+        assert_eq!(read_tar(&tar), entries);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,465 @@
+use crate::config::Config;
+use crate::database::Database;
+use crate::events::ConsoleSink;
+use crate::games_index::csv_field;
+use crate::pipeline;
+use crate::profile;
+use crate::writer;
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Return the value following `flag` in `args` (e.g. `--db <path>`).
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|a| a == flag)
+        .and_then(|i| args.get(i + 1))
+        .map(String::as_str)
+}
+
+/// `rookt stats [--db <path>] [--min-total <n>] [--active-between <start> <end>] [--json]`
+///
+/// Print index totals without running the pipeline. Opens the DB read-only.
+/// `--active-between` also counts players seen in both `YYYY-MM` months.
+pub fn stats(args: &[String]) -> Result<()> {
+    let defaults = Config::default_blitz_300();
+    let db_path = flag_value(args, "--db").map(PathBuf::from).unwrap_or(defaults.db_path);
+    let min_total: u32 = match flag_value(args, "--min-total") {
+        Some(v) => v.parse().context("--min-total must be a non-negative integer")?,
+        None => defaults.min_total_games,
+    };
+
+    let db = Database::open_read_only(&db_path)
+        .with_context(|| format!("Cannot open {}", db_path.display()))?;
+    let players = db.get_total_players()?;
+    let qualifying = db.get_total_qualifying_players(min_total)?;
+    let games = db.get_total_games()?;
+    let active = match args.iter().position(|a| a == "--active-between") {
+        Some(i) => {
+            let (Some(start), Some(end)) = (args.get(i + 1), args.get(i + 2)) else {
+                anyhow::bail!("--active-between needs <start> <end> (YYYY-MM)");
+            };
+            Some((start, end, db.players_active_between(start, end)?.len()))
+        }
+        None => None,
+    };
+
+    if args.iter().any(|a| a == "--json") {
+        let mut json = serde_json::json!({
+            "total_players": players,
+            "qualifying_players": qualifying,
+            "min_total_games": min_total,
+            "total_games": games,
+        });
+        if let Some((start, end, count)) = active {
+            json["active_between"] = serde_json::json!({ "start": start, "end": end, "players": count });
+        }
+        println!("{}", json);
+    } else {
+        println!("Players:            {}", players);
+        println!("Qualifying players: {} (>= {} games)", qualifying, min_total);
+        println!("Total games:        {}", games);
+        if let Some((start, end, count)) = active {
+            println!("Active {}..{}: {}", start, end, count);
+        }
+    }
+    Ok(())
+}
+
+/// `rookt search <prefix> [--db <path>] [--limit <n>] [--json]`
+///
+/// List players whose name starts with `prefix` (case-insensitive), their
+/// total games and win/draw/loss record. Opens the DB read-only.
+pub fn search(args: &[String]) -> Result<()> {
+    let Some(prefix) = args.first().filter(|a| !a.starts_with("--")) else {
+        anyhow::bail!("Usage: rookt search <prefix> [--db <path>] [--limit <n>] [--json]");
+    };
+    let db_path = flag_value(args, "--db")
+        .map(PathBuf::from)
+        .unwrap_or(Config::default_blitz_300().db_path);
+    let limit: u32 = match flag_value(args, "--limit") {
+        Some(v) => v.parse().context("--limit must be a non-negative integer")?,
+        None => 20,
+    };
+
+    let db = Database::open_read_only(&db_path)
+        .with_context(|| format!("Cannot open {}", db_path.display()))?;
+    let players = db.find_players_by_prefix(prefix, limit)?;
+
+    if args.iter().any(|a| a == "--json") {
+        let players = players
+            .iter()
+            .map(|(name, total)| {
+                let (wins, draws, losses) = db.player_results(name)?.unwrap_or_default();
+                Ok(serde_json::json!({
+                    "name": name,
+                    "total_games": total,
+                    "wins": wins,
+                    "draws": draws,
+                    "losses": losses,
+                }))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        println!("{}", serde_json::Value::Array(players));
+    } else if players.is_empty() {
+        println!("No players matching '{}'", prefix);
+    } else {
+        for (name, total) in &players {
+            let (wins, draws, losses) = db.player_results(name)?.unwrap_or_default();
+            println!("{:<30} {:<8} +{} ={} -{}", name, total, wins, draws, losses);
+        }
+    }
+    Ok(())
+}
+
+/// `rookt head-to-head <player> [--db <path>] [--limit <n>]`
+///
+/// Print `player`'s opponents as CSV (`opponent,games`), most games first,
+/// from the pairings stored with `track_pairings`. Opens the DB read-only.
+pub fn head_to_head(args: &[String]) -> Result<()> {
+    let Some(player) = args.first().filter(|a| !a.starts_with("--")) else {
+        anyhow::bail!("Usage: rookt head-to-head <player> [--db <path>] [--limit <n>]");
+    };
+    let db_path = flag_value(args, "--db")
+        .map(PathBuf::from)
+        .unwrap_or(Config::default_blitz_300().db_path);
+    let limit: u32 = match flag_value(args, "--limit") {
+        Some(v) => v.parse().context("--limit must be a non-negative integer")?,
+        None => u32::MAX,
+    };
+
+    let db = Database::open_read_only(&db_path)
+        .with_context(|| format!("Cannot open {}", db_path.display()))?;
+    print!("{}", pairings_csv(&db.head_to_head(player, limit)?));
+    Ok(())
+}
+
+/// `opponents` as CSV with a header row, quoting names that need it.
+fn pairings_csv(opponents: &[(String, u32)]) -> String {
+    let mut csv = String::from("opponent,games\n");
+    for (name, games) in opponents {
+        csv.push_str(&format!("{},{}\n", csv_field(name), games));
+    }
+    csv
+}
+
+/// `rookt merge <src_dir> <dst_dir>`
+///
+/// Merge one output directory into another: each source player file is
+/// appended to the destination file at the same path under `players/`
+/// (created if missing), and the source index's counts are added to the
+/// destination index. Both runs should use the same output layout, and
+/// merging the same source twice duplicates its games.
+pub fn merge(args: &[String]) -> Result<()> {
+    let (Some(src), Some(dst)) = (args.first(), args.get(1)) else {
+        anyhow::bail!("Usage: rookt merge <src_dir> <dst_dir>");
+    };
+    let (src, dst) = (Path::new(src), Path::new(dst));
+    let src_db = src.join("index.db");
+    if !src_db.exists() {
+        anyhow::bail!("No database at {}", src_db.display());
+    }
+
+    let src_players = src.join("players");
+    let dst_players = dst.join("players");
+    let files = writer::player_files(&src_players)?;
+    let mut created = 0;
+    let mut bytes = 0;
+    for path in &files {
+        let target = dst_players.join(path.strip_prefix(&src_players)?);
+        created += !target.exists() as usize;
+        bytes += writer::append_player_file(path, &target)
+            .with_context(|| format!("Cannot append {} to {}", path.display(), target.display()))?;
+    }
+
+    let dst_db = dst.join("index.db");
+    let mut db = Database::open(&dst_db)
+        .with_context(|| format!("Cannot open {}", dst_db.display()))?;
+    let players = db.merge_counts_from(&src_db)?;
+
+    println!(
+        "Merged {} player files ({} new, {:.1} MB) and {} index entries into {}",
+        files.len(),
+        created,
+        bytes as f64 / 1_048_576.0,
+        players,
+        dst.display()
+    );
+    Ok(())
+}
+
+/// `rookt split <input> [--output <dir>] [--profile <name>]`
+///
+/// Split one local `.pgn.zst`, `.pgn.gz` or `.pgn` file into player files
+/// under `<output>/players` with the headless config's filters and monthly
+/// threshold (a filter profile, then `ROOKT_*` variables apply), without
+/// downloading anything or touching the index.
+pub fn split(args: &[String]) -> Result<()> {
+    let Some(input) = args.first().filter(|a| !a.starts_with("--")) else {
+        anyhow::bail!("Usage: rookt split <input> [--output <dir>] [--profile <name>]");
+    };
+    let mut config = Config::default_blitz_300();
+    if let Some(name) = flag_value(args, "--profile") {
+        profile::apply(&mut config, name)?;
+    }
+    config.apply_env()?;
+    if let Some(dir) = flag_value(args, "--output") {
+        config.output_dir = PathBuf::from(dir);
+    }
+
+    let summary = pipeline::split_pgn(Path::new(input), &config, ConsoleSink::new())?;
+    println!(
+        "Wrote {} games for {} players ({:.1} MB) to {}",
+        summary.games_written,
+        summary.players,
+        summary.bytes_written as f64 / 1_048_576.0,
+        config.players_dir().display()
+    );
+    Ok(())
+}
+
+/// `rookt bench <input|url> [--temp <dir>] [--decode-threads <n>] [--read-buffer-kb <n>] [--mmap] [--profile <name>]`
+///
+/// Run pass 1 alone over one local file or downloaded dataset with the
+/// headless config (`ROOKT_*` variables and `--profile` apply) and report
+/// its throughput and peak memory. Writes nothing but the download.
+pub fn bench(args: &[String]) -> Result<()> {
+    let Some(input) = args.first().filter(|a| !a.starts_with("--")) else {
+        anyhow::bail!(
+            "Usage: rookt bench <input|url> [--temp <dir>] [--decode-threads <n>] [--read-buffer-kb <n>] [--mmap] [--profile <name>]"
+        );
+    };
+    let mut config = Config::default_blitz_300();
+    if let Some(name) = flag_value(args, "--profile") {
+        profile::apply(&mut config, name)?;
+    }
+    config.apply_env()?;
+    if let Some(dir) = flag_value(args, "--temp") {
+        config.temp_dir = PathBuf::from(dir);
+    }
+    if let Some(v) = flag_value(args, "--decode-threads") {
+        config.decode_threads = v.parse().context("--decode-threads must be a non-negative integer")?;
+    }
+    if let Some(v) = flag_value(args, "--read-buffer-kb") {
+        let kb: usize = v.parse().context("--read-buffer-kb must be a positive integer")?;
+        if kb == 0 {
+            anyhow::bail!("--read-buffer-kb must be at least 1 KB");
+        }
+        config.read_buffer_bytes = kb.checked_mul(1024).context("--read-buffer-kb is too large")?;
+    }
+    config.use_mmap |= args.iter().any(|a| a == "--mmap");
+
+    let report = pipeline::bench_pass1(input, &config, ConsoleSink::new())?;
+    let secs = report.elapsed.as_secs_f64();
+    println!("Input:              {}", report.input.display());
+    println!("Games scanned:      {} ({} valid)", report.games_scanned, report.valid_games);
+    println!("Pass 1 time:        {:.2} s", secs);
+    println!("Games/sec:          {:.0}", report.games_per_sec());
+    println!(
+        "Decompressed:       {:.1} MB/s ({:.1} MB from {:.1} MB)",
+        report.decoded_mb_per_sec(),
+        report.decoded_bytes as f64 / 1_048_576.0,
+        report.compressed_bytes as f64 / 1_048_576.0
+    );
+    match report.peak_memory_bytes {
+        Some(bytes) => println!("Peak memory:        {:.1} MB", bytes as f64 / 1_048_576.0),
+        None => println!("Peak memory:        unknown on this platform"),
+    }
+    Ok(())
+}
+
+/// `rookt vacuum [--db <path>]`
+///
+/// Compact the index (VACUUM + WAL truncate) and report the size change.
+pub fn vacuum(args: &[String]) -> Result<()> {
+    let db_path = flag_value(args, "--db")
+        .map(PathBuf::from)
+        .unwrap_or(Config::default_blitz_300().db_path);
+    if !db_path.exists() {
+        anyhow::bail!("No database at {}", db_path.display());
+    }
+
+    let before = db_size(&db_path);
+    let db = Database::open_for_vacuum(&db_path)
+        .with_context(|| format!("Cannot open {}", db_path.display()))?;
+    db.vacuum()?;
+    drop(db);
+    let after = db_size(&db_path);
+
+    println!(
+        "Compacted {}: {:.1} MB -> {:.1} MB",
+        db_path.display(),
+        before as f64 / 1_048_576.0,
+        after as f64 / 1_048_576.0
+    );
+    Ok(())
+}
+
+/// `rookt --list [--db <path>] [--json]`
+///
+/// Print every dataset the index has marked processed, with its compressed
+/// size when recorded: the months a run would skip.
+pub fn list(args: &[String]) -> Result<()> {
+    let db_path = flag_value(args, "--db")
+        .map(PathBuf::from)
+        .unwrap_or(Config::default_blitz_300().db_path);
+    let db = Database::open_read_only(&db_path)
+        .with_context(|| format!("Cannot open {}", db_path.display()))?;
+    let datasets = db.list_processed()?;
+
+    if args.iter().any(|a| a == "--json") {
+        let datasets = datasets
+            .iter()
+            .map(|(url, size)| serde_json::json!({ "url": url, "size_bytes": size }))
+            .collect();
+        println!("{}", serde_json::Value::Array(datasets));
+    } else if datasets.is_empty() {
+        println!("No processed datasets in {}", db_path.display());
+    } else {
+        for (url, size) in &datasets {
+            let size = size.map_or("?".to_string(), |s| format!("{:.1} MB", s as f64 / 1_048_576.0));
+            println!("{:<10} {:>12}  {}", pipeline::extract_month(url), size, url);
+        }
+        println!("{} processed datasets", datasets.len());
+    }
+    Ok(())
+}
+
+/// `rookt clean [--output <dir>] [--temp <dir>] [--include-output] [--yes]`
+///
+/// Delete everything in the temp directory (downloads, `.part` files) and,
+/// with `--include-output`, the player files too, after a confirmation
+/// prompt unless `--yes`. The index is never deleted, even if it sits in
+/// one of those directories.
+pub fn clean(args: &[String]) -> Result<()> {
+    let output_dir = flag_value(args, "--output")
+        .map(PathBuf::from)
+        .unwrap_or(Config::default_blitz_300().output_dir);
+    let temp_dir = flag_value(args, "--temp").map(PathBuf::from).unwrap_or(output_dir.join("temp"));
+    let mut dirs = vec![temp_dir];
+    if args.iter().any(|a| a == "--include-output") {
+        dirs.push(output_dir.join("players"));
+    }
+
+    let targets = clean_targets(&dirs, &output_dir.join("index.db"))?;
+    let bytes: u64 = targets.iter().map(|(_, size)| size).sum();
+    if targets.is_empty() {
+        println!("Nothing to clean");
+        return Ok(());
+    }
+    if !args.iter().any(|a| a == "--yes") {
+        let dirs: Vec<String> = dirs.iter().map(|d| d.display().to_string()).collect();
+        print!(
+            "Delete {} entries ({:.1} MB) from {}? [y/N] ",
+            targets.len(),
+            bytes as f64 / 1_048_576.0,
+            dirs.join(", ")
+        );
+        io::stdout().flush()?;
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim(), "y" | "Y" | "yes") {
+            println!("Aborted");
+            return Ok(());
+        }
+    }
+
+    for (path, _) in &targets {
+        let removed = if path.is_dir() { fs::remove_dir_all(path) } else { fs::remove_file(path) };
+        removed.with_context(|| format!("Cannot delete {}", path.display()))?;
+    }
+    println!("Deleted {} entries, reclaimed {:.1} MB", targets.len(), bytes as f64 / 1_048_576.0);
+    Ok(())
+}
+
+/// The entries directly inside `dirs` with their sizes in bytes (whole
+/// trees for subdirectories), leaving out the database at `db_path` and its
+/// WAL/SHM files, and any directory that contains them.
+fn clean_targets(dirs: &[PathBuf], db_path: &Path) -> Result<Vec<(PathBuf, u64)>> {
+    let keep: Vec<PathBuf> = ["", "-wal", "-shm"]
+        .iter()
+        .map(|suffix| {
+            let mut path = db_path.as_os_str().to_os_string();
+            path.push(suffix);
+            PathBuf::from(path)
+        })
+        .collect();
+    let mut targets = Vec::new();
+    for dir in dirs.iter().filter(|d| d.is_dir()) {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if keep.iter().any(|k| k.starts_with(&path)) {
+                continue;
+            }
+            let size = tree_size(&path)?;
+            targets.push((path, size));
+        }
+    }
+    targets.sort();
+    Ok(targets)
+}
+
+/// Total size of the file or directory tree at `path`, in bytes.
+fn tree_size(path: &Path) -> Result<u64> {
+    let meta = fs::symlink_metadata(path)?;
+    if !meta.is_dir() {
+        return Ok(meta.len());
+    }
+    let mut total = 0;
+    for entry in fs::read_dir(path)? {
+        total += tree_size(&entry?.path())?;
+    }
+    Ok(total)
+}
+
+/// Size of the database file plus its WAL, in bytes.
+fn db_size(db_path: &Path) -> u64 {
+    let mut wal = db_path.as_os_str().to_os_string();
+    wal.push("-wal");
+    [db_path, Path::new(&wal)]
+        .iter()
+        .filter_map(|p| fs::metadata(p).ok())
+        .map(|m| m.len())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pairings_csv_quotes_names() {
+        let opponents = [("Bob".to_string(), 3), ("Team \"A\", B".to_string(), 1)];
+        assert_eq!(pairings_csv(&opponents), "opponent,games\nBob,3\n\"Team \"\"A\"\", B\",1\n");
+        assert_eq!(pairings_csv(&[]), "opponent,games\n");
+    }
+
+    #[test]
+    fn test_clean_targets_keep_the_index() {
+        let dir = std::env::temp_dir().join(format!("rookt_test_clean_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let temp = dir.join("temp");
+        fs::create_dir_all(temp.join("nested")).unwrap();
+        fs::write(temp.join("2025-01.pgn.zst"), [0; 100]).unwrap();
+        fs::write(temp.join("2025-02.pgn.zst.part"), [0; 10]).unwrap();
+        fs::write(temp.join("nested").join("x"), [0; 5]).unwrap();
+        fs::write(dir.join("index.db"), "db").unwrap();
+        fs::write(dir.join("index.db-wal"), "wal").unwrap();
+        fs::write(dir.join("summary.txt"), "s").unwrap();
+
+        let db = dir.join("index.db");
+        let targets = clean_targets(std::slice::from_ref(&temp), &db).unwrap();
+        let expected = [("2025-01.pgn.zst", 100), ("2025-02.pgn.zst.part", 10), ("nested", 5)];
+        let found: Vec<(&str, u64)> =
+            targets.iter().map(|(p, size)| (p.file_name().unwrap().to_str().unwrap(), *size)).collect();
+        assert_eq!(found, expected);
+
+        // Even pointed at the output directory itself, the index survives
+        let targets = clean_targets(std::slice::from_ref(&dir), &db).unwrap();
+        let names: Vec<&str> = targets.iter().map(|(p, _)| p.file_name().unwrap().to_str().unwrap()).collect();
+        assert_eq!(names, ["summary.txt", "temp"]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
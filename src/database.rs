@@ -1,7 +1,31 @@
-use anyhow::Result;
-use rusqlite::{params, Connection};
-use std::collections::HashMap;
-use std::path::Path;
+use crate::dedup::SiteFilter;
+use crate::writer::FileCheckpoint;
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// What extracting one dataset produced, stored by `record_dataset`.
+pub struct DatasetRecord<'a> {
+    pub url: &'a str,
+    pub month: &'a str,
+    pub size_bytes: u64,
+    /// Extraction stopped at the output budget or the scan limit (see
+    /// `mark_dataset_partial`).
+    pub partial: bool,
+    /// Games written per player, for `update_player_counts`.
+    pub counts: &'a HashMap<String, u32>,
+    /// For `update_move_totals`.
+    pub move_totals: &'a HashMap<String, (u32, u64)>,
+    /// For `update_results`, when records are kept.
+    pub results: Option<&'a HashMap<String, (u32, u32, u32)>>,
+    /// Serialized site filters of the players whose filters changed, for
+    /// `save_site_filters`.
+    pub sites: &'a HashMap<String, Vec<u8>>,
+    /// Games per opponent of each player, for `update_pairings`, when
+    /// pairings are tracked.
+    pub pairings: Option<&'a HashMap<String, HashMap<String, u32>>>,
+}
 
 /// SQLite database for tracking player game counts and processed datasets.
 pub struct Database {
@@ -19,6 +43,22 @@ impl Database {
         Ok(db)
     }
 
+    /// Open an existing database for `vacuum`: read-write, but without
+    /// creating or migrating anything, and with no busy timeout, so a lock
+    /// held by another connection fails the vacuum at once (SQLITE_BUSY)
+    /// instead of being waited for.
+    pub fn open_for_vacuum(path: &Path) -> Result<Self> {
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_WRITE)?;
+        conn.busy_timeout(std::time::Duration::ZERO)?;
+        Ok(Self { conn })
+    }
+
+    /// Open an existing database without creating or migrating anything.
+    pub fn open_read_only(path: &Path) -> Result<Self> {
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        Ok(Self { conn })
+    }
+
     fn init_tables(&self) -> Result<()> {
         self.conn.execute_batch(
             "PRAGMA journal_mode = WAL;
@@ -26,24 +66,164 @@ impl Database {
 
              CREATE TABLE IF NOT EXISTS players (
                  name TEXT PRIMARY KEY,
-                 total_games INTEGER NOT NULL DEFAULT 0
+                 total_games INTEGER NOT NULL DEFAULT 0,
+                 first_month TEXT,
+                 last_month TEXT,
+                 wins INTEGER NOT NULL DEFAULT 0,
+                 draws INTEGER NOT NULL DEFAULT 0,
+                 losses INTEGER NOT NULL DEFAULT 0
              );
 
              CREATE TABLE IF NOT EXISTS monthly_counts (
                  player TEXT NOT NULL,
                  month TEXT NOT NULL,
                  games INTEGER NOT NULL,
+                 valid_games INTEGER,
+                 half_moves INTEGER,
+                 wins INTEGER,
+                 draws INTEGER,
+                 losses INTEGER,
                  PRIMARY KEY (player, month)
              );
 
              CREATE TABLE IF NOT EXISTS processed_datasets (
+                 url TEXT PRIMARY KEY,
+                 size_bytes INTEGER
+             );
+
+             CREATE TABLE IF NOT EXISTS partial_datasets (
                  url TEXT PRIMARY KEY
              );
 
+             CREATE TABLE IF NOT EXISTS failed_datasets (
+                 url TEXT PRIMARY KEY,
+                 error TEXT NOT NULL
+             );
+
+             CREATE TABLE IF NOT EXISTS pass2_checkpoints (
+                 url TEXT NOT NULL,
+                 path TEXT NOT NULL,
+                 player TEXT NOT NULL,
+                 games INTEGER NOT NULL,
+                 file_len INTEGER NOT NULL,
+                 start_len INTEGER,
+                 PRIMARY KEY (url, path)
+             );
+
+             CREATE TABLE IF NOT EXISTS player_sites (
+                 player TEXT PRIMARY KEY,
+                 filter BLOB NOT NULL
+             );
+
+             CREATE TABLE IF NOT EXISTS pairings (
+                 player TEXT NOT NULL,
+                 opponent TEXT NOT NULL,
+                 month TEXT NOT NULL,
+                 games INTEGER NOT NULL,
+                 PRIMARY KEY (player, opponent, month)
+             );
+
              CREATE INDEX IF NOT EXISTS idx_monthly_player
                  ON monthly_counts(player);
              CREATE INDEX IF NOT EXISTS idx_players_total
-                 ON players(total_games);",
+                 ON players(total_games);
+             CREATE INDEX IF NOT EXISTS idx_players_name_nocase
+                 ON players(name COLLATE NOCASE);",
+        )?;
+        self.migrate_month_range()?;
+        self.migrate_move_totals()?;
+        self.migrate_dataset_sizes()?;
+        self.migrate_results()?;
+        self.migrate_checkpoint_starts()
+    }
+
+    /// Add `first_month`/`last_month` to databases created before they
+    /// existed, backfilled from `monthly_counts`.
+    fn migrate_month_range(&self) -> Result<()> {
+        let has_column: bool = self.conn.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('players') WHERE name = 'first_month'",
+            [],
+            |row| row.get(0),
+        )?;
+        if has_column {
+            return Ok(());
+        }
+        self.conn.execute_batch(
+            "ALTER TABLE players ADD COLUMN first_month TEXT;
+             ALTER TABLE players ADD COLUMN last_month TEXT;
+             UPDATE players SET
+                 first_month = (SELECT MIN(month) FROM monthly_counts WHERE player = name),
+                 last_month = (SELECT MAX(month) FROM monthly_counts WHERE player = name);",
+        )?;
+        Ok(())
+    }
+
+    /// Add the pass 1 move totals to databases created before they existed.
+    /// Old months stay NULL and are left out of the averages.
+    fn migrate_move_totals(&self) -> Result<()> {
+        let has_column: bool = self.conn.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('monthly_counts') WHERE name = 'half_moves'",
+            [],
+            |row| row.get(0),
+        )?;
+        if has_column {
+            return Ok(());
+        }
+        self.conn.execute_batch(
+            "ALTER TABLE monthly_counts ADD COLUMN valid_games INTEGER;
+             ALTER TABLE monthly_counts ADD COLUMN half_moves INTEGER;",
+        )?;
+        Ok(())
+    }
+
+    /// Add `processed_datasets.size_bytes` to databases created before it
+    /// existed. Datasets processed earlier keep an unknown (NULL) size.
+    fn migrate_dataset_sizes(&self) -> Result<()> {
+        let has_column: bool = self.conn.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('processed_datasets') WHERE name = 'size_bytes'",
+            [],
+            |row| row.get(0),
+        )?;
+        if has_column {
+            return Ok(());
+        }
+        self.conn.execute_batch("ALTER TABLE processed_datasets ADD COLUMN size_bytes INTEGER;")?;
+        Ok(())
+    }
+
+    /// Add `pass2_checkpoints.start_len` to databases created before it
+    /// existed. Checkpoints saved earlier have no known start (NULL).
+    fn migrate_checkpoint_starts(&self) -> Result<()> {
+        let has_column: bool = self.conn.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('pass2_checkpoints') WHERE name = 'start_len'",
+            [],
+            |row| row.get(0),
+        )?;
+        if has_column {
+            return Ok(());
+        }
+        self.conn.execute_batch("ALTER TABLE pass2_checkpoints ADD COLUMN start_len INTEGER;")?;
+        Ok(())
+    }
+
+    /// Add the win/draw/loss columns to databases created before they
+    /// existed. Earlier months stay NULL and players start at 0-0-0.
+    fn migrate_results(&self) -> Result<()> {
+        let has_column: bool = self.conn.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('players') WHERE name = 'wins'",
+            [],
+            |row| row.get(0),
+        )?;
+        if has_column {
+            return Ok(());
+        }
+        self.conn.execute_batch(
+            "ALTER TABLE players ADD COLUMN wins INTEGER NOT NULL DEFAULT 0;
+             ALTER TABLE players ADD COLUMN draws INTEGER NOT NULL DEFAULT 0;
+             ALTER TABLE players ADD COLUMN losses INTEGER NOT NULL DEFAULT 0;
+             ALTER TABLE monthly_counts ADD COLUMN wins INTEGER;
+             ALTER TABLE monthly_counts ADD COLUMN draws INTEGER;
+             ALTER TABLE monthly_counts ADD COLUMN losses INTEGER;",
         )?;
         Ok(())
     }
@@ -58,35 +238,307 @@ impl Database {
         Ok(count > 0)
     }
 
-    /// Mark a dataset URL as processed.
+    /// Mark a dataset URL as processed, clearing any earlier failure.
     pub fn mark_dataset_processed(&self, url: &str) -> Result<()> {
         self.conn.execute(
             "INSERT OR IGNORE INTO processed_datasets (url) VALUES (?1)",
             [url],
         )?;
+        self.conn.execute("DELETE FROM failed_datasets WHERE url = ?1", [url])?;
+        Ok(())
+    }
+
+    /// Record the compressed size of a processed dataset.
+    pub fn set_dataset_size(&self, url: &str, size_bytes: u64) -> Result<()> {
+        self.conn.execute(
+            "UPDATE processed_datasets SET size_bytes = ?2 WHERE url = ?1",
+            params![url, size_bytes as i64],
+        )?;
         Ok(())
     }
 
-    /// Update player counts for a given month. Adds to total_games.
+    /// Compressed size of a processed dataset, if it was recorded.
+    pub fn dataset_size(&self, url: &str) -> Result<Option<u64>> {
+        let size: Option<i64> = self
+            .conn
+            .query_row(
+                "SELECT size_bytes FROM processed_datasets WHERE url = ?1",
+                [url],
+                |row| row.get(0),
+            )
+            .optional()?
+            .flatten();
+        Ok(size.map(|s| s as u64))
+    }
+
+    /// Every processed dataset URL with its compressed size, if recorded,
+    /// in URL (and so month) order. Sizes are None in an index from before
+    /// they were recorded (opened read-only, so not migrated).
+    pub fn list_processed(&self) -> Result<Vec<(String, Option<u64>)>> {
+        let has_column: bool = self.conn.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('processed_datasets') WHERE name = 'size_bytes'",
+            [],
+            |row| row.get(0),
+        )?;
+        let sql = if has_column {
+            "SELECT url, size_bytes FROM processed_datasets ORDER BY url"
+        } else {
+            "SELECT url, NULL FROM processed_datasets ORDER BY url"
+        };
+        let mut stmt = self.conn.prepare(sql)?;
+        let rows = stmt.query_map([], |row| {
+            let size: Option<i64> = row.get(1)?;
+            Ok((row.get(0)?, size.map(|s| s as u64)))
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Mark a dataset processed even though extraction stopped early (output
+    /// budget reached). Its games are not extracted again, since the ones
+    /// already written would be duplicated.
+    pub fn mark_dataset_partial(&self, url: &str) -> Result<()> {
+        self.mark_dataset_processed(url)?;
+        self.conn.execute(
+            "INSERT OR IGNORE INTO partial_datasets (url) VALUES (?1)",
+            [url],
+        )?;
+        Ok(())
+    }
+
+    /// Undo a processed dataset so the next run extracts it again: clear its
+    /// processed/partial flags and take its `month` back out of the player
+    /// totals and month ranges. Players left with no months are removed.
+    /// All in one transaction; returns the number of players adjusted.
+    pub fn unmark_dataset_processed(&mut self, url: &str, month: &str) -> Result<usize> {
+        let tx = self.conn.transaction()?;
+        let adjusted = tx.execute(
+            "UPDATE players SET
+                 total_games = total_games - m.games,
+                 wins = players.wins - COALESCE(m.wins, 0),
+                 draws = players.draws - COALESCE(m.draws, 0),
+                 losses = players.losses - COALESCE(m.losses, 0)
+             FROM (SELECT player, games, wins, draws, losses FROM monthly_counts WHERE month = ?1) AS m
+             WHERE name = m.player",
+            [month],
+        )?;
+        tx.execute("DELETE FROM monthly_counts WHERE month = ?1", [month])?;
+        tx.execute("DELETE FROM pairings WHERE month = ?1", [month])?;
+        // Only players whose range started or ended on this month can have a new range
+        tx.execute(
+            "UPDATE players SET
+                 first_month = (SELECT MIN(month) FROM monthly_counts WHERE player = name),
+                 last_month = (SELECT MAX(month) FROM monthly_counts WHERE player = name)
+             WHERE first_month = ?1 OR last_month = ?1",
+            [month],
+        )?;
+        tx.execute("DELETE FROM players WHERE first_month IS NULL AND total_games <= 0", [])?;
+        tx.execute("DELETE FROM processed_datasets WHERE url = ?1", [url])?;
+        tx.execute("DELETE FROM partial_datasets WHERE url = ?1", [url])?;
+        tx.commit()?;
+        Ok(adjusted)
+    }
+
+    /// Store a dataset's counts and mark it processed (or partial) with its
+    /// size, all or nothing: if any step fails, or the process dies midway,
+    /// none of it is kept and the dataset stays unprocessed. The update
+    /// methods use savepoints rather than transactions, so they nest here.
+    pub fn record_dataset(&mut self, record: &DatasetRecord) -> Result<()> {
+        self.conn.execute_batch("SAVEPOINT record_dataset")?;
+        match self.record_dataset_steps(record) {
+            Ok(()) => {
+                self.conn.execute_batch("RELEASE record_dataset")?;
+                Ok(())
+            }
+            Err(e) => {
+                self.conn.execute_batch("ROLLBACK TO record_dataset; RELEASE record_dataset")?;
+                Err(e)
+            }
+        }
+    }
+
+    fn record_dataset_steps(&mut self, record: &DatasetRecord) -> Result<()> {
+        self.update_player_counts(record.month, record.counts)?;
+        self.update_move_totals(record.month, record.move_totals)?;
+        if let Some(results) = record.results {
+            self.update_results(record.month, results)?;
+        }
+        self.save_site_filters(record.sites)?;
+        if let Some(pairings) = record.pairings {
+            self.update_pairings(record.month, pairings)?;
+        }
+        if record.partial {
+            self.mark_dataset_partial(record.url)?;
+        } else {
+            self.mark_dataset_processed(record.url)?;
+        }
+        self.conn.execute("DELETE FROM pass2_checkpoints WHERE url = ?1", [record.url])?;
+        self.set_dataset_size(record.url, record.size_bytes)
+    }
+
+    /// The stored site filters of `players` (see `PlayerWriter::with_dedup`);
+    /// players without one are left out.
+    pub fn site_filters(&self, players: &HashSet<String>) -> Result<HashMap<String, SiteFilter>> {
+        let mut stmt = self.conn.prepare_cached("SELECT filter FROM player_sites WHERE player = ?1")?;
+        let mut filters = HashMap::new();
+        for player in players {
+            let Some(bytes) = stmt.query_row([player], |row| row.get::<_, Vec<u8>>(0)).optional()? else {
+                continue;
+            };
+            let filter = SiteFilter::from_bytes(&bytes)
+                .with_context(|| format!("Corrupt site filter for {} in the index", player))?;
+            filters.insert(player.clone(), filter);
+        }
+        Ok(filters)
+    }
+
+    /// Store each player's games per opponent for `month`, replacing what
+    /// an earlier run stored for the same player and month.
+    pub fn update_pairings(&mut self, month: &str, pairings: &HashMap<String, HashMap<String, u32>>) -> Result<()> {
+        let tx = self.conn.savepoint()?;
+        {
+            let mut clear = tx.prepare("DELETE FROM pairings WHERE player = ?1 AND month = ?2")?;
+            let mut insert =
+                tx.prepare("INSERT INTO pairings (player, opponent, month, games) VALUES (?1, ?2, ?3, ?4)")?;
+            for (player, opponents) in pairings {
+                clear.execute(params![player, month])?;
+                for (opponent, games) in opponents {
+                    insert.execute(params![player, opponent, month, games])?;
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Up to `limit` of `player`'s opponents with their games against
+    /// them over all months, most games first (ties in name order).
+    pub fn head_to_head(&self, player: &str, limit: u32) -> Result<Vec<(String, u32)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT opponent, SUM(games) AS total FROM pairings WHERE player = ?1
+             GROUP BY opponent ORDER BY total DESC, opponent LIMIT ?2",
+        )?;
+        let opponents = stmt
+            .query_map(params![player, limit], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(opponents)
+    }
+
+    /// Replace the site filters of the given players.
+    pub fn save_site_filters(&self, filters: &HashMap<String, Vec<u8>>) -> Result<()> {
+        let mut upsert = self.conn.prepare_cached("INSERT OR REPLACE INTO player_sites (player, filter) VALUES (?1, ?2)")?;
+        for (player, filter) in filters {
+            upsert.execute(params![player, filter])?;
+        }
+        Ok(())
+    }
+
+    /// Store the state of player files during pass 2 of a dataset (see
+    /// `PlayerWriter::with_journal`), replacing earlier rows for the same
+    /// files. The first row of a file also keeps its length as the start
+    /// of the dataset's pass 2 (see `pass2_starts`). Kept until
+    /// `record_dataset` stores the finished dataset or
+    /// `mark_dataset_failed` gives up on it.
+    pub fn save_pass2_checkpoint(&self, url: &str, files: &[FileCheckpoint]) -> Result<()> {
+        self.conn.execute_batch("SAVEPOINT pass2_checkpoint")?;
+        let saved = (|| -> Result<()> {
+            let mut upsert = self.conn.prepare_cached(
+                "INSERT INTO pass2_checkpoints (url, path, player, games, file_len, start_len)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?5)
+                 ON CONFLICT (url, path) DO UPDATE SET
+                     player = excluded.player, games = excluded.games, file_len = excluded.file_len",
+            )?;
+            for file in files {
+                upsert.execute(params![url, file.path.to_string_lossy(), file.player, file.games, file.len as i64])?;
+            }
+            Ok(())
+        })();
+        match saved {
+            Ok(()) => self.conn.execute_batch("RELEASE pass2_checkpoint")?,
+            Err(_) => self.conn.execute_batch("ROLLBACK TO pass2_checkpoint; RELEASE pass2_checkpoint")?,
+        }
+        saved
+    }
+
+    /// The last pass 2 checkpoint of a dataset that was never recorded,
+    /// i.e. one whose extraction was interrupted. Empty if there is none.
+    pub fn pass2_checkpoint(&self, url: &str) -> Result<Vec<FileCheckpoint>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT path, player, games, file_len FROM pass2_checkpoints WHERE url = ?1 ORDER BY path",
+        )?;
+        let rows = stmt.query_map([url], |row| {
+            let path: String = row.get(0)?;
+            let len: i64 = row.get(3)?;
+            Ok(FileCheckpoint { path: PathBuf::from(path), player: row.get(1)?, games: row.get(2)?, len: len as u64 })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Length of each file a dataset's pass 2 checkpoints cover from before
+    /// that pass 2 first appended to it, across interrupted runs. Files
+    /// checkpointed before this was stored report their last checkpoint.
+    pub fn pass2_starts(&self, url: &str) -> Result<Vec<(PathBuf, u64)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT path, COALESCE(start_len, file_len) FROM pass2_checkpoints WHERE url = ?1 ORDER BY path",
+        )?;
+        let rows = stmt.query_map([url], |row| {
+            let path: String = row.get(0)?;
+            let len: i64 = row.get(1)?;
+            Ok((PathBuf::from(path), len as u64))
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Record that processing a dataset failed. It stays unprocessed, so the
+    /// next run retries it from the start: its pass 2 checkpoints go too,
+    /// since later datasets may append to the same files (the caller
+    /// first cuts them back to `pass2_starts`).
+    pub fn mark_dataset_failed(&self, url: &str, error: &str) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "INSERT OR REPLACE INTO failed_datasets (url, error) VALUES (?1, ?2)",
+            params![url, error],
+        )?;
+        tx.execute("DELETE FROM pass2_checkpoints WHERE url = ?1", [url])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Update player counts for a given month. Adds the change in the
+    /// player's count for `month` to total_games, so recording the same
+    /// month again replaces its earlier count instead of adding to it, and
+    /// widens the player's first/last month range. A re-recorded month's
+    /// move totals are cleared until stored again; its W/D/L is kept, for
+    /// `update_results` to replace.
     pub fn update_player_counts(
         &mut self,
         month: &str,
         counts: &HashMap<String, u32>,
     ) -> Result<()> {
-        let tx = self.conn.transaction()?;
+        let tx = self.conn.savepoint()?;
 
         {
+            let mut previous_monthly = tx.prepare(
+                "SELECT games FROM monthly_counts WHERE player = ?1 AND month = ?2",
+            )?;
             let mut insert_monthly = tx.prepare(
-                "INSERT OR REPLACE INTO monthly_counts (player, month, games) VALUES (?1, ?2, ?3)",
+                "INSERT INTO monthly_counts (player, month, games) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(player, month) DO UPDATE SET games = excluded.games, valid_games = NULL, half_moves = NULL",
             )?;
             let mut upsert_player = tx.prepare(
-                "INSERT INTO players (name, total_games) VALUES (?1, ?2)
-                 ON CONFLICT(name) DO UPDATE SET total_games = total_games + excluded.total_games",
+                "INSERT INTO players (name, total_games, first_month, last_month) VALUES (?1, ?2, ?3, ?3)
+                 ON CONFLICT(name) DO UPDATE SET
+                     total_games = total_games + excluded.total_games,
+                     first_month = MIN(COALESCE(first_month, excluded.first_month), excluded.first_month),
+                     last_month = MAX(COALESCE(last_month, excluded.last_month), excluded.last_month)",
             )?;
 
             for (player, &count) in counts {
+                let previous: i64 = previous_monthly
+                    .query_row(params![player, month], |row| row.get(0))
+                    .optional()?
+                    .unwrap_or(0);
                 insert_monthly.execute(params![player, month, count as i64])?;
-                upsert_player.execute(params![player, count as i64])?;
+                upsert_player.execute(params![player, count as i64 - previous, month])?;
             }
         }
 
@@ -94,6 +546,109 @@ impl Database {
         Ok(())
     }
 
+    /// Store each player's pass 1 totals for `month` as `(valid games,
+    /// half-moves)`. They cover every valid game, not just the written ones,
+    /// so caps and sampling do not skew the average. Only players already
+    /// recorded for `month` by `update_player_counts` are updated.
+    pub fn update_move_totals(
+        &mut self,
+        month: &str,
+        totals: &HashMap<String, (u32, u64)>,
+    ) -> Result<()> {
+        let tx = self.conn.savepoint()?;
+        {
+            let mut update = tx.prepare(
+                "UPDATE monthly_counts SET valid_games = ?3, half_moves = ?4
+                 WHERE player = ?1 AND month = ?2",
+            )?;
+            for (player, &(games, half_moves)) in totals {
+                update.execute(params![player, month, games as i64, half_moves as i64])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Store each player's pass 1 `(wins, draws, losses)` for `month` and add
+    /// them to the player's record. Like the move totals they cover every
+    /// valid game; recording a month again replaces its earlier figures.
+    /// Only players already recorded for `month` are updated.
+    pub fn update_results(&mut self, month: &str, results: &HashMap<String, (u32, u32, u32)>) -> Result<()> {
+        let tx = self.conn.savepoint()?;
+        {
+            let mut previous_monthly = tx.prepare(
+                "SELECT COALESCE(wins, 0), COALESCE(draws, 0), COALESCE(losses, 0)
+                 FROM monthly_counts WHERE player = ?1 AND month = ?2",
+            )?;
+            let mut update_monthly = tx.prepare(
+                "UPDATE monthly_counts SET wins = ?3, draws = ?4, losses = ?5
+                 WHERE player = ?1 AND month = ?2",
+            )?;
+            let mut update_player = tx.prepare(
+                "UPDATE players SET wins = wins + ?2, draws = draws + ?3, losses = losses + ?4
+                 WHERE name = ?1",
+            )?;
+            for (player, &(wins, draws, losses)) in results {
+                let previous: Option<(i64, i64, i64)> = previous_monthly
+                    .query_row(params![player, month], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+                    .optional()?;
+                let Some((prev_wins, prev_draws, prev_losses)) = previous else { continue };
+                update_monthly.execute(params![player, month, wins, draws, losses])?;
+                update_player.execute(params![
+                    player,
+                    wins as i64 - prev_wins,
+                    draws as i64 - prev_draws,
+                    losses as i64 - prev_losses
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// A player's `(wins, draws, losses)`, or None if they are not indexed
+    /// (or the index predates the records and was opened read-only).
+    pub fn player_results(&self, name: &str) -> Result<Option<(u32, u32, u32)>> {
+        let has_column: bool = self.conn.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('players') WHERE name = 'wins'",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_column {
+            return Ok(None);
+        }
+        let results = self
+            .conn
+            .query_row("SELECT wins, draws, losses FROM players WHERE name = ?1", [name], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .optional()?;
+        Ok(results)
+    }
+
+    /// Get players whose average valid game, over all months with move
+    /// totals, is shorter than `min_half_moves`.
+    pub fn get_players_below_avg_half_moves(&self, min_half_moves: f64) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT player FROM monthly_counts WHERE half_moves IS NOT NULL
+             GROUP BY player HAVING SUM(half_moves) < ?1 * SUM(valid_games)",
+        )?;
+        let names = stmt
+            .query_map([min_half_moves], |row| row.get::<_, String>(0))?
+            .filter_map(|r| r.ok())
+            .collect();
+        Ok(names)
+    }
+
+    /// Every indexed player's total games, by name.
+    pub fn get_player_totals(&self) -> Result<HashMap<String, u32>> {
+        let mut stmt = self.conn.prepare("SELECT name, total_games FROM players")?;
+        let totals = stmt
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, u32>(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(totals)
+    }
+
     /// Get all player names with total games below the threshold.
     pub fn get_players_below_total(&self, min_total: u32) -> Result<Vec<String>> {
         let mut stmt = self
@@ -106,16 +661,126 @@ impl Database {
         Ok(names)
     }
 
-    /// Remove players (and their monthly data) with total games below threshold.
-    pub fn remove_players_below_total(&mut self, min_total: u32) -> Result<usize> {
+    /// Get players with monthly counts in fewer than `min_months` distinct months.
+    pub fn get_players_below_active_months(&self, min_months: u32) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT name FROM players
+             WHERE (SELECT COUNT(DISTINCT month) FROM monthly_counts WHERE player = name) < ?1",
+        )?;
+        let names = stmt
+            .query_map([min_months], |row| row.get::<_, String>(0))?
+            .filter_map(|r| r.ok())
+            .collect();
+        Ok(names)
+    }
+
+    /// Players active over the whole `start..=end` range (`YYYY-MM`): first
+    /// seen no later than `start` and last seen no earlier than `end`.
+    pub fn players_active_between(&self, start: &str, end: &str) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT name FROM players WHERE first_month <= ?1 AND last_month >= ?2 ORDER BY name",
+        )?;
+        let names = stmt
+            .query_map([start, end], |row| row.get::<_, String>(0))?
+            .filter_map(|r| r.ok())
+            .collect();
+        Ok(names)
+    }
+
+    /// Up to `limit` players whose name starts with `prefix`, ignoring ASCII
+    /// case like Lichess usernames do, with their totals, in name order.
+    /// A range scan on the NOCASE name index.
+    pub fn find_players_by_prefix(&self, prefix: &str, limit: u32) -> Result<Vec<(String, i64)>> {
+        let upper = format!("{}\u{10FFFF}", prefix);
+        let mut stmt = self.conn.prepare(
+            "SELECT name, total_games FROM players
+             WHERE name >= ?1 COLLATE NOCASE AND name < ?2 COLLATE NOCASE
+             ORDER BY name COLLATE NOCASE LIMIT ?3",
+        )?;
+        let players = stmt
+            .query_map(params![prefix, upper, limit], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(players)
+    }
+
+    /// Remove the given players, their monthly data, site filters and
+    /// pairings (as players; they stay in their opponents' lists).
+    pub fn remove_players(&mut self, names: &[String]) -> Result<usize> {
         let tx = self.conn.transaction()?;
+        let mut deleted = 0;
+        {
+            let mut delete_monthly = tx.prepare("DELETE FROM monthly_counts WHERE player = ?1")?;
+            let mut delete_player = tx.prepare("DELETE FROM players WHERE name = ?1")?;
+            let mut delete_sites = tx.prepare("DELETE FROM player_sites WHERE player = ?1")?;
+            let mut delete_pairings = tx.prepare("DELETE FROM pairings WHERE player = ?1")?;
+            for name in names {
+                delete_monthly.execute([name])?;
+                delete_sites.execute([name])?;
+                delete_pairings.execute([name])?;
+                deleted += delete_player.execute([name])?;
+            }
+        }
+        tx.commit()?;
+        Ok(deleted)
+    }
+
+    /// Add the player counts of the index at `other` to this one: monthly
+    /// counts of the same player and month are summed, totals too, and
+    /// month ranges widen. Players on only one side are carried over as
+    /// they are. Dataset flags are left alone. All in one transaction;
+    /// returns the number of players merged in.
+    pub fn merge_counts_from(&mut self, other: &Path) -> Result<usize> {
+        let other = other.to_str().context("Index path is not valid UTF-8")?;
+        self.conn.execute("ATTACH DATABASE ?1 AS other", [other])?;
+        let merged = self.merge_attached();
+        self.conn.execute("DETACH DATABASE other", [])?;
+        merged
+    }
+
+    fn merge_attached(&mut self) -> Result<usize> {
+        let tx = self.conn.transaction()?;
+        // Move totals stay known only where both sides have them
         tx.execute(
-            "DELETE FROM monthly_counts WHERE player IN (SELECT name FROM players WHERE total_games < ?1)",
-            [min_total],
+            "INSERT INTO monthly_counts (player, month, games, valid_games, half_moves, wins, draws, losses)
+             SELECT player, month, games, valid_games, half_moves, wins, draws, losses
+             FROM other.monthly_counts WHERE true
+             ON CONFLICT(player, month) DO UPDATE SET
+                 games = games + excluded.games,
+                 valid_games = valid_games + excluded.valid_games,
+                 half_moves = half_moves + excluded.half_moves,
+                 wins = wins + excluded.wins,
+                 draws = draws + excluded.draws,
+                 losses = losses + excluded.losses",
+            [],
+        )?;
+        // Indexes from before pairings were tracked have no table for them
+        let has_pairings: bool = tx.query_row(
+            "SELECT COUNT(*) > 0 FROM other.sqlite_master WHERE type = 'table' AND name = 'pairings'",
+            [],
+            |row| row.get(0),
+        )?;
+        if has_pairings {
+            tx.execute(
+                "INSERT INTO pairings (player, opponent, month, games)
+                 SELECT player, opponent, month, games FROM other.pairings WHERE true
+                 ON CONFLICT(player, opponent, month) DO UPDATE SET games = games + excluded.games",
+                [],
+            )?;
+        }
+        let merged = tx.execute(
+            "INSERT INTO players (name, total_games, first_month, last_month, wins, draws, losses)
+             SELECT name, total_games, first_month, last_month, wins, draws, losses FROM other.players WHERE true
+             ON CONFLICT(name) DO UPDATE SET
+                 total_games = total_games + excluded.total_games,
+                 wins = wins + excluded.wins,
+                 draws = draws + excluded.draws,
+                 losses = losses + excluded.losses,
+                 first_month = MIN(COALESCE(first_month, excluded.first_month), COALESCE(excluded.first_month, first_month)),
+                 last_month = MAX(COALESCE(last_month, excluded.last_month), COALESCE(excluded.last_month, last_month))",
+            [],
         )?;
-        let deleted = tx.execute("DELETE FROM players WHERE total_games < ?1", [min_total])?;
         tx.commit()?;
-        Ok(deleted)
+        Ok(merged)
     }
 
     /// Count players with total games >= threshold.
@@ -128,8 +793,36 @@ impl Database {
         Ok(count)
     }
 
+    /// Rebuild the file to drop freelist pages, then checkpoint and truncate
+    /// the WAL. VACUUM takes the write lock for the whole rebuild; opened
+    /// with `open_for_vacuum`, it fails (SQLITE_BUSY) rather than waits
+    /// while another connection holds it, so it never stalls a live
+    /// pipeline.
+    pub fn vacuum(&self) -> Result<()> {
+        self.conn.execute_batch("VACUUM;").map_err(|e| match e.sqlite_error_code() {
+            Some(rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked) => {
+                anyhow::Error::new(e).context("Database is in use by another process")
+            }
+            _ => e.into(),
+        })?;
+        let busy: i64 = self
+            .conn
+            .query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |row| row.get(0))?;
+        if busy != 0 {
+            anyhow::bail!("WAL checkpoint blocked by an open reader; try again later");
+        }
+        Ok(())
+    }
+
+    /// Checkpoint the WAL into the main file and close the connection, so
+    /// no `-wal` file is left for the next reader to replay.
+    pub fn close(self) -> Result<()> {
+        self.conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE);")?;
+        self.conn.close().map_err(|(_, e)| e)?;
+        Ok(())
+    }
+
     /// Count total tracked players.
-    #[allow(dead_code)]
     pub fn get_total_players(&self) -> Result<i64> {
         let count: i64 = self.conn.query_row(
             "SELECT COUNT(*) FROM players",
@@ -138,4 +831,397 @@ impl Database {
         )?;
         Ok(count)
     }
+
+    /// Sum of total_games across all tracked players.
+    pub fn get_total_games(&self) -> Result<i64> {
+        let total: i64 = self.conn.query_row(
+            "SELECT COALESCE(SUM(total_games), 0) FROM players",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(total)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_db(name: &str) -> (Database, std::path::PathBuf) {
+        let dir = std::env::temp_dir().join(format!("rookt_test_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        (Database::open(&dir.join("index.db")).unwrap(), dir)
+    }
+
+    fn counts(names: &[&str]) -> HashMap<String, u32> {
+        names.iter().map(|n| (n.to_string(), 10)).collect()
+    }
+
+    #[test]
+    fn test_month_range_and_active_months_filter() {
+        let (mut db, dir) = temp_db("month_span");
+        // Months arrive out of order: the range must still widen correctly
+        db.update_player_counts("2025-06", &counts(&["Steady", "Burst"])).unwrap();
+        db.update_player_counts("2025-01", &counts(&["Steady"])).unwrap();
+        db.update_player_counts("2025-12", &counts(&["Steady", "Late"])).unwrap();
+        db.update_player_counts("2025-07", &counts(&["Burst"])).unwrap();
+
+        assert_eq!(db.players_active_between("2025-01", "2025-12").unwrap(), ["Steady"]);
+        assert_eq!(db.players_active_between("2025-06", "2025-07").unwrap(), ["Burst", "Steady"]);
+
+        // "Steady" has 3 distinct months, the others 2 and 1
+        let mut few = db.get_players_below_active_months(3).unwrap();
+        few.sort();
+        assert_eq!(few, ["Burst", "Late"]);
+        assert_eq!(db.get_players_below_active_months(2).unwrap(), ["Late"]);
+        assert!(db.get_players_below_active_months(1).unwrap().is_empty());
+
+        assert_eq!(db.remove_players(&few).unwrap(), 2);
+        assert_eq!(db.get_total_players().unwrap(), 1);
+
+        drop(db);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_unmark_dataset_reconciles_counts() {
+        let (mut db, dir) = temp_db("unmark");
+        let url = "https://example.org/lichess_db_standard_rated_2025-02.pgn.zst";
+        db.update_player_counts("2025-01", &counts(&["Both"])).unwrap();
+        db.update_player_counts("2025-02", &counts(&["Both", "Only"])).unwrap();
+        db.mark_dataset_partial(url).unwrap();
+
+        assert_eq!(db.unmark_dataset_processed(url, "2025-02").unwrap(), 2);
+        assert!(!db.is_dataset_processed(url).unwrap());
+        assert_eq!(db.get_total_players().unwrap(), 1, "player with no months left is removed");
+        assert_eq!(db.get_total_games().unwrap(), 10);
+        assert_eq!(db.players_active_between("2025-01", "2025-01").unwrap(), ["Both"]);
+        assert!(db.players_active_between("2025-01", "2025-02").unwrap().is_empty());
+
+        // Re-running the month restores the original totals
+        db.update_player_counts("2025-02", &counts(&["Both", "Only"])).unwrap();
+        assert_eq!(db.get_total_games().unwrap(), 30);
+
+        drop(db);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_recording_a_month_twice_is_idempotent() {
+        let (mut db, dir) = temp_db("idempotent");
+        db.update_player_counts("2025-01", &counts(&["A", "B"])).unwrap();
+        db.update_player_counts("2025-02", &counts(&["A"])).unwrap();
+        db.update_player_counts("2025-01", &counts(&["A", "B"])).unwrap();
+        assert_eq!(db.get_total_games().unwrap(), 30);
+
+        // A changed count replaces the old one
+        let mut fewer = counts(&["A"]);
+        fewer.insert("A".into(), 4);
+        db.update_player_counts("2025-01", &fewer).unwrap();
+        assert_eq!(db.get_total_games().unwrap(), 24);
+        assert_eq!(db.get_total_qualifying_players(14).unwrap(), 1);
+
+        drop(db);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_record_dataset_is_all_or_nothing() {
+        let (mut db, dir) = temp_db("record_dataset");
+        let url = "https://example.org/2025-01.pgn.zst";
+        let written = counts(&["A", "B"]);
+        let move_totals: HashMap<String, (u32, u64)> = [("A".to_string(), (12, 900))].into();
+        let record = DatasetRecord {
+            url,
+            month: "2025-01",
+            size_bytes: 1234,
+            partial: false,
+            counts: &written,
+            move_totals: &move_totals,
+            results: None,
+            sites: &HashMap::new(),
+            pairings: None,
+        };
+
+        // Pass 2 checkpoints: later rows for a file replace earlier ones
+        let file = |path: &str, games, len| FileCheckpoint { path: path.into(), player: "A".into(), games, len };
+        db.save_pass2_checkpoint(url, &[file("a1", 0, 0), file("a2", 0, 70)]).unwrap();
+        db.save_pass2_checkpoint(url, &[file("a1", 3, 40)]).unwrap();
+        assert_eq!(db.pass2_checkpoint(url).unwrap(), [file("a1", 3, 40), file("a2", 0, 70)]);
+        assert!(db.pass2_checkpoint("https://example.org/2025-02.pgn.zst").unwrap().is_empty());
+
+        // Simulate a crash between storing the counts and marking the dataset
+        db.conn
+            .execute_batch(
+                "CREATE TRIGGER crash BEFORE INSERT ON processed_datasets
+                 BEGIN SELECT RAISE(ABORT, 'simulated crash'); END",
+            )
+            .unwrap();
+        assert!(db.record_dataset(&record).is_err());
+        assert_eq!(db.get_total_games().unwrap(), 0, "counts rolled back");
+        assert!(!db.is_dataset_processed(url).unwrap());
+        assert_eq!(db.pass2_checkpoint(url).unwrap().len(), 2, "still resumable");
+
+        // The re-run records the month once
+        db.conn.execute_batch("DROP TRIGGER crash").unwrap();
+        db.record_dataset(&record).unwrap();
+        assert_eq!(db.get_total_games().unwrap(), 20);
+        assert!(db.is_dataset_processed(url).unwrap());
+        assert_eq!(db.dataset_size(url).unwrap(), Some(1234));
+        assert!(db.pass2_checkpoint(url).unwrap().is_empty(), "checkpoint dropped once recorded");
+
+        drop(db);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_find_players_by_prefix() {
+        let (mut db, dir) = temp_db("prefix");
+        db.update_player_counts("2025-01", &counts(&["MagnusC", "magnolia", "Mags", "Hikaru"])).unwrap();
+
+        let found = db.find_players_by_prefix("MAGN", 10).unwrap();
+        assert_eq!(found, [("magnolia".to_string(), 10), ("MagnusC".to_string(), 10)]);
+        assert_eq!(db.find_players_by_prefix("mag", 2).unwrap().len(), 2);
+        assert!(db.find_players_by_prefix("zz", 10).unwrap().is_empty());
+
+        let plan: String = db
+            .conn
+            .query_row(
+                "EXPLAIN QUERY PLAN SELECT name FROM players
+                 WHERE name >= 'a' COLLATE NOCASE AND name < 'b' COLLATE NOCASE",
+                [],
+                |row| row.get(3),
+            )
+            .unwrap();
+        assert!(plan.contains("idx_players_name_nocase"), "{}", plan);
+
+        drop(db);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_average_half_moves_across_months() {
+        let (mut db, dir) = temp_db("avg_moves");
+        db.update_player_counts("2025-01", &counts(&["Long", "Mixed", "Legacy"])).unwrap();
+        db.update_player_counts("2025-02", &counts(&["Long", "Mixed"])).unwrap();
+        // "Mixed": 10 games x 20 plies, then 30 x 100: 3200 / 40 = 80 on average
+        let jan = HashMap::from([("Long".to_string(), (10, 1000)), ("Mixed".to_string(), (10, 200))]);
+        let feb = HashMap::from([("Long".to_string(), (10, 1000)), ("Mixed".to_string(), (30, 3000))]);
+        db.update_move_totals("2025-01", &jan).unwrap();
+        db.update_move_totals("2025-02", &feb).unwrap();
+
+        // "Legacy" has no move totals and is never pruned for them
+        assert!(db.get_players_below_avg_half_moves(80.0).unwrap().is_empty());
+        assert_eq!(db.get_players_below_avg_half_moves(80.5).unwrap(), ["Mixed"]);
+
+        // Re-recording a month clears its totals until they are stored again
+        db.update_player_counts("2025-02", &counts(&["Mixed"])).unwrap();
+        assert_eq!(db.get_players_below_avg_half_moves(80.0).unwrap(), ["Mixed"]);
+
+        drop(db);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_player_totals_span_months() {
+        let (mut db, dir) = temp_db("player_totals");
+        db.update_player_counts("2025-01", &counts(&["Alice", "Bob"])).unwrap();
+        db.update_player_counts("2025-02", &counts(&["Alice"])).unwrap();
+
+        let totals = db.get_player_totals().unwrap();
+        assert_eq!(totals, HashMap::from([("Alice".to_string(), 20), ("Bob".to_string(), 10)]));
+
+        drop(db);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_results_replace_per_month() {
+        let (mut db, dir) = temp_db("results");
+        db.update_player_counts("2025-01", &counts(&["Alice", "Bob"])).unwrap();
+        db.update_player_counts("2025-02", &counts(&["Alice"])).unwrap();
+        let results = |wins, draws, losses| HashMap::from([("Alice".to_string(), (wins, draws, losses))]);
+
+        db.update_results("2025-01", &results(3, 2, 1)).unwrap();
+        db.update_results("2025-02", &results(1, 0, 0)).unwrap();
+        // Recording a month again replaces it
+        db.update_results("2025-01", &results(4, 2, 1)).unwrap();
+        assert_eq!(db.player_results("Alice").unwrap(), Some((5, 2, 1)));
+        assert_eq!(db.player_results("Bob").unwrap(), Some((0, 0, 0)));
+        assert_eq!(db.player_results("Nobody").unwrap(), None);
+
+        db.unmark_dataset_processed("https://example.org/2025-01.pgn.zst", "2025-01").unwrap();
+        assert_eq!(db.player_results("Alice").unwrap(), Some((1, 0, 0)));
+
+        drop(db);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_recording_a_month_twice_keeps_one_record() {
+        let (mut db, dir) = temp_db("results_twice");
+        let url = "https://example.org/2025-01.pgn.zst";
+        let written = HashMap::from([("Alice".to_string(), 5)]);
+        let results = HashMap::from([("Alice".to_string(), (3, 1, 1))]);
+        let record = DatasetRecord {
+            url,
+            month: "2025-01",
+            size_bytes: 0,
+            partial: false,
+            counts: &written,
+            move_totals: &HashMap::new(),
+            results: Some(&results),
+            sites: &HashMap::new(),
+            pairings: None,
+        };
+        db.record_dataset(&record).unwrap();
+        db.record_dataset(&record).unwrap();
+        assert_eq!(db.get_total_games().unwrap(), 5);
+        assert_eq!(db.player_results("Alice").unwrap(), Some((3, 1, 1)));
+
+        // and the month can still be taken back out
+        db.unmark_dataset_processed(url, "2025-01").unwrap();
+        assert_eq!(db.player_results("Alice").unwrap(), None);
+
+        drop(db);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_dataset_size_round_trip() {
+        let (db, dir) = temp_db("dataset_size");
+        let url = "https://example.org/lichess_db_standard_rated_2025-03.pgn.zst";
+        assert_eq!(db.dataset_size(url).unwrap(), None);
+
+        db.mark_dataset_processed(url).unwrap();
+        assert_eq!(db.dataset_size(url).unwrap(), None, "size not recorded yet");
+        let size = 31_457_280_000;
+        db.set_dataset_size(url, size).unwrap();
+        assert_eq!(db.dataset_size(url).unwrap(), Some(size));
+
+        // Marking it again (e.g. after a partial run) keeps the size
+        db.mark_dataset_partial(url).unwrap();
+        assert_eq!(db.dataset_size(url).unwrap(), Some(size));
+
+        drop(db);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_list_processed() {
+        let (db, dir) = temp_db("list_processed");
+        let url = |month: &str| format!("https://example.org/lichess_db_standard_rated_{}.pgn.zst", month);
+        assert!(db.list_processed().unwrap().is_empty());
+
+        db.mark_dataset_processed(&url("2025-03")).unwrap();
+        db.set_dataset_size(&url("2025-03"), 1024).unwrap();
+        db.mark_dataset_processed(&url("2025-01")).unwrap();
+        db.mark_dataset_failed(&url("2025-02"), "corrupt").unwrap();
+        assert_eq!(
+            db.list_processed().unwrap(),
+            [(url("2025-01"), None), (url("2025-03"), Some(1024))],
+            "failed datasets are not processed"
+        );
+        drop(db);
+
+        // An index from before sizes were recorded, opened without migrating
+        let old = dir.join("old.db");
+        let conn = Connection::open(&old).unwrap();
+        conn.execute_batch("CREATE TABLE processed_datasets (url TEXT PRIMARY KEY);").unwrap();
+        conn.execute("INSERT INTO processed_datasets (url) VALUES (?1)", [url("2025-01")]).unwrap();
+        drop(conn);
+        let db = Database::open_read_only(&old).unwrap();
+        assert_eq!(db.list_processed().unwrap(), [(url("2025-01"), None)]);
+
+        drop(db);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_merge_counts_from_other_index() {
+        let (mut dst, dir) = temp_db("merge_dst");
+        let (mut src, src_dir) = temp_db("merge_src");
+        dst.update_player_counts("2025-01", &counts(&["Both", "DstOnly"])).unwrap();
+        src.update_player_counts("2025-01", &counts(&["Both"])).unwrap();
+        src.update_player_counts("2025-03", &counts(&["Both", "SrcOnly"])).unwrap();
+        drop(src);
+
+        assert_eq!(dst.merge_counts_from(&src_dir.join("index.db")).unwrap(), 2);
+        assert_eq!(dst.get_total_players().unwrap(), 3);
+        assert_eq!(dst.get_total_games().unwrap(), 50);
+        assert_eq!(dst.get_total_qualifying_players(30).unwrap(), 1, "Both: 10 + 10 + 10");
+        assert_eq!(dst.players_active_between("2025-01", "2025-03").unwrap(), ["Both"]);
+        let games: i64 = dst
+            .conn
+            .query_row(
+                "SELECT games FROM monthly_counts WHERE player = 'Both' AND month = '2025-01'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(games, 20);
+
+        drop(dst);
+        std::fs::remove_dir_all(dir).unwrap();
+        std::fs::remove_dir_all(src_dir).unwrap();
+    }
+
+    #[test]
+    fn test_head_to_head_sums_months() {
+        let (mut db, dir) = temp_db("head_to_head");
+        let pairings = |rows: &[(&str, &[(&str, u32)])]| -> HashMap<String, HashMap<String, u32>> {
+            rows.iter()
+                .map(|(player, opponents)| {
+                    (player.to_string(), opponents.iter().map(|(o, n)| (o.to_string(), *n)).collect())
+                })
+                .collect()
+        };
+        db.update_pairings("2025-01", &pairings(&[("A", &[("B", 3), ("C", 1)])])).unwrap();
+        db.update_pairings("2025-02", &pairings(&[("A", &[("C", 1), ("D", 2)]), ("B", &[("A", 4)])])).unwrap();
+        let opponents = |db: &Database| db.head_to_head("A", 10).unwrap();
+        let expected = [("B".to_string(), 3), ("C".to_string(), 2), ("D".to_string(), 2)];
+        assert_eq!(opponents(&db), expected);
+        assert_eq!(db.head_to_head("A", 1).unwrap(), [("B".to_string(), 3)]);
+
+        // Recording a month again replaces its pairings
+        db.update_pairings("2025-01", &pairings(&[("A", &[("C", 5)])])).unwrap();
+        assert_eq!(opponents(&db), [("C".to_string(), 6), ("D".to_string(), 2)]);
+
+        db.unmark_dataset_processed("https://example.org/2025-02", "2025-02").unwrap();
+        assert_eq!(opponents(&db), [("C".to_string(), 5)]);
+        db.remove_players(&["A".to_string()]).unwrap();
+        assert!(opponents(&db).is_empty());
+
+        drop(db);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_vacuum_reclaims_space() {
+        let (mut db, dir) = temp_db("vacuum");
+        let path = dir.join("index.db");
+        let names: Vec<String> = (0..5000).map(|i| format!("Player{:05}", i)).collect();
+        let all: HashMap<String, u32> = names.iter().map(|n| (n.clone(), 1)).collect();
+        db.update_player_counts("2025-01", &all).unwrap();
+        db.remove_players(&names).unwrap();
+        db.conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(())).unwrap();
+        let before = std::fs::metadata(&path).unwrap().len();
+
+        db.vacuum().unwrap();
+        assert!(std::fs::metadata(&path).unwrap().len() < before);
+        assert_eq!(std::fs::metadata(dir.join("index.db-wal")).map_or(0, |m| m.len()), 0);
+
+        // A writer in another connection makes it fail instead of wait
+        let writer = Connection::open(&path).unwrap();
+        writer.execute_batch("BEGIN IMMEDIATE;").unwrap();
+        let started = std::time::Instant::now();
+        let err = Database::open_for_vacuum(&path).unwrap().vacuum().unwrap_err();
+        assert!(err.to_string().contains("in use"), "{:#}", err);
+        assert!(started.elapsed() < std::time::Duration::from_secs(1));
+        writer.execute_batch("ROLLBACK;").unwrap();
+        Database::open_for_vacuum(&path).unwrap().vacuum().unwrap();
+
+        drop(db);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
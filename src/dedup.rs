@@ -0,0 +1,143 @@
+use crate::sample::SplitMix64;
+
+/// Bits per item in each slice, and probes per lookup. At full capacity a
+/// slice answers "maybe present" for about 1 in 70,000 new items; across
+/// the slices of a large filter, about 1 in 8,000.
+const BITS_PER_ITEM: u64 = 24;
+const PROBES: u64 = 12;
+/// Items the first slice holds; each further slice holds twice as many.
+const FIRST_CAPACITY: u32 = 64;
+
+/// Scalable Bloom filter over a player's `Site` URLs: it never forgets a
+/// URL, occasionally claims to know one it has not seen, and grows in
+/// slices as URLs are added, so memory follows the games (3 to 6 bytes
+/// each) instead of being reserved up front.
+#[derive(Clone, Default)]
+pub struct SiteFilter {
+    slices: Vec<Slice>,
+}
+
+#[derive(Clone)]
+struct Slice {
+    bits: Vec<u64>,
+    capacity: u32,
+    len: u32,
+}
+
+impl Slice {
+    fn new(capacity: u32) -> Self {
+        let words = (capacity as u64 * BITS_PER_ITEM).div_ceil(64) as usize;
+        Self { bits: vec![0; words], capacity, len: 0 }
+    }
+
+    fn contains(&self, hash: u64) -> bool {
+        positions(&self.bits, hash).all(|bit| self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0)
+    }
+
+    fn insert(&mut self, hash: u64) {
+        for bit in positions(&self.bits, hash) {
+            self.bits[(bit / 64) as usize] |= 1 << (bit % 64);
+        }
+        self.len += 1;
+    }
+}
+
+/// Bit positions of `hash` in `bits`: one SplitMix64 step per probe.
+fn positions(bits: &[u64], hash: u64) -> impl Iterator<Item = u64> + use<> {
+    let len = bits.len() as u64;
+    let mut rng = SplitMix64::new(hash);
+    (0..PROBES).map(move |_| ((rng.next_u64() as u128 * len as u128 * 64) >> 64) as u64)
+}
+
+impl SiteFilter {
+    /// Add `site`, returning false if it (probably) was there already.
+    pub fn insert(&mut self, site: &str) -> bool {
+        let hash = hash_site(site);
+        if self.slices.iter().any(|slice| slice.contains(hash)) {
+            return false;
+        }
+        let full = self.slices.last().is_none_or(|slice| slice.len >= slice.capacity);
+        if full {
+            let capacity = self.slices.last().map_or(FIRST_CAPACITY, |slice| slice.capacity.saturating_mul(2));
+            self.slices.push(Slice::new(capacity));
+        }
+        self.slices.last_mut().unwrap().insert(hash);
+        true
+    }
+
+    /// Whether `site` was (probably) added already, without adding it.
+    pub fn contains(&self, site: &str) -> bool {
+        let hash = hash_site(site);
+        self.slices.iter().any(|slice| slice.contains(hash))
+    }
+
+    /// Serialized form for the index: per slice, its capacity and length
+    /// (little-endian `u32`s) followed by its bit words (`u64`s).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for slice in &self.slices {
+            out.extend_from_slice(&slice.capacity.to_le_bytes());
+            out.extend_from_slice(&slice.len.to_le_bytes());
+            for word in &slice.bits {
+                out.extend_from_slice(&word.to_le_bytes());
+            }
+        }
+        out
+    }
+
+    /// Read back `to_bytes`. Returns None for bytes it did not produce.
+    pub fn from_bytes(mut bytes: &[u8]) -> Option<Self> {
+        let mut slices = Vec::new();
+        while !bytes.is_empty() {
+            let capacity = u32::from_le_bytes(bytes.get(..4)?.try_into().ok()?);
+            let len = u32::from_le_bytes(bytes.get(4..8)?.try_into().ok()?);
+            if capacity == 0 || bytes.len() < 8 + (capacity as u64 * BITS_PER_ITEM / 8) as usize {
+                return None;
+            }
+            let mut slice = Slice::new(capacity);
+            let end = 8 + slice.bits.len() * 8;
+            for (word, chunk) in slice.bits.iter_mut().zip(bytes.get(8..end)?.chunks_exact(8)) {
+                *word = u64::from_le_bytes(chunk.try_into().ok()?);
+            }
+            slice.len = len;
+            slices.push(slice);
+            bytes = &bytes[end..];
+        }
+        Some(Self { slices })
+    }
+}
+
+/// 64-bit FNV-1a of the URL's bytes. Filters are stored in the index, so
+/// the hash must never change between builds.
+fn hash_site(site: &str) -> u64 {
+    site.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_site_filter_grows_and_round_trips() {
+        let mut filter = SiteFilter::default();
+        let sites: Vec<String> = (0..5_000).map(|i| format!("https://lichess.org/{:08x}", i)).collect();
+        let false_positives = sites.iter().filter(|site| !filter.insert(site)).count();
+        assert!(false_positives <= 2, "{} false positives", false_positives);
+        assert!(sites.iter().all(|site| !filter.insert(site)), "every site is remembered");
+        assert!(filter.contains(&sites[0]) && !filter.contains("https://lichess.org/other"));
+        assert!(filter.slices.len() > 1);
+        assert!(filter.to_bytes().len() <= sites.len() * 6, "3 bytes per slot, at most twice the slots");
+
+        let mut copy = SiteFilter::from_bytes(&filter.to_bytes()).unwrap();
+        assert!(!copy.insert(&sites[1234]));
+        assert!(copy.insert("https://lichess.org/new"));
+        assert!(SiteFilter::from_bytes(&[1, 2, 3]).is_none());
+        assert!(SiteFilter::from_bytes(&[]).unwrap().slices.is_empty());
+    }
+
+    #[test]
+    fn test_site_hash_is_fixed() {
+        assert_eq!(hash_site(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(hash_site("a"), 0xaf63_dc4c_8601_ec8c);
+    }
+}
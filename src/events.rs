@@ -1,9 +1,15 @@
+use crate::stats::fmt_duration;
 use anyhow::Result;
+#[cfg(feature = "tui")]
 use indicatif::{ProgressBar, ProgressStyle};
+use std::fmt;
 use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    mpsc, Arc, Condvar, Mutex,
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
 };
+use std::time::Duration;
+#[cfg(feature = "tui")]
+use std::sync::{atomic::AtomicBool, mpsc, Condvar};
 
 // ── Events from pipeline to UI ──────────────────────────────────────────────
 
@@ -11,19 +17,36 @@ use std::sync::{
 pub enum UiEvent {
     Log(String),
 
+    /// Compressed sizes known before the run starts (from earlier runs),
+    /// one entry per dataset in run order.
+    DatasetSizes(Vec<Option<u64>>),
     DatasetStarted { index: usize, total: usize, name: String },
     DatasetSkipped { name: String },
-    DatasetComplete,
-
-    DownloadStarted { total_bytes: u64 },
-    DownloadProgress { bytes_read: u64 },
-    DownloadComplete { size_bytes: u64 },
-
-    /// Progress reading the compressed .zst file (pass 1 or pass 2).
-    FileProgress { bytes_read: u64, total_bytes: u64 },
-
+    /// Dataset recap: players extracted for, game entries written, and time
+    /// spent on it (in two-phase mode, its counting plus its extraction).
+    DatasetComplete { qualifying_players: u64, games_extracted: u64, duration: Duration },
+    /// Dataset failed; the run continues with the next one unless fail-fast.
+    DatasetFailed { name: String, error: String },
+
+    /// Download events carry the dataset index, since prefetched datasets
+    /// download while an earlier one is still being processed. `total_bytes`
+    /// is None when the server sent no content-length.
+    DownloadStarted { index: usize, total_bytes: Option<u64> },
+    DownloadProgress { index: usize, bytes_read: u64 },
+    DownloadComplete { index: usize, size_bytes: u64 },
+
+    /// Progress reading the input file (pass 1 or pass 2). `bytes_read` and
+    /// `total_bytes` are compressed sizes; `decoded_bytes` is the PGN text
+    /// produced so far, for measuring parse throughput.
+    FileProgress { bytes_read: u64, total_bytes: u64, decoded_bytes: u64 },
+
+    /// The input is downloaded (or cached) and is being opened for pass 1,
+    /// which can take a moment on large files.
+    Preparing,
     Pass1Started,
     Pass1Progress { games_scanned: u64, valid_games: u64, unique_players: u64 },
+    /// Players with the most valid games so far in pass 1, most first.
+    TopPlayers(Vec<(String, u32)>),
     Pass1Complete {
         total_scanned: u64,
         valid_games: u64,
@@ -31,19 +54,49 @@ pub enum UiEvent {
         qualifying_games: u64,
     },
 
+    /// Two-phase mode: pass 1 has run over every pending dataset and
+    /// `qualifying_players` reach the total threshold across them.
+    CountingComplete { datasets: u64, qualifying_players: u64 },
+
     Pass2Started,
     Pass2Progress { games_extracted: u64 },
-    Pass2Complete { total_extracted: u64 },
+    /// Compressed bytes flushed to player files so far in this pass 2, sent
+    /// after each flush.
+    WriteProgress { bytes_written: u64 },
+    /// `bytes_written` is the compressed player output of this pass.
+    Pass2Complete { total_extracted: u64, bytes_written: u64 },
 
     PruneStarted { to_remove: u64 },
     PruneComplete { remaining: u64, removed: u64 },
 
+    /// Acknowledges a pause: the pipeline reached a check and is now
+    /// blocked there, so the numbers stop moving.
+    Paused,
+    /// The pipeline left a pause and carries on.
+    Resumed,
+
     Finished,
+    /// The user cancelled the run; datasets completed before it are kept.
+    Cancelled,
     Error(String),
 }
 
 // ── Pipeline control (pause / cancel) ───────────────────────────────────────
 
+/// Error returned by `check()` when the user cancels the run. Lets callers
+/// tell a cancellation apart from a genuine failure via `err.is::<Cancelled>()`.
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Cancelled by user")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+#[cfg(feature = "tui")]
 pub struct PipelineControl {
     paused: AtomicBool,
     cancelled: AtomicBool,
@@ -51,6 +104,7 @@ pub struct PipelineControl {
     cvar: Condvar,
 }
 
+#[cfg(feature = "tui")]
 impl PipelineControl {
     pub fn new() -> Self {
         Self {
@@ -85,18 +139,29 @@ impl PipelineControl {
         self.cancelled.load(Ordering::SeqCst)
     }
 
-    /// Blocks while paused. Returns `Err` if cancelled.
-    pub fn check(&self) -> Result<()> {
+    /// Blocks while paused. Returns `Err` if cancelled. `acknowledge` is
+    /// called with true once it starts blocking and with false when it
+    /// resumes (not on cancel), so the UI can tell a requested pause from
+    /// one in effect.
+    pub fn check(&self, mut acknowledge: impl FnMut(bool)) -> Result<()> {
         if self.cancelled.load(Ordering::SeqCst) {
-            anyhow::bail!("Cancelled by user");
+            return Err(Cancelled.into());
         }
+        let mut blocked = false;
         while self.paused.load(Ordering::SeqCst) {
+            if !blocked {
+                blocked = true;
+                acknowledge(true);
+            }
             let guard = self.lock.lock().unwrap();
             let _guard = self.cvar.wait(guard).unwrap();
             if self.cancelled.load(Ordering::SeqCst) {
-                anyhow::bail!("Cancelled by user");
+                return Err(Cancelled.into());
             }
         }
+        if blocked {
+            acknowledge(false);
+        }
         Ok(())
     }
 }
@@ -112,19 +177,47 @@ pub trait EventSink: Send + Sync {
 
 // ── Console sink (headless mode) ────────────────────────────────────────────
 
+/// Stand-in for `indicatif::ProgressBar` in builds without the `tui`
+/// feature: no bar is drawn and messages are printed as plain lines.
+#[cfg(not(feature = "tui"))]
+struct ProgressBar;
+
+#[cfg(not(feature = "tui"))]
+impl ProgressBar {
+    fn set_position(&self, _pos: u64) {}
+    fn finish_and_clear(&self) {}
+    fn println(&self, msg: String) {
+        println!("{}", msg);
+    }
+}
+
 pub struct ConsoleSink {
     pb: Mutex<Option<ProgressBar>>,
+    /// Index of the dataset being processed; download progress for other
+    /// (prefetched) datasets is not drawn.
+    current_dataset: AtomicUsize,
 }
 
 impl ConsoleSink {
     pub fn new() -> Arc<Self> {
         Arc::new(Self {
             pb: Mutex::new(None),
+            current_dataset: AtomicUsize::new(0),
         })
     }
 
-    fn make_pb(total: u64, template: &str) -> ProgressBar {
-        let pb = ProgressBar::new(total);
+    fn is_current(&self, index: usize) -> bool {
+        self.current_dataset.load(Ordering::SeqCst) == index
+    }
+
+    /// A progress bar, or a spinner when the total is unknown (`template`
+    /// should then leave out the bar, total and ETA).
+    #[cfg(feature = "tui")]
+    fn make_pb(total: Option<u64>, template: &str) -> ProgressBar {
+        let pb = match total {
+            Some(total) => ProgressBar::new(total),
+            None => ProgressBar::new_spinner(),
+        };
         pb.set_style(
             ProgressStyle::default_bar()
                 .template(template)
@@ -133,6 +226,11 @@ impl ConsoleSink {
         );
         pb
     }
+
+    #[cfg(not(feature = "tui"))]
+    fn make_pb(_total: Option<u64>, _template: &str) -> ProgressBar {
+        ProgressBar
+    }
 }
 
 impl EventSink for ConsoleSink {
@@ -141,26 +239,53 @@ impl EventSink for ConsoleSink {
             UiEvent::Log(msg) => println!("  {}", msg),
 
             UiEvent::DatasetStarted { index, total, name } => {
+                self.current_dataset.store(index, Ordering::SeqCst);
                 println!("\n━━━ [{}/{}] {} ━━━", index + 1, total, name);
             }
             UiEvent::DatasetSkipped { name } => {
                 println!("  Already processed: {}", name);
             }
-            UiEvent::DatasetComplete => {}
+            UiEvent::DatasetComplete { qualifying_players, games_extracted, duration } => println!(
+                "  Done: {} qualifying players, {} games extracted in {}",
+                qualifying_players,
+                games_extracted,
+                fmt_duration(duration)
+            ),
+            UiEvent::DatasetFailed { name, error } => {
+                if let Some(pb) = self.pb.lock().unwrap().take() {
+                    pb.finish_and_clear();
+                }
+                eprintln!("\n  ERROR: {} failed, continuing: {}", name, error);
+            }
 
-            UiEvent::DownloadStarted { total_bytes } => {
-                let pb = Self::make_pb(
-                    total_bytes,
-                    "  DL {spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}, ETA: {eta})",
+            UiEvent::DownloadStarted { index, .. }
+            | UiEvent::DownloadProgress { index, .. } if !self.is_current(index) => {}
+            UiEvent::DownloadComplete { index, size_bytes } if !self.is_current(index) => {
+                let msg = format!(
+                    "  Prefetched dataset {} ({:.2} GB)",
+                    index + 1,
+                    size_bytes as f64 / 1_073_741_824.0
                 );
+                match self.pb.lock().unwrap().as_ref() {
+                    Some(pb) => pb.println(msg),
+                    None => println!("{}", msg),
+                }
+            }
+
+            UiEvent::DownloadStarted { total_bytes, .. } => {
+                let template = match total_bytes {
+                    Some(_) => "  DL {spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}, ETA: {eta})",
+                    None => "  DL {spinner:.green} [{elapsed_precise}] {bytes} downloaded, size unknown ({bytes_per_sec})",
+                };
+                let pb = Self::make_pb(total_bytes, template);
                 *self.pb.lock().unwrap() = Some(pb);
             }
-            UiEvent::DownloadProgress { bytes_read } => {
+            UiEvent::DownloadProgress { bytes_read, .. } => {
                 if let Some(pb) = self.pb.lock().unwrap().as_ref() {
                     pb.set_position(bytes_read);
                 }
             }
-            UiEvent::DownloadComplete { size_bytes } => {
+            UiEvent::DownloadComplete { size_bytes, .. } => {
                 if let Some(pb) = self.pb.lock().unwrap().take() {
                     pb.finish_and_clear();
                 }
@@ -170,11 +295,11 @@ impl EventSink for ConsoleSink {
                 );
             }
 
-            UiEvent::FileProgress { bytes_read, total_bytes } => {
+            UiEvent::FileProgress { bytes_read, total_bytes, .. } => {
                 let mut guard = self.pb.lock().unwrap();
                 if guard.is_none() {
                     *guard = Some(Self::make_pb(
-                        total_bytes,
+                        Some(total_bytes),
                         "    {spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}, ETA: {eta})",
                     ));
                 }
@@ -183,6 +308,7 @@ impl EventSink for ConsoleSink {
                 }
             }
 
+            UiEvent::Preparing => println!("  Preparing file..."),
             UiEvent::Pass1Started => println!("  Pass 1: Counting valid games per player..."),
             UiEvent::Pass1Progress { games_scanned, unique_players, .. } => {
                 if games_scanned % 1_000_000 == 0 {
@@ -193,6 +319,8 @@ impl EventSink for ConsoleSink {
                     );
                 }
             }
+            UiEvent::TopPlayers(_) => {}
+            UiEvent::DatasetSizes(_) => {}
             UiEvent::Pass1Complete {
                 total_scanned,
                 valid_games,
@@ -210,13 +338,18 @@ impl EventSink for ConsoleSink {
                 );
             }
 
+            UiEvent::CountingComplete { datasets, qualifying_players } => println!(
+                "\nCounting done: {} players qualify across {} datasets; extracting...",
+                qualifying_players, datasets
+            ),
             UiEvent::Pass2Started => println!("  Pass 2: Extracting games..."),
             UiEvent::Pass2Progress { games_extracted } => {
                 if games_extracted > 0 && games_extracted % 500_000 == 0 {
                     eprint!("\r    Extracted {} entries...   ", games_extracted);
                 }
             }
-            UiEvent::Pass2Complete { total_extracted } => {
+            UiEvent::WriteProgress { .. } => {}
+            UiEvent::Pass2Complete { total_extracted, .. } => {
                 if let Some(pb) = self.pb.lock().unwrap().take() {
                     pb.finish_and_clear();
                 }
@@ -236,6 +369,9 @@ impl EventSink for ConsoleSink {
             }
 
             UiEvent::Finished => println!("\n=== Complete ==="),
+            // The console has no pause
+            UiEvent::Paused | UiEvent::Resumed => {}
+            UiEvent::Cancelled => println!("\n=== Cancelled ==="),
             UiEvent::Error(msg) => eprintln!("\n  ERROR: {}", msg),
         }
     }
@@ -247,23 +383,26 @@ impl EventSink for ConsoleSink {
 
 // ── Channel sink (TUI mode) ────────────────────────────────────────────────
 
+#[cfg(feature = "tui")]
 pub struct ChannelSink {
     tx: mpsc::Sender<UiEvent>,
     control: Arc<PipelineControl>,
 }
 
+#[cfg(feature = "tui")]
 impl ChannelSink {
     pub fn new(tx: mpsc::Sender<UiEvent>, control: Arc<PipelineControl>) -> Arc<Self> {
         Arc::new(Self { tx, control })
     }
 }
 
+#[cfg(feature = "tui")]
 impl EventSink for ChannelSink {
     fn send(&self, event: UiEvent) {
         let _ = self.tx.send(event);
     }
 
     fn check(&self) -> Result<()> {
-        self.control.check()
+        self.control.check(|paused| self.send(if paused { UiEvent::Paused } else { UiEvent::Resumed }))
     }
 }
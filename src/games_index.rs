@@ -0,0 +1,156 @@
+use crate::config::IndexFormat;
+use anyhow::{Context, Result};
+use std::borrow::Cow;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Columns of `games.index`, in CSV order.
+const COLUMNS: &str = "player,month,site,result,white_elo,black_elo";
+
+/// Rows for `games.index`, one per game entry written during a dataset's
+/// pass 2. They are staged in `<path>.part` and only appended to the index
+/// by `finish`, once the player files hold the games, so a restarted pass
+/// (which creates a fresh stage) never leaves rows behind.
+pub struct GamesIndex {
+    path: PathBuf,
+    stage: PathBuf,
+    out: BufWriter<File>,
+    format: IndexFormat,
+    month: String,
+    rows: u64,
+}
+
+impl GamesIndex {
+    /// Start staging rows for `month` of the index at `path`, discarding
+    /// any stage an earlier attempt left.
+    pub fn create(path: &Path, format: IndexFormat, month: &str) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let stage = PathBuf::from(format!("{}.part", path.display()));
+        let file = File::create(&stage).with_context(|| format!("Cannot create {}", stage.display()))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            stage,
+            out: BufWriter::new(file),
+            format,
+            month: month.to_string(),
+            rows: 0,
+        })
+    }
+
+    /// Stage a row for `pgn`, written to `player`'s file. The other columns
+    /// come from its headers; missing ones are left empty (null in NDJSON).
+    pub fn add(&mut self, player: &str, pgn: &str) -> Result<()> {
+        let site = header(pgn, "Site").unwrap_or("");
+        let result = header(pgn, "Result").unwrap_or("");
+        let elo = |key| header(pgn, key).and_then(|v| v.parse::<u32>().ok());
+        let (white_elo, black_elo) = (elo("WhiteElo"), elo("BlackElo"));
+        match self.format {
+            IndexFormat::Csv => {
+                let elo = |elo: Option<u32>| elo.map_or(String::new(), |e| e.to_string());
+                writeln!(
+                    self.out,
+                    "{},{},{},{},{},{}",
+                    csv_field(player),
+                    csv_field(&self.month),
+                    csv_field(site),
+                    csv_field(result),
+                    elo(white_elo),
+                    elo(black_elo)
+                )?
+            }
+            IndexFormat::Ndjson => {
+                let row = serde_json::json!({
+                    "player": player,
+                    "month": self.month,
+                    "site": site,
+                    "result": result,
+                    "white_elo": white_elo,
+                    "black_elo": black_elo,
+                });
+                writeln!(self.out, "{}", row)?
+            }
+        }
+        self.rows += 1;
+        Ok(())
+    }
+
+    /// Append the staged rows to the index (a new CSV index starts with a
+    /// header row) and remove the stage. Returns the rows appended.
+    pub fn finish(self) -> Result<u64> {
+        self.out.into_inner().map_err(|e| e.into_error())?;
+        let new = fs::metadata(&self.path).map_or(true, |meta| meta.len() == 0);
+        let mut index = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Cannot open {}", self.path.display()))?;
+        if new && self.format == IndexFormat::Csv {
+            writeln!(index, "{}", COLUMNS)?;
+        }
+        io::copy(&mut File::open(&self.stage)?, &mut index)?;
+        index.sync_all()?;
+        fs::remove_file(&self.stage)?;
+        Ok(self.rows)
+    }
+}
+
+/// The value of the `[key "..."]` header of `pgn`, if it has one.
+pub fn header<'a>(pgn: &'a str, key: &str) -> Option<&'a str> {
+    pgn.lines()
+        .take_while(|line| line.starts_with('['))
+        .find_map(|line| line.strip_prefix('[')?.strip_prefix(key)?.strip_prefix(" \"")?.strip_suffix("\"]"))
+}
+
+/// `value` as one CSV field: quoted, with quotes doubled, when it holds a
+/// comma, quote or line break.
+pub fn csv_field(value: &str) -> Cow<'_, str> {
+    if value.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rows_are_staged_until_finish() {
+        let dir = std::env::temp_dir().join(format!("rookt_test_games_index_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("games.index");
+        let pgn = "[Site \"https://lichess.org/abc\"]\n[Result \"1-0\"]\n[WhiteElo \"1500\"]\n[BlackElo \"?\"]\n\n1. e4 1-0\n\n";
+
+        let mut index = GamesIndex::create(&path, IndexFormat::Csv, "2025-01").unwrap();
+        index.add("Alice", pgn).unwrap();
+        index.add("Team, \"B\"", "[Result \"*\"]\n\n*\n\n").unwrap();
+        assert!(!path.exists(), "nothing is appended before finish");
+        assert_eq!(index.finish().unwrap(), 2);
+        let mut index = GamesIndex::create(&path, IndexFormat::Csv, "2025-02").unwrap();
+        index.add("Alice", pgn).unwrap();
+        index.finish().unwrap();
+        let csv = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines, [
+            COLUMNS,
+            "Alice,2025-01,https://lichess.org/abc,1-0,1500,",
+            "\"Team, \"\"B\"\"\",2025-01,,*,,",
+            "Alice,2025-02,https://lichess.org/abc,1-0,1500,",
+        ]);
+        assert!(!dir.join("games.index.part").exists());
+
+        let json = dir.join("games.ndjson");
+        let mut index = GamesIndex::create(&json, IndexFormat::Ndjson, "2025-01").unwrap();
+        index.add("Alice", pgn).unwrap();
+        index.finish().unwrap();
+        let row: serde_json::Value = serde_json::from_str(fs::read_to_string(&json).unwrap().trim()).unwrap();
+        assert_eq!(row["site"], "https://lichess.org/abc");
+        assert_eq!((row["white_elo"].as_u64(), row["black_elo"].is_null()), (Some(1500), true));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
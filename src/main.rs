@@ -1,21 +1,148 @@
+// Without the TUI, some event fields and helpers only it consumes go unused.
+#![cfg_attr(not(feature = "tui"), allow(dead_code))]
+
+mod archive;
+mod commands;
 mod config;
 mod database;
+mod dedup;
 mod download;
 mod events;
+mod games_index;
+mod mmap;
 mod parser;
 mod pipeline;
+mod profile;
+mod sample;
+mod stats;
+mod system;
+#[cfg(feature = "tui")]
 mod tui;
 mod writer;
 
 fn main() -> anyhow::Result<()> {
     let args: Vec<String> = std::env::args().collect();
 
-    if args.iter().any(|a| a == "--headless") {
-        // Headless mode: use default config and console output
-        let config = config::Config::default_blitz_300();
-        pipeline::run(&config)
-    } else {
-        // TUI mode: interactive config + dashboard
-        tui::run()
+    match args.get(1).map(String::as_str) {
+        // Print index totals and exit
+        Some("stats") => return commands::stats(&args[2..]),
+        // Look up players by name prefix and exit
+        Some("search") => return commands::search(&args[2..]),
+        // Merge another output directory into one and exit
+        Some("merge") => return commands::merge(&args[2..]),
+        // Compact the index and exit
+        Some("vacuum") => return commands::vacuum(&args[2..]),
+        // Split one local input into player files and exit
+        Some("split") => return commands::split(&args[2..]),
+        // Delete downloaded inputs (and optionally player files) and exit
+        Some("clean") => return commands::clean(&args[2..]),
+        // Time pass 1 over one dataset and exit
+        Some("bench") => return commands::bench(&args[2..]),
+        // Print a player's opponents as CSV and exit
+        Some("head-to-head") => return commands::head_to_head(&args[2..]),
+        _ => {}
+    }
+    // List the datasets the index has processed and exit
+    if args.iter().any(|a| a == "--list") {
+        return commands::list(&args[1..]);
+    }
+
+    // TUI mode: interactive config + dashboard (headless-only without the feature)
+    #[cfg(feature = "tui")]
+    if !args.iter().any(|a| a == "--headless") {
+        return tui::run();
+    }
+
+    // Headless mode: default config, overridden by a filter profile, then
+    // by ROOKT_* variables and then by the other flags, with console output
+    let mut config = config::Config::default_blitz_300();
+    if let Some(i) = args.iter().position(|a| a == "--profile") {
+        let Some(name) = args.get(i + 1) else {
+            anyhow::bail!("--profile needs a profile name or file");
+        };
+        let path = profile::apply(&mut config, name)?;
+        println!("Loaded filter profile {}", path.display());
+    }
+    config.apply_env()?;
+    config.fail_fast = args.iter().any(|a| a == "--fail-fast");
+    config.offline = args.iter().any(|a| a == "--offline");
+    config.strict = args.iter().any(|a| a == "--strict");
+    config.two_phase = args.iter().any(|a| a == "--two-phase");
+    config.record_results = args.iter().any(|a| a == "--record-results");
+    config.track_pairings = args.iter().any(|a| a == "--track-pairings");
+    config.shard_output = !args.iter().any(|a| a == "--flat-output");
+    if args.iter().any(|a| a == "--tar") {
+        config.archive = Some(config::ArchiveFormat::Tar);
+    }
+    if args.iter().any(|a| a == "--tar-zst") {
+        config.archive = Some(config::ArchiveFormat::TarZst);
+    }
+    if let Some(i) = args.iter().position(|a| a == "--games-index") {
+        config.games_index = match args.get(i + 1).map(String::as_str) {
+            Some("csv") => Some(config::IndexFormat::Csv),
+            Some("ndjson") => Some(config::IndexFormat::Ndjson),
+            _ => anyhow::bail!("--games-index needs a format: csv or ndjson"),
+        };
+    }
+    if let Some(i) = args.iter().position(|a| a == "--limit") {
+        let limit = args.get(i + 1).and_then(|v| v.parse().ok()).filter(|&n: &usize| n > 0);
+        let Some(limit) = limit else {
+            anyhow::bail!("--limit needs a positive number of datasets");
+        };
+        config.max_datasets = Some(limit);
+    }
+    if let Some(i) = args.iter().position(|a| a == "--write-threads") {
+        let threads = args.get(i + 1).and_then(|v| v.parse().ok()).filter(|&n: &usize| n > 0);
+        let Some(threads) = threads else {
+            anyhow::bail!("--write-threads needs a positive number of threads");
+        };
+        config.write_threads = threads;
+    }
+    if let Some(i) = args.iter().position(|a| a == "--max-scan-games") {
+        let max = args.get(i + 1).and_then(|v| v.parse().ok()).filter(|&n: &u64| n > 0);
+        let Some(max) = max else {
+            anyhow::bail!("--max-scan-games needs a positive number of games");
+        };
+        config.max_scan_games = Some(max);
+    }
+    if let Some(i) = args.iter().position(|a| a == "--any-of") {
+        let Some(list) = args.get(i + 1) else {
+            anyhow::bail!("--any-of needs a list of criteria (e.g. event,time-control)");
+        };
+        config.any_of = config::Criterion::parse_list(list)?;
+    }
+    if let Some(i) = args.iter().position(|a| a == "--qualifying-log") {
+        let Some(path) = args.get(i + 1) else {
+            anyhow::bail!("--qualifying-log needs a file path");
+        };
+        config.qualifying_log = Some(path.into());
+    }
+    if let Some(i) = args.iter().position(|a| a == "--sample-datasets") {
+        let count = args.get(i + 1).and_then(|v| v.parse().ok()).filter(|&n: &usize| n > 0);
+        let Some(count) = count else {
+            anyhow::bail!("--sample-datasets needs a positive number of datasets");
+        };
+        config.sample_datasets = Some(count);
+    }
+    if let Some(i) = args.iter().position(|a| a == "--sample-per-player") {
+        let count = args.get(i + 1).and_then(|v| v.parse().ok()).filter(|&n: &u32| n > 0);
+        let Some(count) = count else {
+            anyhow::bail!("--sample-per-player needs a positive number of games");
+        };
+        config.sample_per_player = Some(count);
+    }
+    if let Some(i) = args.iter().position(|a| a == "--sample-seed") {
+        let Some(seed) = args.get(i + 1).and_then(|v| v.parse().ok()) else {
+            anyhow::bail!("--sample-seed needs a non-negative integer");
+        };
+        config.sample_seed = seed;
+    }
+    if let Some(i) = args.iter().position(|a| a == "--reprocess") {
+        let Some(month) = args.get(i + 1) else {
+            anyhow::bail!("--reprocess needs a month (YYYY-MM)");
+        };
+        let adjusted = pipeline::reset_month(&config, month)?;
+        println!("Reset {}: {} player totals adjusted", month, adjusted);
     }
+    pipeline::run(&config)
 }
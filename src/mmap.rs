@@ -0,0 +1,66 @@
+use std::fs::File;
+use std::io;
+
+/// A whole file mapped read-only into memory, unmapped on drop. Reading
+/// it (through a `Cursor`) copies straight from the page cache, with no
+/// read syscall per buffer.
+///
+/// The bytes stay valid only while nobody shrinks the file: reading past
+/// a new, shorter end faults (SIGBUS) instead of returning an error.
+/// rookt never truncates an input it reads (downloads go to a `.part`
+/// file that is renamed into place), so that takes another process
+/// rewriting the file mid-pass.
+pub struct Mmap {
+    ptr: *const u8,
+    len: usize,
+}
+
+// The mapping is private, read-only and owned by this value
+unsafe impl Send for Mmap {}
+
+impl Mmap {
+    /// Map all of `file`. Fails for empty files (which cannot be mapped) and
+    /// wherever the OS refuses; callers fall back to buffered reads.
+    #[cfg(unix)]
+    pub fn map(file: &File) -> io::Result<Self> {
+        use std::os::fd::AsRawFd;
+
+        let len = usize::try_from(file.metadata()?.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "file too large to map"))?;
+        if len == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "cannot map an empty file"));
+        }
+        // SAFETY: a fresh private read-only mapping of a valid descriptor;
+        // the result is checked for MAP_FAILED before use
+        let ptr = unsafe {
+            libc::mmap(std::ptr::null_mut(), len, libc::PROT_READ, libc::MAP_PRIVATE, file.as_raw_fd(), 0)
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        // Only a read-ahead hint, so a failure changes nothing
+        // SAFETY: the range is the mapping just created
+        unsafe { libc::madvise(ptr, len, libc::MADV_SEQUENTIAL) };
+        Ok(Self { ptr: ptr as *const u8, len })
+    }
+
+    #[cfg(not(unix))]
+    pub fn map(_file: &File) -> io::Result<Self> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "memory maps are only used on Unix"))
+    }
+}
+
+impl AsRef<[u8]> for Mmap {
+    fn as_ref(&self) -> &[u8] {
+        // SAFETY: `ptr` points to `len` mapped, readable bytes until drop
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        // SAFETY: unmaps exactly the mapping created in `map`, once
+        unsafe { libc::munmap(self.ptr as *mut libc::c_void, self.len) };
+    }
+}
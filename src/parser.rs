@@ -2,12 +2,53 @@ use anyhow::Result;
 use std::io::BufRead;
 
 /// Minimal game info extracted during pass 1 (counting).
+#[derive(Default)]
 pub struct GameInfo {
     pub event: String,
+    /// The `Site` header, on Lichess the game's URL; empty if missing.
+    pub site: String,
     pub white: String,
     pub black: String,
+    /// Lichess title codes (e.g. "GM", "BOT"); empty when untitled.
+    pub white_title: String,
+    pub black_title: String,
+    /// The `WhiteTeam` / `BlackTeam` headers (team events); empty if missing.
+    pub white_team: String,
+    pub black_team: String,
     pub time_control: String,
+    /// Game date as `YYYY.MM.DD` (from `UTCDate`, falling back to `Date`).
+    /// Unknown parts are `?`, as in `????.??.??`.
+    pub date: String,
     pub half_move_count: u32,
+    /// Each side's last `[%clk]` value in seconds (its clock after its final
+    /// move); None without clock annotations.
+    pub white_clock: Option<u32>,
+    pub black_clock: Option<u32>,
+    /// The `Result` header: `1-0`, `0-1`, `1/2-1/2` or `*`; empty if missing.
+    pub result: String,
+    /// The `SetUp` and `FEN` headers, present when the game starts from a
+    /// custom position (Chess960, odds games); empty if missing.
+    pub set_up: String,
+    pub fen: String,
+    /// First mainline plies in SAN, captured only up to the parser's
+    /// `opening_plies` limit (empty by default).
+    pub opening: Vec<String>,
+}
+
+/// The standard initial position, without the move counters.
+const INITIAL_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -";
+
+impl GameInfo {
+    /// Whether the game starts from the standard initial position: no `FEN`
+    /// header (and no `SetUp "1"` claiming one), or a `FEN` giving the
+    /// initial position with any move counters.
+    pub fn standard_start(&self) -> bool {
+        if self.fen.is_empty() {
+            return self.set_up != "1";
+        }
+        let fields: Vec<&str> = self.fen.split_whitespace().take(4).collect();
+        fields.join(" ") == INITIAL_FEN
+    }
 }
 
 /// Full game data including raw PGN text, for pass 2 (extraction).
@@ -19,7 +60,12 @@ pub struct Game {
 #[derive(PartialEq)]
 enum State {
     BetweenGames,
+    /// A second `Event` tag here starts the next game: the current one had
+    /// no move section at all.
     InHeaders,
+    /// After the headers. The first blank line once movetext has been seen
+    /// ends the game; blank lines before that (some exports put two or more
+    /// between headers and moves) are skipped.
     InMoves,
 }
 
@@ -28,6 +74,8 @@ pub struct PgnParser<R> {
     reader: R,
     line_buf: String,
     pending_line: Option<String>,
+    opening_plies: usize,
+    verbatim: bool,
 }
 
 impl<R: BufRead> PgnParser<R> {
@@ -36,9 +84,25 @@ impl<R: BufRead> PgnParser<R> {
             reader,
             line_buf: String::with_capacity(4096),
             pending_line: None,
+            opening_plies: 0,
+            verbatim: false,
         }
     }
 
+    /// Capture the first `plies` mainline moves of each game into `GameInfo::opening`.
+    pub fn with_opening_plies(mut self, plies: usize) -> Self {
+        self.opening_plies = plies;
+        self
+    }
+
+    /// Make `next_game` keep each game's exact source bytes in `raw_pgn`
+    /// (line endings, the terminating blank line)
+    /// instead of normalizing them. Only blank lines between games are dropped.
+    pub fn with_verbatim(mut self, verbatim: bool) -> Self {
+        self.verbatim = verbatim;
+        self
+    }
+
     /// Fill `self.line_buf` with the next line. Returns false at EOF.
     fn read_line(&mut self) -> Result<bool> {
         if let Some(pending) = self.pending_line.take() {
@@ -52,23 +116,16 @@ impl<R: BufRead> PgnParser<R> {
 
     /// Pass 1: Extract headers and half-move count only (no raw PGN stored).
     pub fn next_info(&mut self) -> Result<Option<GameInfo>> {
-        let mut event = String::new();
-        let mut white = String::new();
-        let mut black = String::new();
-        let mut time_control = String::new();
+        let mut info = GameInfo::default();
+        let mut moves = MoveTokenizer::default();
         let mut state = State::BetweenGames;
-        let mut half_moves: u32 = 0;
+        let mut seen_moves = false;
+        let mut seen_event = false;
 
         loop {
             if !self.read_line()? {
                 return if state != State::BetweenGames {
-                    Ok(Some(GameInfo {
-                        event,
-                        white,
-                        black,
-                        time_control,
-                        half_move_count: half_moves,
-                    }))
+                    Ok(Some(info))
                 } else {
                     Ok(None)
                 };
@@ -78,15 +135,10 @@ impl<R: BufRead> PgnParser<R> {
 
             if trimmed.is_empty() {
                 match state {
-                    State::InMoves => {
-                        return Ok(Some(GameInfo {
-                            event,
-                            white,
-                            black,
-                            time_control,
-                            half_move_count: half_moves,
-                        }));
+                    State::InMoves if seen_moves => {
+                        return Ok(Some(info));
                     }
+                    State::InMoves => {}
                     State::InHeaders => {
                         state = State::InMoves;
                     }
@@ -96,36 +148,41 @@ impl<R: BufRead> PgnParser<R> {
             }
 
             let is_header = trimmed.starts_with('[') && trimmed.ends_with(']') && trimmed.contains('"');
+            let is_event = is_header && trimmed.starts_with("[Event ");
 
             match state {
                 State::BetweenGames => {
                     if is_header {
                         state = State::InHeaders;
-                        extract_header_into(trimmed, &mut event, &mut white, &mut black, &mut time_control);
+                        seen_event = is_event;
+                        extract_header_into(trimmed, &mut info);
                     }
                 }
                 State::InHeaders => {
-                    if is_header {
-                        extract_header_into(trimmed, &mut event, &mut white, &mut black, &mut time_control);
+                    if is_event && seen_event {
+                        // A game without moves, followed directly by the next
+                        self.pending_line = Some(self.line_buf.clone());
+                        return Ok(Some(info));
+                    } else if is_header {
+                        seen_event |= is_event;
+                        extract_header_into(trimmed, &mut info);
                     } else {
                         // No empty line between headers and moves — handle gracefully
                         state = State::InMoves;
-                        half_moves += count_clk(trimmed);
+                        seen_moves = true;
+                        scan_clocks(trimmed, &mut info);
+                        moves.feed(trimmed, &mut info.opening, self.opening_plies);
                     }
                 }
                 State::InMoves => {
                     if is_header {
                         // Next game started without blank line separator
                         self.pending_line = Some(self.line_buf.clone());
-                        return Ok(Some(GameInfo {
-                            event,
-                            white,
-                            black,
-                            time_control,
-                            half_move_count: half_moves,
-                        }));
+                        return Ok(Some(info));
                     }
-                    half_moves += count_clk(trimmed);
+                    seen_moves = true;
+                    scan_clocks(trimmed, &mut info);
+                    moves.feed(trimmed, &mut info.opening, self.opening_plies);
                 }
             }
         }
@@ -133,54 +190,43 @@ impl<R: BufRead> PgnParser<R> {
 
     /// Pass 2: Extract full game including raw PGN text.
     pub fn next_game(&mut self) -> Result<Option<Game>> {
-        let mut event = String::new();
-        let mut white = String::new();
-        let mut black = String::new();
-        let mut time_control = String::new();
+        let mut info = GameInfo::default();
+        let mut moves = MoveTokenizer::default();
         let mut state = State::BetweenGames;
-        let mut half_moves: u32 = 0;
+        let mut seen_moves = false;
+        let mut seen_event = false;
         let mut raw = String::with_capacity(2048);
+        let verbatim = self.verbatim;
 
         loop {
             if !self.read_line()? {
                 return if state != State::BetweenGames {
-                    Ok(Some(Game {
-                        info: GameInfo {
-                            event,
-                            white,
-                            black,
-                            time_control,
-                            half_move_count: half_moves,
-                        },
-                        raw_pgn: raw,
-                    }))
+                    Ok(Some(Game { info, raw_pgn: raw }))
                 } else {
                     Ok(None)
                 };
             }
 
-            // Normalize line ending
-            let line = self.line_buf.trim_end_matches(|c| c == '\r' || c == '\n');
+            // Normalize line ending (verbatim mode keeps `full` as read)
+            let full = self.line_buf.as_str();
+            let line = full.trim_end_matches(['\r', '\n']);
             let trimmed = line.trim();
 
             if trimmed.is_empty() {
                 match state {
+                    State::InMoves if seen_moves => {
+                        append_line(&mut raw, "", full, verbatim);
+                        return Ok(Some(Game { info, raw_pgn: raw }));
+                    }
+                    // Extra blank lines before the moves: kept only verbatim
                     State::InMoves => {
-                        raw.push('\n');
-                        return Ok(Some(Game {
-                            info: GameInfo {
-                                event,
-                                white,
-                                black,
-                                time_control,
-                                half_move_count: half_moves,
-                            },
-                            raw_pgn: raw,
-                        }));
+                        if verbatim {
+                            raw.push_str(full);
+                        }
                     }
                     State::InHeaders => {
                         state = State::InMoves;
-                        raw.push('\n');
+                        append_line(&mut raw, "", full, verbatim);
                     }
                     State::BetweenGames => {}
                 }
@@ -188,60 +234,104 @@ impl<R: BufRead> PgnParser<R> {
             }
 
             let is_header = trimmed.starts_with('[') && trimmed.ends_with(']') && trimmed.contains('"');
+            let is_event = is_header && trimmed.starts_with("[Event ");
 
             match state {
                 State::BetweenGames => {
                     if is_header {
                         state = State::InHeaders;
-                        extract_header_into(trimmed, &mut event, &mut white, &mut black, &mut time_control);
-                        raw.push_str(line);
-                        raw.push('\n');
+                        seen_event = is_event;
+                        extract_header_into(trimmed, &mut info);
+                        append_line(&mut raw, line, full, verbatim);
                     }
                 }
                 State::InHeaders => {
-                    if is_header {
-                        extract_header_into(trimmed, &mut event, &mut white, &mut black, &mut time_control);
-                        raw.push_str(line);
-                        raw.push('\n');
+                    if is_event && seen_event {
+                        self.pending_line = Some(self.line_buf.clone());
+                        if !verbatim {
+                            raw.push('\n'); // end the headers as a game with moves would
+                        }
+                        return Ok(Some(Game { info, raw_pgn: raw }));
+                    } else if is_header {
+                        seen_event |= is_event;
+                        extract_header_into(trimmed, &mut info);
+                        append_line(&mut raw, line, full, verbatim);
                     } else {
                         state = State::InMoves;
-                        raw.push('\n'); // empty line between headers and moves
-                        half_moves += count_clk(trimmed);
-                        raw.push_str(line);
-                        raw.push('\n');
+                        seen_moves = true;
+                        if !verbatim {
+                            raw.push('\n'); // empty line between headers and moves
+                        }
+                        scan_clocks(trimmed, &mut info);
+                        moves.feed(trimmed, &mut info.opening, self.opening_plies);
+                        append_line(&mut raw, line, full, verbatim);
                     }
                 }
                 State::InMoves => {
                     if is_header {
                         self.pending_line = Some(self.line_buf.clone());
-                        return Ok(Some(Game {
-                            info: GameInfo {
-                                event,
-                                white,
-                                black,
-                                time_control,
-                                half_move_count: half_moves,
-                            },
-                            raw_pgn: raw,
-                        }));
+                        return Ok(Some(Game { info, raw_pgn: raw }));
                     }
-                    half_moves += count_clk(trimmed);
-                    raw.push_str(line);
-                    raw.push('\n');
+                    seen_moves = true;
+                    scan_clocks(trimmed, &mut info);
+                    moves.feed(trimmed, &mut info.opening, self.opening_plies);
+                    append_line(&mut raw, line, full, verbatim);
                 }
             }
         }
     }
+
+    /// Iterate over games as `GameInfo` (pass 1), via `next_info`. Each
+    /// item is a game or the read error that ended the stream.
+    pub fn infos(&mut self) -> Infos<'_, R> {
+        Infos { parser: self }
+    }
+
+    /// Iterate over full games with raw PGN (pass 2), via `next_game`.
+    pub fn games(&mut self) -> Games<'_, R> {
+        Games { parser: self }
+    }
+}
+
+/// Iterator returned by `PgnParser::infos`.
+pub struct Infos<'a, R> {
+    parser: &'a mut PgnParser<R>,
+}
+
+impl<R: BufRead> Iterator for Infos<'_, R> {
+    type Item = Result<GameInfo>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.parser.next_info().transpose()
+    }
+}
+
+/// Iterator returned by `PgnParser::games`.
+pub struct Games<'a, R> {
+    parser: &'a mut PgnParser<R>,
+}
+
+impl<R: BufRead> Iterator for Games<'_, R> {
+    type Item = Result<Game>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.parser.next_game().transpose()
+    }
+}
+
+/// Append one source line to `raw`: exactly as read in verbatim mode,
+/// otherwise the line without its ending plus `\n`.
+fn append_line(raw: &mut String, line: &str, full: &str, verbatim: bool) {
+    if verbatim {
+        raw.push_str(full);
+    } else {
+        raw.push_str(line);
+        raw.push('\n');
+    }
 }
 
 /// Parse a PGN header line `[Key "Value"]` and update the relevant field.
-fn extract_header_into(
-    line: &str,
-    event: &mut String,
-    white: &mut String,
-    black: &mut String,
-    time_control: &mut String,
-) {
+fn extract_header_into(line: &str, info: &mut GameInfo) {
     let inner = &line[1..line.len() - 1];
     let Some(space) = inner.find(' ') else { return };
     let key = &inner[..space];
@@ -251,18 +341,122 @@ fn extract_header_into(
     }
     let value = &rest[1..rest.len() - 1];
 
-    match key {
-        "Event" => { event.clear(); event.push_str(value); }
-        "White" => { white.clear(); white.push_str(value); }
-        "Black" => { black.clear(); black.push_str(value); }
-        "TimeControl" => { time_control.clear(); time_control.push_str(value); }
-        _ => {}
+    let field = match key {
+        "Event" => &mut info.event,
+        "Site" => &mut info.site,
+        "White" => &mut info.white,
+        "Black" => &mut info.black,
+        "WhiteTitle" => &mut info.white_title,
+        "BlackTitle" => &mut info.black_title,
+        "WhiteTeam" => &mut info.white_team,
+        "BlackTeam" => &mut info.black_team,
+        "TimeControl" => &mut info.time_control,
+        "Result" => &mut info.result,
+        "SetUp" => &mut info.set_up,
+        "FEN" => &mut info.fen,
+        "UTCDate" => &mut info.date,
+        // UTCDate takes precedence; Date is only a fallback
+        "Date" if info.date.is_empty() => &mut info.date,
+        _ => return,
+    };
+    field.clear();
+    field.push_str(value);
+}
+
+/// Incremental SAN tokenizer over movetext. Skips move numbers, `{}` and `;`
+/// comments, `()` variations, NAGs and results, and strips `!`/`?` suffixes.
+#[derive(Default)]
+struct MoveTokenizer {
+    in_comment: bool,
+    variation_depth: u32,
+}
+
+impl MoveTokenizer {
+    /// Append mainline moves from `line` to `out` until it holds `limit` entries.
+    fn feed(&mut self, line: &str, out: &mut Vec<String>, limit: usize) {
+        let mut token_start = None;
+        for (i, c) in line.char_indices() {
+            if out.len() >= limit {
+                return;
+            }
+            if self.in_comment {
+                if c == '}' {
+                    self.in_comment = false;
+                }
+                continue;
+            }
+            if !(c.is_whitespace() || matches!(c, '{' | '(' | ')' | ';')) {
+                token_start.get_or_insert(i);
+                continue;
+            }
+            if let Some(start) = token_start.take() {
+                self.push_token(&line[start..i], out);
+            }
+            match c {
+                '{' => self.in_comment = true,
+                '(' => self.variation_depth += 1,
+                ')' => self.variation_depth = self.variation_depth.saturating_sub(1),
+                ';' => return,
+                _ => {}
+            }
+        }
+        if let Some(start) = token_start
+            && out.len() < limit
+        {
+            self.push_token(&line[start..], out);
+        }
+    }
+
+    fn push_token(&self, token: &str, out: &mut Vec<String>) {
+        if self.variation_depth > 0 || token.starts_with('$') || token == "*" {
+            return;
+        }
+        let mut san = token;
+        if san.starts_with(|c: char| c.is_ascii_digit()) {
+            let digits_end = san.find(|c: char| !c.is_ascii_digit()).unwrap_or(san.len());
+            if !san[digits_end..].starts_with('.') {
+                return; // result such as 1-0 or 1/2-1/2
+            }
+            san = san[digits_end..].trim_start_matches('.');
+        }
+        let san = san.trim_end_matches(['!', '?']);
+        if !san.is_empty() {
+            out.push(san.to_string());
+        }
+    }
+}
+
+/// Tokenize movetext such as `1. e4 c5 2. Nf3` into SAN moves.
+pub fn san_moves(movetext: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    MoveTokenizer::default().feed(movetext, &mut out, usize::MAX);
+    out
+}
+
+/// Count `[%clk` occurrences in a line (each = 1 half-move) and record the
+/// clocks, which alternate white, black from the first move.
+fn scan_clocks(line: &str, info: &mut GameInfo) {
+    for (i, tag) in line.match_indices("[%clk") {
+        let clock = parse_clock(&line[i + tag.len()..]);
+        if info.half_move_count.is_multiple_of(2) {
+            info.white_clock = clock;
+        } else {
+            info.black_clock = clock;
+        }
+        info.half_move_count += 1;
     }
 }
 
-/// Count `[%clk` occurrences in a line (each = 1 half-move).
-fn count_clk(line: &str) -> u32 {
-    line.matches("[%clk").count() as u32
+/// Parse the `H:MM:SS` (optionally with fractional seconds) that follows
+/// `[%clk` into whole seconds.
+fn parse_clock(rest: &str) -> Option<u32> {
+    let value = rest.split(']').next()?.trim();
+    let mut secs = 0u32;
+    for part in value.split(':') {
+        let whole = part.split('.').next()?;
+        secs = secs.checked_mul(60)?.checked_add(whole.parse().ok()?)?;
+    }
+    Some(secs)
 }
 
 #[cfg(test)]
@@ -326,4 +520,182 @@ mod tests {
 
         assert!(parser.next_game().unwrap().is_none());
     }
+
+    #[test]
+    fn test_iterator_adapters() {
+        let mut parser = PgnParser::new(Cursor::new(SAMPLE_PGN));
+        let whites: Vec<String> = parser.infos().map(|info| info.unwrap().white).collect();
+        assert_eq!(whites, ["PlayerA", "PlayerC"]);
+
+        let mut parser = PgnParser::new(Cursor::new(SAMPLE_PGN));
+        let games: Vec<Game> = parser.games().collect::<Result<_>>().unwrap();
+        assert_eq!(games.len(), 2);
+        assert!(games[1].raw_pgn.contains("1. d4"));
+        assert!(!games[0].raw_pgn.contains("PlayerC"), "one game per item");
+    }
+
+    #[test]
+    fn test_verbatim_raw_pgn_matches_source() {
+        let source = "[Event \"Rated Blitz game\"]  \r\n[White \"PlayerA\"]\r\n\r\n1. e4 e5\r\n2. Nf3 1-0\r\n\r\n\
+                      [Event \"Rated Blitz game\"]\n[White \"PlayerC\"]\n\n1. d4 d5 0-1\n";
+
+        let mut parser = PgnParser::new(Cursor::new(source)).with_verbatim(true);
+        let raw: Vec<String> = parser.games().map(|g| g.unwrap().raw_pgn).collect();
+        assert_eq!(raw.len(), 2);
+        assert_eq!(raw.concat(), source);
+
+        // Default mode normalizes line endings
+        let mut parser = PgnParser::new(Cursor::new(source));
+        let first = parser.next_game().unwrap().unwrap().raw_pgn;
+        assert!(!first.contains('\r'));
+    }
+
+    #[test]
+    fn test_repeated_blank_lines_between_games() {
+        // Two blank lines between headers and moves, three between games
+        let source = "[White \"PlayerA\"]\n[Black \"PlayerB\"]\n\n\n1. e4 { [%clk 0:05:00] } 1... e5 { [%clk 0:05:00] } 1-0\n\n\n\n\
+                      [White \"PlayerC\"]\n[Black \"PlayerD\"]\n\n1. d4 { [%clk 0:05:00] } 0-1\n\n\n";
+
+        let mut parser = PgnParser::new(Cursor::new(source));
+        let infos = parser.infos().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(infos.len(), 2);
+        assert_eq!((infos[0].white.as_str(), infos[0].half_move_count), ("PlayerA", 2));
+        assert_eq!((infos[1].white.as_str(), infos[1].half_move_count), ("PlayerC", 1));
+
+        let mut parser = PgnParser::new(Cursor::new(source));
+        let games = parser.games().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(games.len(), 2);
+        // The extra blank line is normalized away
+        assert!(games[0].raw_pgn.contains("[Black \"PlayerB\"]\n\n1. e4"));
+        assert!(games[0].raw_pgn.ends_with("1-0\n\n"));
+
+        let mut parser = PgnParser::new(Cursor::new(source)).with_verbatim(true);
+        let games = parser.games().collect::<Result<Vec<_>>>().unwrap();
+        assert!(games[0].raw_pgn.contains("[Black \"PlayerB\"]\n\n\n1. e4"));
+    }
+
+    #[test]
+    fn test_zero_move_games_between_normal_games() {
+        let normal = |white: &str| {
+            format!(
+                "[Event \"Rated Blitz game\"]\n[White \"{}\"]\n\n1. e4 {{ [%clk 0:05:00] }} 1... e5 {{ [%clk 0:05:00] }} 1-0\n\n",
+                white
+            )
+        };
+        let middles = [
+            // Only the result where the moves would be
+            "[Event \"Rated Blitz game\"]\n[White \"Empty\"]\n\n0-1\n\n",
+            // An empty move section: the blank line after the headers, then another
+            "[Event \"Rated Blitz game\"]\n[White \"Empty\"]\n\n\n",
+            // No move section at all, straight into the next game's headers
+            "[Event \"Rated Blitz game\"]\n[White \"Empty\"]\n",
+        ];
+        for middle in middles {
+            let source = format!("{}{}{}", normal("First"), middle, normal("Last"));
+
+            let mut parser = PgnParser::new(Cursor::new(source.as_str()));
+            let infos = parser.infos().collect::<Result<Vec<_>>>().unwrap();
+            let seen: Vec<(&str, u32)> = infos.iter().map(|i| (i.white.as_str(), i.half_move_count)).collect();
+            assert_eq!(seen, [("First", 2), ("Empty", 0), ("Last", 2)], "{:?}", middle);
+
+            for verbatim in [false, true] {
+                let mut parser = PgnParser::new(Cursor::new(source.as_str())).with_verbatim(verbatim);
+                let games = parser.games().collect::<Result<Vec<_>>>().unwrap();
+                let whites: Vec<&str> = games.iter().map(|g| g.info.white.as_str()).collect();
+                assert_eq!(whites, ["First", "Empty", "Last"], "{:?}", middle);
+                assert_eq!(games[0].raw_pgn, normal("First"));
+                assert!(games[1].raw_pgn.starts_with("[Event \"Rated Blitz game\"]\n[White \"Empty\"]\n"));
+                assert!(!games[1].raw_pgn.contains("Last"));
+                if !verbatim {
+                    assert!(games[1].raw_pgn.ends_with("]\n\n") || games[1].raw_pgn.ends_with("0-1\n\n"));
+                }
+                assert_eq!(games[2].raw_pgn, normal("Last"));
+            }
+        }
+    }
+
+    #[test]
+    fn test_crlf_blank_line_separators() {
+        let source = "[White \"PlayerA\"]\r\n\r\n\r\n1. e4 { [%clk 0:05:00] } 1-0\r\n\r\n\r\n\
+                      [White \"PlayerC\"]\r\n\r\n1. d4 { [%clk 0:05:00] } 0-1\r\n";
+
+        let mut parser = PgnParser::new(Cursor::new(source));
+        let infos = parser.infos().collect::<Result<Vec<_>>>().unwrap();
+        let whites: Vec<&str> = infos.iter().map(|i| i.white.as_str()).collect();
+        assert_eq!(whites, ["PlayerA", "PlayerC"]);
+        assert!(infos.iter().all(|i| i.half_move_count == 1));
+
+        let mut parser = PgnParser::new(Cursor::new(source));
+        let games = parser.games().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].raw_pgn, "[White \"PlayerA\"]\n\n1. e4 { [%clk 0:05:00] } 1-0\n\n");
+        assert!(games[1].raw_pgn.contains("1. d4"));
+    }
+
+    #[test]
+    fn test_next_info_parses_titles() {
+        let pgn = r#"[Event "Rated Blitz game"]
+[White "SomeBot"]
+[Black "PlayerB"]
+[WhiteTitle "BOT"]
+[BlackTitle "GM"]
+[TimeControl "300+0"]
+
+1. e4 { [%clk 0:05:00] } 1... e5 { [%clk 0:05:00] } 1-0
+"#;
+        let mut parser = PgnParser::new(Cursor::new(pgn));
+
+        let g = parser.next_info().unwrap().unwrap();
+        assert_eq!(g.white_title, "BOT");
+        assert_eq!(g.black_title, "GM");
+    }
+
+    #[test]
+    fn test_utc_date_takes_precedence() {
+        let pgn = r#"[Event "Rated Blitz game"]
+[Date "2025.08.01"]
+[UTCDate "2025.07.31"]
+
+1. e4 1-0
+
+[Event "Rated Blitz game"]
+[Date "2025.08.02"]
+
+1. d4 1-0
+"#;
+        let mut parser = PgnParser::new(Cursor::new(pgn));
+
+        assert_eq!(parser.next_info().unwrap().unwrap().date, "2025.07.31");
+        assert_eq!(parser.next_info().unwrap().unwrap().date, "2025.08.02");
+    }
+
+    #[test]
+    fn test_final_clocks_per_side() {
+        let mut parser = PgnParser::new(Cursor::new(SAMPLE_PGN));
+        let info = parser.next_info().unwrap().unwrap();
+        assert_eq!((info.white_clock, info.black_clock), (Some(299), Some(299)));
+
+        let pgn = "[White \"A\"]\n\n1. e4 { [%clk 0:03:00] } 1... e5 { [%clk 1:00:05.4] }\n2. Nf3 { [%clk 0:00:07] } 1-0\n";
+        let info = PgnParser::new(Cursor::new(pgn)).next_info().unwrap().unwrap();
+        assert_eq!((info.white_clock, info.black_clock, info.half_move_count), (Some(7), Some(3605), 3));
+
+        let info = PgnParser::new(Cursor::new("[White \"A\"]\n\n1. e4 e5 1-0\n")).next_info().unwrap().unwrap();
+        assert_eq!((info.white_clock, info.black_clock), (None, None));
+    }
+
+    #[test]
+    fn test_san_moves_skips_numbers_and_comments() {
+        let moves = san_moves("1. e4 { [%clk 0:05:00] } 1... c5?! { [%eval 0.3] } 2.Nf3 (2. c3 d5) $1 Nc6 1-0");
+        assert_eq!(moves, ["e4", "c5", "Nf3", "Nc6"]);
+    }
+
+    #[test]
+    fn test_opening_plies_capture() {
+        let mut parser = PgnParser::new(Cursor::new(SAMPLE_PGN)).with_opening_plies(3);
+        let g1 = parser.next_info().unwrap().unwrap();
+        assert_eq!(g1.opening, ["e4", "e5", "Nf3"]);
+
+        let mut parser = PgnParser::new(Cursor::new(SAMPLE_PGN));
+        assert!(parser.next_game().unwrap().unwrap().info.opening.is_empty());
+    }
 }
@@ -1,21 +1,35 @@
-use crate::config::Config;
-use crate::database::Database;
-use crate::download;
-use crate::events::{ConsoleSink, EventSink, UiEvent};
-use crate::parser::{GameInfo, PgnParser};
-use crate::writer::PlayerWriter;
+use crate::archive;
+use crate::config::{Config, Criterion, GroupBy};
+use crate::database::{Database, DatasetRecord};
+use crate::download::{self, DownloadOptions, Fetch};
+use crate::events::{Cancelled, ConsoleSink, EventSink, UiEvent};
+use crate::games_index::{self, GamesIndex};
+use crate::mmap::Mmap;
+use crate::parser::{self, GameInfo, PgnParser};
+use crate::sample::PlayerSampler;
+use crate::stats::{ProgressSnapshot, RunStats};
+use crate::writer::{FileCheckpoint, FlushJournal, PlayerWriter};
 use anyhow::{Context, Result};
-use std::collections::{HashMap, HashSet};
-use std::fs::{self, File};
-use std::io::{BufReader, Read};
-use std::path::Path;
-use std::sync::Arc;
+use regex::Regex;
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Cursor, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
 /// Run the pipeline in headless mode (console output).
 pub fn run(config: &Config) -> Result<()> {
     let sink = ConsoleSink::new();
     sink.send(UiEvent::Log(format!("Output: {}", config.output_dir.display())));
-    sink.send(UiEvent::Log(format!("Event: {}", config.event_filter)));
+    match &config.event_filter_regex {
+        Some(re) => sink.send(UiEvent::Log(format!("Event regex: {}", re))),
+        None => sink.send(UiEvent::Log(format!("Event: {}", config.event_filter))),
+    }
     if let Some(tc) = &config.time_control_filter {
         sink.send(UiEvent::Log(format!("TimeControl: {}", tc)));
     }
@@ -23,123 +37,937 @@ pub fn run(config: &Config) -> Result<()> {
         "Moves: {} full, Monthly: {}, Total: {}",
         config.min_full_moves, config.min_monthly_games, config.min_total_games
     )));
-    run_with_sink(config, sink)
+    if config.offline {
+        sink.send(UiEvent::Log("Offline: using cached inputs only".into()));
+    }
+    let stats = run_with_sink(config, sink)?;
+    print!("\n{}", stats);
+    Ok(())
 }
 
 /// Run the pipeline with a given EventSink (used by both headless and TUI).
-pub fn run_with_sink(config: &Config, sink: Arc<dyn EventSink>) -> Result<()> {
+/// Writes `summary.txt` into the output directory and returns the run totals.
+pub fn run_with_sink(config: &Config, sink: Arc<dyn EventSink>) -> Result<RunStats> {
+    let mut source = HttpSource::new(config);
+    if !config.offline
+        && let Some(proxy) = download::describe_proxy(&source.prefetcher.options)?
+    {
+        sink.send(UiEvent::Log(format!("Downloading via proxy {}", proxy)));
+    }
+    run_with_source(config, &mut source, sink)
+}
+
+/// Run the pipeline over datasets read from `source`.
+fn run_with_source(
+    config: &Config,
+    source: &mut dyn InputSource,
+    sink: Arc<dyn EventSink>,
+) -> Result<RunStats> {
     fs::create_dir_all(&config.temp_dir)?;
     fs::create_dir_all(config.players_dir())?;
 
+    // Opening a large index (WAL recovery, migrations) can take a while
+    let started = Instant::now();
+    sink.send(UiEvent::Log(format!("Opening index {}...", config.db_path.display())));
     let mut db = Database::open(&config.db_path)?;
-    let total = config.dataset_urls.len();
+    sink.send(UiEvent::Log(format!("Index ready ({:.2}s)", started.elapsed().as_secs_f64())));
+    let result = run_datasets(config, source, &mut db, sink.clone());
+    // Checkpoint even on cancel or failure, so a later `stats` sees a
+    // complete index and no -wal file is left behind
+    if let Err(e) = db.close() {
+        sink.send(UiEvent::Log(format!("Index not closed cleanly: {:#}", e)));
+    }
+    if result.is_ok() {
+        sink.send(UiEvent::Finished);
+    }
+    result
+}
+
+/// Count, extract and prune every dataset against the open index.
+fn run_datasets(
+    config: &Config,
+    source: &mut dyn InputSource,
+    db: &mut Database,
+    sink: Arc<dyn EventSink>,
+) -> Result<RunStats> {
+    let mut tracker = Tracker::new(config.output_dir.join(ProgressSnapshot::FILE_NAME));
+    for url in config.duplicate_urls() {
+        sink.send(UiEvent::Log(format!("Warning: {} is listed more than once; processing it once", url)));
+    }
+    let datasets = config.datasets();
+    let total = datasets.len();
+    if datasets.is_empty() {
+        sink.send(UiEvent::Log("Warning: the configured range has no datasets; nothing to process".into()));
+    }
+    if config.sample_datasets.is_some() {
+        let months: Vec<String> = datasets.iter().map(|url| extract_month(url)).collect();
+        sink.send(UiEvent::Log(format!("Sampled datasets: {}", months.join(", "))));
+    }
+
+    let sizes = datasets
+        .iter()
+        .map(|url| db.dataset_size(url))
+        .collect::<Result<Vec<_>>>()?;
+    if sizes.iter().any(Option::is_some) {
+        sink.send(UiEvent::DatasetSizes(sizes));
+    }
 
-    for (i, url) in config.dataset_urls.iter().enumerate() {
-        sink.check()?;
+    // In two-phase mode the loop below only counts; extraction waits until
+    // every dataset has been counted
+    let mut counted = Vec::new();
+    for (i, url) in datasets.iter().enumerate() {
+        if let Err(e) = sink.check() {
+            tracker.cancel(&*sink);
+            return Err(e);
+        }
         let name = url.rsplit('/').next().unwrap_or(url).to_string();
-        sink.send(UiEvent::DatasetStarted { index: i, total, name: name.clone() });
+        tracker.emit(&*sink, UiEvent::DatasetStarted { index: i, total, name: name.clone() });
 
         if db.is_dataset_processed(url)? {
-            sink.send(UiEvent::DatasetSkipped { name });
+            tracker.emit(&*sink, UiEvent::DatasetSkipped { name });
             continue;
         }
 
-        let month = extract_month(url);
-        let zst_path = config.temp_dir.join(format!("{}.pgn.zst", month));
-
-        // Download
-        download::download(url, &zst_path, &*sink)?;
-        sink.check()?;
-
-        // Pass 1
-        sink.send(UiEvent::Pass1Started);
-        let player_counts = pass1_count(&zst_path, config, sink.clone())?;
-
-        let total_valid: u64 = player_counts.values().map(|v| *v as u64).sum();
-        let qualifying: HashSet<String> = player_counts
-            .iter()
-            .filter(|(_, count)| **count >= config.min_monthly_games)
-            .map(|(name, _)| name.clone())
-            .collect();
-        let qualifying_games: u64 = qualifying
-            .iter()
-            .filter_map(|n| player_counts.get(n))
-            .map(|v| *v as u64)
-            .sum();
+        let result = if config.two_phase {
+            count_dataset(i, url, config, db, &mut tracker, source, &sink)
+        } else {
+            process_dataset(i, url, config, db, &mut tracker, source, &sink)
+        };
+        match settle(result, url, config, db, &mut tracker, source, &sink)? {
+            Some(DatasetOutcome::Counted(dataset)) => counted.push(dataset),
+            Some(DatasetOutcome::BudgetReached) => break,
+            _ => {}
+        }
+    }
 
-        sink.send(UiEvent::Pass1Complete {
-            total_scanned: player_counts.values().map(|v| *v as u64).sum::<u64>() + total_valid, // approximate
-            valid_games: total_valid,
-            qualifying_players: qualifying.len() as u64,
-            qualifying_games,
+    if !counted.is_empty() {
+        let totals = global_totals(&counted, config, db)?;
+        let qualifying_players = totals.values().filter(|&&t| t >= config.min_total_games as u64).count();
+        tracker.emit(&*sink, UiEvent::CountingComplete {
+            datasets: counted.len() as u64,
+            qualifying_players: qualifying_players as u64,
         });
-        sink.check()?;
-
-        if !qualifying.is_empty() {
-            // Pass 2
-            sink.send(UiEvent::Pass2Started);
-            let mut writer = PlayerWriter::new(config.players_dir(), config.write_buffer_max_bytes);
-            let extracted = pass2_extract(&zst_path, config, &qualifying, &mut writer, sink.clone())?;
-            writer.flush_all()?;
-            sink.send(UiEvent::Pass2Complete { total_extracted: extracted });
-
-            let qualifying_counts: HashMap<String, u32> = player_counts
-                .into_iter()
-                .filter(|(name, _)| qualifying.contains(name))
-                .collect();
-            db.update_player_counts(&month, &qualifying_counts)?;
-        }
 
-        db.mark_dataset_processed(url)?;
+        for dataset in &counted {
+            if let Err(e) = sink.check() {
+                tracker.cancel(&*sink);
+                return Err(e);
+            }
+            let name = dataset.url.rsplit('/').next().unwrap_or(&dataset.url).to_string();
+            tracker.emit(&*sink, UiEvent::DatasetStarted { index: dataset.index, total, name });
 
-        if zst_path.exists() {
-            fs::remove_file(&zst_path)?;
+            let qualifying: HashSet<String> = dataset
+                .tallies
+                .keys()
+                .filter(|name| totals[*name] >= config.min_total_games as u64)
+                .cloned()
+                .collect();
+            let result = extract_dataset(dataset, &qualifying, config, db, &mut tracker, source, &sink);
+            if let Some(DatasetOutcome::BudgetReached) =
+                settle(result, &dataset.url, config, db, &mut tracker, source, &sink)?
+            {
+                break;
+            }
         }
-
-        sink.send(UiEvent::DatasetComplete);
     }
 
     // Final prune
-    let to_remove = db.get_players_below_total(config.min_total_games)?;
-    sink.send(UiEvent::PruneStarted { to_remove: to_remove.len() as u64 });
+    let started = Instant::now();
+    sink.send(UiEvent::Log("Scanning index for players to prune...".into()));
+    let mut to_remove = db.get_players_below_total(config.min_total_games)?;
+    if let Some(min_months) = config.min_active_months {
+        to_remove.extend(db.get_players_below_active_months(min_months)?);
+    }
+    if let Some(min_half_moves) = config.min_avg_half_moves {
+        to_remove.extend(db.get_players_below_avg_half_moves(min_half_moves)?);
+    }
+    to_remove.sort_unstable();
+    to_remove.dedup();
+    sink.send(UiEvent::Log(format!(
+        "Prune scan: {} players checked, {} to remove ({:.2}s)",
+        db.get_total_players()?,
+        to_remove.len(),
+        started.elapsed().as_secs_f64()
+    )));
+    tracker.emit(&*sink, UiEvent::PruneStarted { to_remove: to_remove.len() as u64 });
 
-    let writer = PlayerWriter::new(config.players_dir(), 0);
+    let writer = PlayerWriter::new(config.players_dir(), 0)
+        .with_layout(config.output_layout, "")
+        .with_lowercase_names(config.lowercase_filenames)
+        .with_sharding(config.shard_output);
     for name in &to_remove {
         writer.delete_player(name)?;
     }
-    let removed = db.remove_players_below_total(config.min_total_games)?;
-    cleanup_empty_dirs(&config.players_dir())?;
+    let removed = db.remove_players(&to_remove)?;
+    // A flat players directory has no shard directories to empty, and any
+    // subdirectory in it is not ours
+    if writer.uses_subdirs() {
+        cleanup_empty_dirs(&config.players_dir())?;
+    }
 
     let remaining = db.get_total_qualifying_players(config.min_total_games)?;
-    sink.send(UiEvent::PruneComplete {
+    tracker.emit(&*sink, UiEvent::PruneComplete {
         remaining: remaining as u64,
         removed: removed as u64,
     });
 
-    sink.send(UiEvent::Finished);
+    if let Some(format) = config.archive {
+        let (path, files) = archive::archive_players(&config.output_dir, format)?;
+        sink.send(UiEvent::Log(format!("Archived {} player files to {}", files, path.display())));
+    }
+    let stats = tracker.finish(&*sink);
+    if !stats.failed_datasets.is_empty() {
+        sink.send(UiEvent::Log(format!(
+            "{} dataset(s) failed and will be retried next run: {}",
+            stats.failed_datasets.len(),
+            stats.failed_datasets.join(", ")
+        )));
+    }
+
+    let summary_path = config.output_dir.join("summary.txt");
+    fs::write(&summary_path, stats.to_string())
+        .with_context(|| format!("Cannot write {}", summary_path.display()))?;
+    sink.send(UiEvent::Log(format!("Summary written to {}", summary_path.display())));
+
+    Ok(stats)
+}
+
+/// Mark the configured dataset for `month` (`YYYY-MM`) unprocessed and take
+/// its counts back out of the index, so the next run extracts it again.
+/// Returns the number of players whose totals were adjusted.
+pub fn reset_month(config: &Config, month: &str) -> Result<usize> {
+    let url = config
+        .dataset_urls
+        .iter()
+        .find(|url| extract_month(url) == month)
+        .with_context(|| format!("No configured dataset for month {}", month))?;
+    let mut db = Database::open(&config.db_path)?;
+    db.unmark_dataset_processed(url, month)
+}
+
+/// What `split_pgn` wrote.
+#[derive(Debug, PartialEq)]
+pub struct SplitSummary {
+    /// Players who qualified and got a file.
+    pub players: usize,
+    /// Game entries written (a game counts once per qualifying side).
+    pub games_written: u64,
+    /// Compressed bytes appended to player files.
+    pub bytes_written: u64,
+}
+
+/// Split one local input (`.pgn.zst`, `.pgn.gz` or `.pgn`) into player files
+/// under `config.players_dir()`: both passes of a dataset, without
+/// downloads or the index. `config`'s filters pick the valid games and its
+/// monthly thresholds the players; thresholds across datasets
+/// (`min_total_games` and the other prune rules) need the index and are
+/// not applied. Games are appended to existing files. Backs `rookt split`.
+pub fn split_pgn(input: &Path, config: &Config, sink: Arc<dyn EventSink>) -> Result<SplitSummary> {
+    let name = input.to_string_lossy();
+    let (player_counts, _, _, _) = pass1_count(open_input_reader(input, config, sink.clone())?, config, sink.clone())?;
+    let qualifying: HashSet<String> = player_counts
+        .into_iter()
+        .filter(|(_, tally)| tally.qualifies(config))
+        .map(|(name, _)| name)
+        .collect();
+
+    let mut writer = PlayerWriter::new(config.players_dir(), config.write_buffer_max_bytes)
+        .with_flush_interval(config.flush_interval)
+        .with_layout(config.output_layout, &extract_month(&name))
+        .with_lowercase_names(config.lowercase_filenames)
+        .with_sharding(config.shard_output)
+        .with_write_threads(config.write_threads);
+    if config.dedup_games {
+        writer = writer.with_dedup(HashMap::new());
+    }
+    let source_tag = config.tag_source.then(|| dataset_stem(&name));
+    let index = games_index(config, &extract_month(&name))?;
+    let mut extraction = pass2_extract(
+        open_input_reader(input, config, sink.clone())?,
+        config,
+        &qualifying,
+        source_tag,
+        &mut writer,
+        index,
+        sink,
+    )?;
+    writer.flush_all()?;
+    if let Some(index) = extraction.index.take() {
+        index.finish()?;
+    }
+    Ok(SplitSummary {
+        players: extraction.written.len(),
+        games_written: extraction.extracted,
+        bytes_written: writer.bytes_written(),
+    })
+}
+
+/// What `bench_pass1` measured.
+#[derive(Debug, PartialEq)]
+pub struct BenchReport {
+    /// The file pass 1 read, downloaded first for a URL.
+    pub input: PathBuf,
+    pub games_scanned: u64,
+    pub valid_games: u64,
+    /// Size of the input file (compressed, for `.zst` and `.gz`).
+    pub compressed_bytes: u64,
+    /// PGN text the parser read.
+    pub decoded_bytes: u64,
+    /// Time spent in pass 1, excluding any download.
+    pub elapsed: Duration,
+    /// Peak resident memory of the process, where it can be read.
+    pub peak_memory_bytes: Option<u64>,
+}
+
+impl BenchReport {
+    pub fn games_per_sec(&self) -> f64 {
+        self.games_scanned as f64 / self.elapsed.as_secs_f64().max(1e-9)
+    }
+
+    pub fn decoded_mb_per_sec(&self) -> f64 {
+        self.decoded_bytes as f64 / 1_048_576.0 / self.elapsed.as_secs_f64().max(1e-9)
+    }
+}
+
+/// Run pass 1 alone over one dataset, given as a local file or a URL
+/// (downloaded into `config.temp_dir` first, and kept there, so a later run
+/// or benchmark reuses it), and measure its throughput. `config`'s filters
+/// and read settings apply; nothing is written.
+pub fn bench_pass1(input: &str, config: &Config, sink: Arc<dyn EventSink>) -> Result<BenchReport> {
+    let path = if input.starts_with("http://") || input.starts_with("https://") {
+        let path = input_path(config, input);
+        if let Fetch::Unavailable(reason) = download::download(input, &path, 0, &config.into(), &*sink)? {
+            anyhow::bail!("Cannot fetch {}: {}", input, reason);
+        }
+        path
+    } else {
+        PathBuf::from(input)
+    };
+    let compressed_bytes = fs::metadata(&path).with_context(|| format!("Cannot open {}", path.display()))?.len();
+
+    let started = Instant::now();
+    let mut reader = ConsumedCounter { inner: open_input_reader(&path, config, sink.clone())?, consumed: 0 };
+    let (_, _, games_scanned, valid_games) = pass1_count(&mut reader, config, sink)?;
+    let elapsed = started.elapsed();
+    Ok(BenchReport {
+        input: path,
+        games_scanned,
+        valid_games,
+        compressed_bytes,
+        decoded_bytes: reader.consumed,
+        elapsed,
+        peak_memory_bytes: crate::system::peak_memory_bytes(),
+    })
+}
+
+/// Counts the bytes a `BufRead` consumer takes from `inner`.
+struct ConsumedCounter<R> {
+    inner: R,
+    consumed: u64,
+}
+
+impl<R: BufRead> Read for ConsumedCounter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.consumed += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for ConsumedCounter<R> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.consumed += amount as u64;
+        self.inner.consume(amount);
+    }
+}
+
+/// How processing a single dataset ended.
+#[derive(Debug, PartialEq)]
+enum DatasetOutcome {
+    Complete,
+    /// Two-phase mode: pass 1 is done and pass 2 waits for the other datasets.
+    Counted(CountedDataset),
+    /// The input could not be fetched (e.g. offline with no cached file);
+    /// the dataset stays unprocessed.
+    Unavailable(String),
+    /// Stopped early because the output budget ran out; the run should stop.
+    BudgetReached,
+}
+
+/// A dataset whose pass 1 is done.
+#[derive(Debug, PartialEq)]
+struct CountedDataset {
+    index: usize,
+    url: String,
+    month: String,
+    size_bytes: u64,
+    /// Pass 1 tallies of the players that qualify this month.
+    tallies: HashMap<String, Tally>,
+    /// Games per opponent of the same players, when pairings are tracked.
+    pairings: Pairings,
+    /// Time spent fetching and counting the dataset.
+    count_time: Duration,
+}
+
+/// Report how a dataset ended and record a failure. Returns the outcome, or
+/// None for a failure the run continues past; cancellation, and any failure
+/// with `fail_fast`, is returned as the error.
+fn settle(
+    result: Result<DatasetOutcome>,
+    url: &str,
+    config: &Config,
+    db: &mut Database,
+    tracker: &mut Tracker,
+    source: &mut dyn InputSource,
+    sink: &Arc<dyn EventSink>,
+) -> Result<Option<DatasetOutcome>> {
+    let name = url.rsplit('/').next().unwrap_or(url);
+    match result {
+        Ok(DatasetOutcome::Unavailable(reason)) => {
+            sink.send(UiEvent::Log(format!("Dataset not available: {} ({}), skipping", name, reason)));
+            Ok(Some(DatasetOutcome::Unavailable(reason)))
+        }
+        Ok(outcome @ DatasetOutcome::Counted(_)) => Ok(Some(outcome)),
+        Ok(outcome) => {
+            if outcome == DatasetOutcome::BudgetReached {
+                sink.send(UiEvent::Log(format!(
+                    "Output budget reached ({:.2} GB written); stopped early in {}. \
+                     Remaining datasets are left for a later run.",
+                    tracker.snapshot.stats.output_bytes as f64 / 1_073_741_824.0,
+                    name
+                )));
+            }
+            Ok(Some(outcome))
+        }
+        Err(e) if e.is::<Cancelled>() => {
+            tracker.cancel(&**sink);
+            Err(e)
+        }
+        Err(e) if config.fail_fast => Err(e),
+        Err(e) => {
+            // Drop the input so a re-run fetches a fresh copy instead of
+            // reusing a possibly corrupt file.
+            let _ = source.discard(url);
+            let error = format!("{:#}", e);
+            // Later datasets append to the same files, so a checkpoint kept
+            // for a later run could cut their games off: undo this pass 2
+            undo_pass2(db, url)?;
+            db.mark_dataset_failed(url, &error)?;
+            tracker.emit(&**sink, UiEvent::DatasetFailed { name: name.to_string(), error });
+            Ok(None)
+        }
+    }
+}
+
+/// Cut every file a dataset's pass 2 appended to, in this run or an
+/// interrupted one, back to its length before that pass 2 (removing the
+/// files it created), so the dataset can be retried from scratch.
+fn undo_pass2(db: &Database, url: &str) -> Result<()> {
+    for (path, len) in db.pass2_starts(url)? {
+        if !path.exists() {
+            continue;
+        }
+        match len {
+            0 => fs::remove_file(&path)?,
+            len => OpenOptions::new().write(true).open(&path)?.set_len(len)?,
+        }
+    }
+    Ok(())
+}
+
+/// Download, count, extract and record a single dataset.
+fn process_dataset(
+    index: usize,
+    url: &str,
+    config: &Config,
+    db: &mut Database,
+    tracker: &mut Tracker,
+    source: &mut dyn InputSource,
+    sink: &Arc<dyn EventSink>,
+) -> Result<DatasetOutcome> {
+    match count_dataset(index, url, config, db, tracker, source, sink)? {
+        DatasetOutcome::Counted(dataset) => {
+            let qualifying: HashSet<String> = dataset.tallies.keys().cloned().collect();
+            extract_dataset(&dataset, &qualifying, config, db, tracker, source, sink)
+        }
+        outcome => Ok(outcome),
+    }
+}
+
+/// Download a dataset and run pass 1 over it. The input is kept for pass 2.
+fn count_dataset(
+    index: usize,
+    url: &str,
+    config: &Config,
+    db: &Database,
+    tracker: &mut Tracker,
+    source: &mut dyn InputSource,
+    sink: &Arc<dyn EventSink>,
+) -> Result<DatasetOutcome> {
+    let started = Instant::now();
+    let size_bytes = match source.prepare(index, url, db, sink)? {
+        Fetch::Ready { size_bytes } => size_bytes,
+        Fetch::Unavailable(reason) => return Ok(DatasetOutcome::Unavailable(reason)),
+    };
+    sink.check()?;
+
+    let (player_counts, mut pairings, scanned, _) = with_read_retries(config, sink, "pass 1", |_| {
+        tracker.emit(&**sink, UiEvent::Preparing);
+        let reader = source.open(url, sink.clone())?;
+        tracker.emit(&**sink, UiEvent::Pass1Started);
+        pass1_count(reader, config, sink.clone())
+    })?;
+
+    let total_valid: u64 = player_counts.values().map(|t| t.games as u64).sum();
+    let tallies: HashMap<String, Tally> =
+        player_counts.into_iter().filter(|(_, tally)| tally.qualifies(config)).collect();
+    let qualifying_games: u64 = tallies.values().map(|t| t.games as u64).sum();
+    pairings.retain(|name, _| tallies.contains_key(name));
+
+    tracker.emit(&**sink, UiEvent::Pass1Complete {
+        total_scanned: scanned,
+        valid_games: total_valid,
+        qualifying_players: tallies.len() as u64,
+        qualifying_games,
+    });
+    sink.check()?;
+
+    Ok(DatasetOutcome::Counted(CountedDataset {
+        index,
+        url: url.to_string(),
+        month: extract_month(url),
+        size_bytes,
+        tallies,
+        pairings,
+        count_time: started.elapsed(),
+    }))
+}
+
+/// Each counted player's expected total once every counted dataset is
+/// extracted: the games already in the index plus what pass 2 will write
+/// for them in each dataset (after the monthly cap and sampling).
+fn global_totals(counted: &[CountedDataset], config: &Config, db: &Database) -> Result<HashMap<String, u64>> {
+    let indexed = db.get_player_totals()?;
+    let mut totals: HashMap<String, u64> = HashMap::new();
+    for dataset in counted {
+        for (name, tally) in &dataset.tallies {
+            let total = totals
+                .entry(name.clone())
+                .or_insert_with(|| indexed.get(name).copied().unwrap_or(0) as u64);
+            *total += tally.expected_written(config) as u64;
+        }
+    }
+    Ok(totals)
+}
+
+/// Run a pass (`run`, given the attempt number), restarting it up to
+/// `decode_retries` times when it fails on a transient read error. A
+/// restart reopens the input and reads it from the start, so `run` must
+/// undo whatever an earlier attempt left behind.
+fn with_read_retries<T>(
+    config: &Config,
+    sink: &Arc<dyn EventSink>,
+    pass: &str,
+    mut run: impl FnMut(u32) -> Result<T>,
+) -> Result<T> {
+    let mut attempt = 0;
+    loop {
+        match run(attempt) {
+            Err(e) if attempt < config.decode_retries && is_transient_read_error(&e) => {
+                attempt += 1;
+                sink.send(UiEvent::Log(format!(
+                    "Read error in {}: {:#}; restarting it ({}/{})",
+                    pass, e, attempt, config.decode_retries
+                )));
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Whether `err` comes from an I/O failure that may not happen again on a
+/// second read (timeouts, interrupted or low-level device errors), as
+/// opposed to corrupt data, a bad config or a cancellation.
+fn is_transient_read_error(err: &anyhow::Error) -> bool {
+    const EIO: i32 = 5;
+    err.chain().filter_map(|e| e.downcast_ref::<std::io::Error>()).any(|e| {
+        matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::Interrupted | ErrorKind::WouldBlock)
+            || (cfg!(unix) && e.raw_os_error() == Some(EIO))
+    })
+}
+
+/// Append a `{"player", "month", "games"}` line per qualifying player (by
+/// name) to the NDJSON file at `path`, where `games` is the player's valid
+/// games that month. The file is flushed before returning.
+fn log_qualifying(
+    path: &Path,
+    month: &str,
+    qualifying: &HashSet<String>,
+    tallies: &HashMap<String, Tally>,
+) -> Result<()> {
+    let file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+    let mut out = BufWriter::new(file);
+    let mut names: Vec<&String> = qualifying.iter().collect();
+    names.sort_unstable();
+    for name in names {
+        let games = tallies.get(name).map_or(0, |t| t.games);
+        let line = serde_json::json!({ "player": name, "month": month, "games": games });
+        writeln!(out, "{}", line)?;
+    }
+    out.flush()?;
     Ok(())
 }
 
+/// Run pass 2 over a counted dataset for `qualifying` players, record the
+/// result, drop the input and report the dataset complete.
+fn extract_dataset(
+    dataset: &CountedDataset,
+    qualifying: &HashSet<String>,
+    config: &Config,
+    db: &mut Database,
+    tracker: &mut Tracker,
+    source: &mut dyn InputSource,
+    sink: &Arc<dyn EventSink>,
+) -> Result<DatasetOutcome> {
+    let started = Instant::now();
+    let CountedDataset { url, month, tallies, pairings, .. } = dataset;
+    let qualifying_games: u64 = qualifying.iter().filter_map(|n| tallies.get(n)).map(|t| t.games as u64).sum();
+
+    if let Some(path) = &config.qualifying_log {
+        log_qualifying(path, month, qualifying, tallies)
+            .with_context(|| format!("Cannot write {}", path.display()))?;
+    }
+
+    let mut truncated = false;
+    let mut scan_limited = false;
+    let mut extracted = 0;
+    let mut written = HashMap::new();
+    let mut sites = HashMap::new();
+    if qualifying.is_empty() {
+        sink.send(UiEvent::Log(format!("No qualifying players for {}; nothing extracted", month)));
+    } else {
+        // Pass 2
+        tracker.emit(&**sink, UiEvent::Pass2Started);
+        let budget = config
+            .max_output_bytes
+            .map(|max| max.saturating_sub(tracker.snapshot.stats.output_bytes));
+        let journal = Pass2Journal { db, url };
+        let mut writer = PlayerWriter::new(config.players_dir(), config.write_buffer_max_bytes)
+            .with_flush_interval(config.flush_interval)
+            .with_output_limit(budget)
+            .with_layout(config.output_layout, month)
+            .with_lowercase_names(config.lowercase_filenames)
+            .with_sharding(config.shard_output)
+            .with_write_threads(config.write_threads)
+            .with_journal(&journal);
+        if config.dedup_games {
+            writer = writer.with_dedup(db.site_filters(qualifying)?);
+        }
+        // An earlier run died in this pass 2: keep the games it completed
+        let mut checkpoint = db.pass2_checkpoint(url)?;
+        if config.games_index.is_some() {
+            // Rows are appended only at the end of pass 2, so the length
+            // before it lets a rerun cut off an append whose dataset was
+            // never recorded
+            let index_path = config.games_index_path();
+            let start = match checkpoint.iter().position(|file| file.path == index_path) {
+                Some(i) => checkpoint.remove(i).len,
+                None => {
+                    let len = fs::metadata(&index_path).map_or(0, |meta| meta.len());
+                    let start = FileCheckpoint { path: index_path.clone(), player: String::new(), games: 0, len };
+                    db.save_pass2_checkpoint(url, &[start])?;
+                    len
+                }
+            };
+            if fs::metadata(&index_path).is_ok_and(|meta| meta.len() > start) {
+                OpenOptions::new().write(true).open(&index_path)?.set_len(start)?;
+            }
+        }
+        if !checkpoint.is_empty() {
+            writer.resume(&checkpoint)?;
+            let kept: u64 = writer.resumed_games().values().map(|&n| n as u64).sum();
+            sink.send(UiEvent::Log(format!(
+                "Resuming pass 2 of {}: {} games written by an interrupted run are kept",
+                month, kept
+            )));
+        }
+        let source_tag = config.tag_source.then(|| dataset_stem(url));
+        let extraction = with_read_retries(config, sink, "pass 2", |attempt| {
+            if attempt > 0 {
+                // Restarting from the first game: remove this dataset's frames
+                writer.rollback()?;
+                tracker.emit(&**sink, UiEvent::Pass2Started);
+            }
+            let index = games_index(config, month)?;
+            pass2_extract(source.open(url, sink.clone())?, config, qualifying, source_tag, &mut writer, index, sink.clone())
+        })?;
+        writer.flush_all()?;
+        if let Some(index) = extraction.index {
+            index.finish()?;
+        }
+        truncated = extraction.truncated;
+        scan_limited = extraction.scan_limited;
+        extracted = extraction.extracted;
+        if !truncated && extraction.seen != qualifying_games {
+            // Both passes decode the same file, so this means a flaky decoder
+            let msg = format!(
+                "pass 2 saw {} qualifying games but pass 1 counted {}",
+                extraction.seen, qualifying_games
+            );
+            if config.strict {
+                anyhow::bail!("Pass mismatch: {}", msg);
+            }
+            sink.send(UiEvent::Log(format!("Warning: {}", msg)));
+        }
+        tracker.emit(&**sink, UiEvent::Pass2Complete {
+            total_extracted: extraction.extracted,
+            bytes_written: writer.bytes_written(),
+        });
+        written = extraction.written;
+        sites = writer.changed_sites();
+    }
+
+    // Record what was actually written, so totals match the files on disk,
+    // in one transaction with the processed flag: a crash in between would
+    // otherwise leave counts for a month the next run extracts again
+    let move_totals: HashMap<String, (u32, u64)> = written
+        .keys()
+        .filter_map(|name| tallies.get(name).map(|t| (name.clone(), (t.games, t.half_moves))))
+        .collect();
+    let results: Option<HashMap<String, (u32, u32, u32)>> = config.record_results.then(|| {
+        written
+            .keys()
+            .filter_map(|name| tallies.get(name).map(|t| (name.clone(), (t.record.wins, t.record.draws, t.record.losses))))
+            .collect()
+    });
+    let pairings: Option<Pairings> = config.track_pairings.then(|| {
+        written
+            .keys()
+            .filter_map(|name| pairings.get(name).map(|opponents| (name.clone(), opponents.clone())))
+            .collect()
+    });
+    db.record_dataset(&DatasetRecord {
+        url,
+        month,
+        size_bytes: dataset.size_bytes,
+        partial: truncated || scan_limited,
+        counts: &written,
+        move_totals: &move_totals,
+        results: results.as_ref(),
+        sites: &sites,
+        pairings: pairings.as_ref(),
+    })?;
+
+    source.discard(url)?;
+    tracker.emit(&**sink, UiEvent::DatasetComplete {
+        qualifying_players: qualifying.len() as u64,
+        games_extracted: extracted,
+        duration: dataset.count_time + started.elapsed(),
+    });
+    Ok(if truncated { DatasetOutcome::BudgetReached } else { DatasetOutcome::Complete })
+}
+
 // ── Helpers ──────────────────────────────────────────────────────────────────
 
-fn extract_month(url: &str) -> String {
+/// Minimum time between `.progress.json` writes.
+const PROGRESS_SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Folds emitted events into a `ProgressSnapshot` and persists it, throttled
+/// to `PROGRESS_SAVE_INTERVAL`.
+struct Tracker {
+    snapshot: ProgressSnapshot,
+    path: PathBuf,
+    started: Instant,
+    last_save: Option<Instant>,
+}
+
+impl Tracker {
+    fn new(path: PathBuf) -> Self {
+        Self { snapshot: ProgressSnapshot::default(), path, started: Instant::now(), last_save: None }
+    }
+
+    /// Send `event` through the sink and fold it into the run totals. Saves
+    /// at most every `PROGRESS_SAVE_INTERVAL`, except that a phase change is
+    /// always saved: the next event may be a long way off.
+    fn emit(&mut self, sink: &dyn EventSink, event: UiEvent) {
+        let phase = self.snapshot.phase.clone();
+        self.snapshot.record(&event);
+        sink.send(event);
+        if self.snapshot.phase != phase || self.last_save.is_none_or(|t| t.elapsed() >= PROGRESS_SAVE_INTERVAL) {
+            self.save(sink);
+        }
+    }
+
+    fn save(&mut self, sink: &dyn EventSink) {
+        self.snapshot.stats.elapsed = self.started.elapsed();
+        if let Err(e) = self.snapshot.save(&self.path) {
+            sink.send(UiEvent::Log(format!("Warning: cannot save progress snapshot: {}", e)));
+        }
+        self.last_save = Some(Instant::now());
+    }
+
+    /// Report a cancellation and force a save, so the snapshot shows where
+    /// the run stopped. The interrupted dataset stays unprocessed.
+    fn cancel(&mut self, sink: &dyn EventSink) {
+        self.emit(sink, UiEvent::Cancelled);
+        self.save(sink);
+    }
+
+    /// Mark the run done, force a final save and return the totals.
+    fn finish(mut self, sink: &dyn EventSink) -> RunStats {
+        self.snapshot.record(&UiEvent::Finished);
+        self.save(sink);
+        self.snapshot.stats
+    }
+}
+
+/// Supported input extensions, most specific first.
+const INPUT_EXTENSIONS: [&str; 3] = [".pgn.zst", ".pgn.gz", ".pgn"];
+
+/// Return the supported input extension `name` ends with, if any.
+fn input_extension(name: &str) -> Option<&'static str> {
+    INPUT_EXTENSIONS.into_iter().find(|ext| name.ends_with(ext))
+}
+
+/// Local path of the downloaded input for `url` inside `temp_dir`.
+fn input_path(config: &Config, url: &str) -> PathBuf {
+    let ext = input_extension(url).unwrap_or(".pgn.zst");
+    config.temp_dir.join(format!("{}{}", extract_month(url), ext))
+}
+
+/// Dataset file name without its input extension, e.g.
+/// `lichess_db_standard_rated_2025-08`.
+fn dataset_stem(url: &str) -> &str {
     let filename = url.rsplit('/').next().unwrap_or(url);
-    let without_ext = filename.trim_end_matches(".pgn.zst");
-    without_ext.rsplit('_').next().unwrap_or("unknown").to_string()
+    input_extension(filename).map_or(filename, |ext| &filename[..filename.len() - ext.len()])
+}
+
+pub fn extract_month(url: &str) -> String {
+    dataset_stem(url).rsplit('_').next().unwrap_or("unknown").to_string()
+}
+
+/// Where dataset PGN comes from. Each dataset is prepared once, opened once
+/// per pass, then discarded.
+trait InputSource {
+    /// Make dataset `index` (`url`) readable, or report it unavailable.
+    fn prepare(&mut self, index: usize, url: &str, db: &Database, sink: &Arc<dyn EventSink>) -> Result<Fetch>;
+    /// Open a prepared dataset as decoded PGN text.
+    fn open(&self, url: &str, sink: Arc<dyn EventSink>) -> Result<Box<dyn BufRead>>;
+    /// Drop any local copy of `url` (after it was processed or failed).
+    fn discard(&mut self, url: &str) -> Result<()>;
+}
+
+/// The production source: datasets are downloaded (or prefetched) into
+/// `temp_dir` and decoded from there by extension.
+struct HttpSource<'a> {
+    config: &'a Config,
+    prefetcher: Prefetcher,
+}
+
+impl<'a> HttpSource<'a> {
+    fn new(config: &'a Config) -> Self {
+        let prefetcher = Prefetcher::new(config.download_concurrency.saturating_sub(1), config.into());
+        Self { config, prefetcher }
+    }
+}
+
+impl InputSource for HttpSource<'_> {
+    /// Download (or wait for the prefetch), then start fetching what comes next.
+    fn prepare(&mut self, index: usize, url: &str, db: &Database, sink: &Arc<dyn EventSink>) -> Result<Fetch> {
+        let fetch = self.prefetcher.fetch(index, url, &input_path(self.config, url), &**sink)?;
+        self.prefetcher.schedule(index, self.config, db, sink)?;
+        Ok(fetch)
+    }
+
+    fn open(&self, url: &str, sink: Arc<dyn EventSink>) -> Result<Box<dyn BufRead>> {
+        Ok(Box::new(open_input_reader(&input_path(self.config, url), self.config, sink)?))
+    }
+
+    fn discard(&mut self, url: &str) -> Result<()> {
+        let path = input_path(self.config, url);
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Background downloads of upcoming datasets, keyed by dataset index.
+/// At most `depth` datasets ahead of the current one are fetched. All
+/// downloads, foreground or not, share `options` (and so its rate limit).
+struct Prefetcher {
+    depth: usize,
+    options: DownloadOptions,
+    handles: HashMap<usize, JoinHandle<Result<Fetch>>>,
+}
+
+impl Prefetcher {
+    fn new(depth: usize, options: DownloadOptions) -> Self {
+        Self { depth, options, handles: HashMap::new() }
+    }
+
+    /// Wait for the prefetch of `index`, or download it now if none was started.
+    fn fetch(&mut self, index: usize, url: &str, dest: &Path, sink: &dyn EventSink) -> Result<Fetch> {
+        match self.wait(index)? {
+            Some(fetch) => Ok(fetch),
+            None => download::download(url, dest, index, &self.options, sink),
+        }
+    }
+
+    /// Start downloads for the unprocessed datasets following `current`.
+    fn schedule(
+        &mut self,
+        current: usize,
+        config: &Config,
+        db: &Database,
+        sink: &Arc<dyn EventSink>,
+    ) -> Result<()> {
+        let datasets = config.datasets();
+        for (index, url) in datasets.iter().enumerate().skip(current + 1).take(self.depth) {
+            if self.handles.contains_key(&index) || db.is_dataset_processed(url)? {
+                continue;
+            }
+            let url = url.clone();
+            let dest = input_path(config, &url);
+            let sink = sink.clone();
+            let options = self.options.clone();
+            let handle = thread::spawn(move || download::download(&url, &dest, index, &options, &*sink));
+            self.handles.insert(index, handle);
+        }
+        Ok(())
+    }
+
+    /// Block until the prefetch of `index` finishes. Returns None if none was started.
+    fn wait(&mut self, index: usize) -> Result<Option<Fetch>> {
+        match self.handles.remove(&index) {
+            Some(handle) => {
+                let fetch = handle.join().map_err(|_| anyhow::anyhow!("Download thread panicked"))??;
+                Ok(Some(fetch))
+            }
+            None => Ok(None),
+        }
+    }
 }
 
-/// ProgressReader sends FileProgress events through the sink.
+/// ProgressReader sends FileProgress events through the sink. It sits on the
+/// compressed side; `decoded` is the uncompressed byte count maintained by
+/// the `DecodedCounter` on the other side of the decoder.
 struct ProgressReader<R> {
     inner: R,
     read_bytes: u64,
     total_bytes: u64,
+    decoded: Arc<AtomicU64>,
     sink: Arc<dyn EventSink>,
     last_report: u64,
 }
 
 impl<R: Read> ProgressReader<R> {
-    fn new(inner: R, total_bytes: u64, sink: Arc<dyn EventSink>) -> Self {
-        Self { inner, read_bytes: 0, total_bytes, sink, last_report: 0 }
+    fn new(inner: R, total_bytes: u64, decoded: Arc<AtomicU64>, sink: Arc<dyn EventSink>) -> Self {
+        Self { inner, read_bytes: 0, total_bytes, decoded, sink, last_report: 0 }
     }
 }
 
@@ -152,6 +980,7 @@ impl<R: Read> Read for ProgressReader<R> {
             self.sink.send(UiEvent::FileProgress {
                 bytes_read: self.read_bytes,
                 total_bytes: self.total_bytes,
+                decoded_bytes: self.decoded.load(Ordering::Relaxed),
             });
             self.last_report = self.read_bytes;
         }
@@ -159,111 +988,749 @@ impl<R: Read> Read for ProgressReader<R> {
     }
 }
 
-fn open_zst_reader(
+/// Counts bytes coming out of the decoder (uncompressed PGN text).
+struct DecodedCounter<R> {
+    inner: R,
+    count: Arc<AtomicU64>,
+}
+
+impl<R: Read> Read for DecodedCounter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+/// Open a PGN input, picking the decoder from the extension:
+/// `.zst` → zstd, `.gz` → gzip, `.pgn` → plain text. With `use_mmap` the
+/// file is memory-mapped, or read buffered if it cannot be.
+fn open_input_reader(
     path: &Path,
+    config: &Config,
     sink: Arc<dyn EventSink>,
-) -> Result<BufReader<zstd::Decoder<'static, BufReader<ProgressReader<File>>>>> {
+) -> Result<BufReader<Box<dyn Read>>> {
+    let name = path.to_string_lossy();
     let file = File::open(path).with_context(|| format!("Cannot open {}", path.display()))?;
     let file_size = file.metadata()?.len();
-    let progress = ProgressReader::new(file, file_size, sink);
-    let decoder = zstd::Decoder::new(progress)?;
-    Ok(BufReader::with_capacity(256 * 1024, decoder))
+    let input: Box<dyn Read + Send> = match config.use_mmap.then(|| Mmap::map(&file)) {
+        Some(Ok(map)) => Box::new(Cursor::new(map)),
+        Some(Err(e)) => {
+            sink.send(UiEvent::Log(format!("Cannot map {} ({}); reading it buffered", path.display(), e)));
+            Box::new(file)
+        }
+        None => Box::new(file),
+    };
+    let decoded = Arc::new(AtomicU64::new(0));
+    let progress = ProgressReader::new(input, file_size, decoded.clone(), sink);
+    let decoder: Box<dyn Read + Send> = if name.ends_with(".zst") {
+        let mut decoder = zstd::Decoder::new(progress)?;
+        decoder
+            .window_log_max(config.zstd_window_log_max)
+            .context("Invalid zstd window log max")?;
+        Box::new(decoder)
+    } else if name.ends_with(".gz") {
+        Box::new(flate2::read::MultiGzDecoder::new(BufReader::new(progress)))
+    } else if name.ends_with(".pgn") {
+        Box::new(progress)
+    } else {
+        anyhow::bail!("Unsupported input format: {}", path.display());
+    };
+    let decoder: Box<dyn Read> = if config.decode_threads > 1 {
+        Box::new(ThreadedReader::new(decoder))
+    } else {
+        decoder
+    };
+    let counted: Box<dyn Read> = Box::new(DecodedCounter { inner: decoder, count: decoded });
+    Ok(BufReader::with_capacity(config.read_buffer_bytes.max(1), counted))
 }
 
-fn is_valid_game(info: &GameInfo, config: &Config) -> bool {
-    if info.event != config.event_filter {
-        return false;
+/// Decoded bytes per chunk handed from the decoder thread to the parser.
+const DECODE_CHUNK_BYTES: usize = 1 << 20;
+/// Chunks the decoder thread may run ahead of the parser.
+const DECODE_CHUNKS_AHEAD: usize = 4;
+
+/// Runs a decoder (and the file reads under it) on its own thread, up to
+/// `DECODE_CHUNKS_AHEAD` chunks ahead, so decompression overlaps parsing.
+/// The thread stops when the input ends, on an error (passed on to the
+/// reader) or once the reader is dropped.
+struct ThreadedReader {
+    rx: mpsc::Receiver<std::io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl ThreadedReader {
+    fn new(mut inner: Box<dyn Read + Send>) -> Self {
+        let (tx, rx) = mpsc::sync_channel(DECODE_CHUNKS_AHEAD);
+        thread::spawn(move || {
+            loop {
+                let mut chunk = vec![0; DECODE_CHUNK_BYTES];
+                let mut filled = 0;
+                while filled < chunk.len() {
+                    match inner.read(&mut chunk[filled..]) {
+                        Ok(0) => break,
+                        Ok(n) => filled += n,
+                        Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                        Err(e) => {
+                            let _ = tx.send(Err(e));
+                            return;
+                        }
+                    }
+                }
+                chunk.truncate(filled);
+                if filled == 0 || tx.send(Ok(chunk)).is_err() {
+                    return;
+                }
+            }
+        });
+        Self { rx, chunk: Vec::new(), pos: 0 }
     }
-    if let Some(tc) = &config.time_control_filter {
-        if info.time_control != *tc {
-            return false;
+}
+
+impl Read for ThreadedReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.pos == self.chunk.len() {
+            match self.rx.recv() {
+                Ok(chunk) => {
+                    self.chunk = chunk?;
+                    self.pos = 0;
+                }
+                Err(_) => return Ok(0), // decoder thread finished
+            }
         }
+        let n = (&self.chunk[self.pos..]).read(buf)?;
+        self.pos += n;
+        Ok(n)
     }
-    info.half_move_count >= config.min_full_moves * 2
 }
 
-fn pass1_count(
-    zst_path: &Path,
-    config: &Config,
-    sink: Arc<dyn EventSink>,
-) -> Result<HashMap<String, u32>> {
-    let reader = open_zst_reader(zst_path, sink.clone())?;
-    let mut parser = PgnParser::new(reader);
-    let mut counts: HashMap<String, u32> = HashMap::new();
-    let mut scanned = 0u64;
-    let mut valid = 0u64;
+/// Game filter built once per pass from `Config`, holding any state that
+/// is expensive to derive per game (e.g. the tokenized move prefix).
+struct GameFilter<'a> {
+    config: &'a Config,
+    move_prefix: Vec<String>,
+    event_regex: Option<Regex>,
+}
 
-    while let Some(info) = parser.next_info()? {
-        scanned += 1;
-        if scanned % 100_000 == 0 {
-            sink.send(UiEvent::Pass1Progress {
-                games_scanned: scanned,
-                valid_games: valid,
-                unique_players: counts.len() as u64,
-            });
-        }
-        if scanned % 500_000 == 0 {
-            sink.check()?;
+impl<'a> GameFilter<'a> {
+    fn new(config: &'a Config) -> Result<Self> {
+        let move_prefix = config.move_prefix.as_deref().map(parser::san_moves).unwrap_or_default();
+        if let Some(prefix) = &config.move_prefix
+            && move_prefix.is_empty()
+        {
+            anyhow::bail!("Move prefix '{}' has no moves, e.g. 1. e4 c5", prefix);
         }
+        let event_regex = config.event_filter_regex.as_deref().map(event_regex).transpose()?;
+        Ok(Self { config, move_prefix, event_regex })
+    }
 
-        if !is_valid_game(&info, config) {
-            continue;
-        }
-        valid += 1;
+    /// Number of opening plies the parser must capture for `is_valid`.
+    fn opening_plies(&self) -> usize {
+        self.move_prefix.len()
+    }
 
-        if !info.white.is_empty() {
-            *counts.entry(info.white.clone()).or_insert(0) += 1;
+    /// Whether a game is kept: never when an exclusion applies; otherwise
+    /// when it meets every set criterion outside `Config::any_of` and, if
+    /// any criterion in that group is set, at least one of those.
+    fn is_valid(&self, info: &GameInfo) -> bool {
+        let config = self.config;
+        if config.exclude_bots && (info.white_title == "BOT" || info.black_title == "BOT") {
+            return false;
+        }
+        if config.exclude_non_standard_start && !info.standard_start() {
+            return false;
         }
-        if !info.black.is_empty() {
-            *counts.entry(info.black).or_insert(0) += 1;
+        let mut grouped = None;
+        for criterion in Criterion::ALL {
+            let Some(passed) = self.check(criterion, info) else {
+                continue;
+            };
+            if config.any_of.contains(&criterion) {
+                grouped = Some(grouped.unwrap_or(false) || passed);
+            } else if !passed {
+                return false;
+            }
         }
+        grouped.unwrap_or(true)
     }
 
-    sink.send(UiEvent::Pass1Progress {
-        games_scanned: scanned,
-        valid_games: valid,
-        unique_players: counts.len() as u64,
+    /// Whether `info` meets `criterion`, or None when it is not set.
+    fn check(&self, criterion: Criterion, info: &GameInfo) -> Option<bool> {
+        let config = self.config;
+        match criterion {
+            Criterion::Event => match &self.event_regex {
+                Some(re) => Some(re.is_match(&info.event)),
+                None => (!config.event_filter.is_empty()).then(|| info.event == config.event_filter),
+            },
+            Criterion::Rated => config
+                .rated_filter
+                .map(|rated| info.event.starts_with(if rated { "Rated" } else { "Casual" })),
+            Criterion::TimeControl => config.time_control_filter.as_ref().map(|tc| info.time_control == *tc),
+            Criterion::Titles => config
+                .require_titles
+                .as_ref()
+                .map(|titles| titles.iter().any(|t| *t == info.white_title || *t == info.black_title)),
+            Criterion::Dates => (config.date_from.is_some() || config.date_to.is_some())
+                .then(|| date_in_range(&info.date, config)),
+            Criterion::FinalClock => (config.min_final_clock.is_some() || config.max_final_clock.is_some())
+                .then(|| final_clocks_in_range(info, config)),
+            Criterion::MinMoves => (config.min_full_moves > 0).then(|| info.half_move_count >= config.min_full_moves * 2),
+            Criterion::MovePrefix => (!self.move_prefix.is_empty()).then(|| info.opening == self.move_prefix),
+        }
+    }
+}
+
+/// Compile an event regex, anchored so it must match the whole Event header.
+pub fn event_regex(pattern: &str) -> Result<Regex> {
+    Regex::new(&format!("^(?:{})$", pattern)).with_context(|| format!("Invalid event regex '{}'", pattern))
+}
+
+/// Check the final clocks against `min_final_clock` / `max_final_clock`.
+fn final_clocks_in_range(info: &GameInfo, config: &Config) -> bool {
+    if config.min_final_clock.is_none() && config.max_final_clock.is_none() {
+        return true;
+    }
+    let (Some(white), Some(black)) = (info.white_clock, info.black_clock) else {
+        return false;
+    };
+    config.min_final_clock.is_none_or(|min| white >= min || black >= min)
+        && config.max_final_clock.is_none_or(|max| white <= max && black <= max)
+}
+
+/// Check a `YYYY.MM.DD` date against the configured inclusive bounds.
+/// Unknown or partial dates (containing `?`) fail whenever a bound is set.
+fn date_in_range(date: &str, config: &Config) -> bool {
+    if config.date_from.is_none() && config.date_to.is_none() {
+        return true;
+    }
+    if date.len() != 10 || date.contains('?') {
+        return false;
+    }
+    config.date_from.as_ref().is_none_or(|from| date >= from.as_str())
+        && config.date_to.as_ref().is_none_or(|to| date <= to.as_str())
+}
+
+/// Pass 1: tally each player's valid games (both sides of every game count,
+/// whatever `require_both_players` says about which games pass 2 writes).
+/// Returns the tallies, each player's games per opponent (empty unless
+/// `track_pairings` is set) and the numbers of games scanned and valid.
+fn pass1_count(
+    reader: impl BufRead,
+    config: &Config,
+    sink: Arc<dyn EventSink>,
+) -> Result<(HashMap<String, Tally>, Pairings, u64, u64)> {
+    let filter = GameFilter::new(config)?;
+    let mut parser = PgnParser::new(reader).with_opening_plies(filter.opening_plies());
+    let mut counts: HashMap<String, Tally> = HashMap::new();
+    let mut opponents: HashMap<String, Opponents> = HashMap::new();
+    let mut pairings: Pairings = HashMap::new();
+    let mut scanned = 0u64;
+    let mut valid = 0u64;
+
+    for info in parser.infos() {
+        if config.max_scan_games.is_some_and(|max| scanned >= max) {
+            sink.send(UiEvent::Log(format!("Scan limit reached: only the first {} games were read", scanned)));
+            break;
+        }
+        let info = info?;
+        scanned += 1;
+        if scanned.is_multiple_of(config.progress_interval_games) {
+            sink.send(UiEvent::Pass1Progress {
+                games_scanned: scanned,
+                valid_games: valid,
+                unique_players: counts.len() as u64,
+            });
+            sink.send(UiEvent::TopPlayers(top_players(&counts, TOP_PLAYERS)));
+        }
+        if scanned.is_multiple_of(config.check_interval_games) {
+            sink.check()?;
+        }
+
+        if !filter.is_valid(&info) {
+            continue;
+        }
+        valid += 1;
+
+        let (white, black) = group_keys(&info, config.group_by);
+        let sides = [
+            (white, Record::of(&info.result, true)),
+            (black, Record::of(&info.result, false)),
+        ];
+        for (name, record) in sides {
+            if !name.is_empty() {
+                let tally = counts.entry(name.to_string()).or_default();
+                tally.games += 1;
+                tally.half_moves += info.half_move_count as u64;
+                tally.record.add(record);
+            }
+        }
+        if let Some(min) = config.min_unique_opponents
+            && !white.is_empty()
+            && !black.is_empty()
+        {
+            for (name, opponent) in [(white, black), (black, white)] {
+                match opponents.get_mut(name) {
+                    Some(seen) => seen.add(opponent, min),
+                    None => {
+                        let mut seen = Opponents::new(config.approximate_opponents);
+                        seen.add(opponent, min);
+                        opponents.insert(name.to_string(), seen);
+                    }
+                }
+            }
+        }
+        if config.track_pairings && !white.is_empty() && !black.is_empty() {
+            count_pairing(&mut pairings, white, black);
+            count_pairing(&mut pairings, black, white);
+        }
+    }
+    for (name, seen) in &opponents {
+        if let Some(tally) = counts.get_mut(name) {
+            tally.opponents = seen.count();
+        }
+    }
+
+    sink.send(UiEvent::Pass1Progress {
+        games_scanned: scanned,
+        valid_games: valid,
+        unique_players: counts.len() as u64,
     });
-    Ok(counts)
+    sink.send(UiEvent::TopPlayers(top_players(&counts, TOP_PLAYERS)));
+    Ok((counts, pairings, scanned, valid))
+}
+
+/// The names a game is counted and written under, white's first. A game
+/// between two members of one team counts once, for white's side; the
+/// other name is then empty, as for a missing header.
+fn group_keys(info: &GameInfo, group_by: GroupBy) -> (&str, &str) {
+    let (white, black) = match group_by {
+        GroupBy::Player => (&info.white, &info.black),
+        GroupBy::Team => (&info.white_team, &info.black_team),
+    };
+    (white, if black == white { "" } else { black })
+}
+
+/// Number of leaders reported in `TopPlayers` events.
+const TOP_PLAYERS: usize = 5;
+
+/// Each player's valid games per opponent in a dataset, from pass 1.
+type Pairings = HashMap<String, HashMap<String, u32>>;
+
+/// One player's valid games in a dataset, from pass 1.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Tally {
+    games: u32,
+    half_moves: u64,
+    record: Record,
+    /// Distinct opponents, counted only for `min_unique_opponents`.
+    opponents: u32,
+}
+
+/// The distinct opponents pass 1 has seen for one player. An exact set
+/// stops growing at the threshold, since only reaching it matters; the
+/// sketch (linear counting over 256 bits) stays at 32 bytes, reading
+/// within a few percent up to a few hundred opponents and saturating
+/// beyond about a thousand.
+enum Opponents {
+    Exact(HashSet<String>),
+    Sketch([u64; 4]),
 }
 
+impl Opponents {
+    fn new(approximate: bool) -> Self {
+        match approximate {
+            true => Self::Sketch([0; 4]),
+            false => Self::Exact(HashSet::new()),
+        }
+    }
+
+    /// Record `name`; exact sets ignore it once they hold `cap` names.
+    fn add(&mut self, name: &str, cap: u32) {
+        match self {
+            Self::Exact(names) => {
+                if names.len() < cap as usize && !names.contains(name) {
+                    names.insert(name.to_string());
+                }
+            }
+            Self::Sketch(bits) => {
+                let mut hasher = std::hash::DefaultHasher::new();
+                std::hash::Hash::hash(name, &mut hasher);
+                let bit = std::hash::Hasher::finish(&hasher) % 256;
+                bits[(bit / 64) as usize] |= 1 << (bit % 64);
+            }
+        }
+    }
+
+    fn count(&self) -> u32 {
+        match self {
+            Self::Exact(names) => names.len() as u32,
+            Self::Sketch(bits) => {
+                let zeros: u32 = bits.iter().map(|word| word.count_zeros()).sum();
+                match zeros {
+                    0 => u32::MAX,
+                    _ => (-256.0 * (zeros as f64 / 256.0).ln()).round() as u32,
+                }
+            }
+        }
+    }
+}
+
+/// Wins, draws and losses from one player's side.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Record {
+    wins: u32,
+    draws: u32,
+    losses: u32,
+}
+
+impl Record {
+    /// One game's `result` for the player on the white (or black) side.
+    /// Unfinished (`*`) and missing results count as nothing.
+    fn of(result: &str, white: bool) -> Self {
+        match (result, white) {
+            ("1-0", true) | ("0-1", false) => Self { wins: 1, ..Self::default() },
+            ("0-1", true) | ("1-0", false) => Self { losses: 1, ..Self::default() },
+            ("1/2-1/2", _) => Self { draws: 1, ..Self::default() },
+            _ => Self::default(),
+        }
+    }
+
+    fn add(&mut self, other: Record) {
+        self.wins += other.wins;
+        self.draws += other.draws;
+        self.losses += other.losses;
+    }
+}
+
+impl Tally {
+    /// Whether the player qualifies for extraction this month.
+    fn qualifies(&self, config: &Config) -> bool {
+        self.games >= config.min_monthly_games
+            && config
+                .min_avg_half_moves
+                .is_none_or(|min| self.half_moves as f64 >= min * self.games as f64)
+            && config.min_unique_opponents.is_none_or(|min| self.opponents >= min)
+    }
+
+    /// Games pass 2 writes for the player, after the monthly cap and sampling.
+    fn expected_written(&self, config: &Config) -> u32 {
+        [config.max_monthly_games, config.sample_per_player]
+            .into_iter()
+            .flatten()
+            .fold(self.games, u32::min)
+    }
+}
+
+/// Add one game against `opponent` to `name`'s pairings, cloning the names
+/// only the first time they meet.
+fn count_pairing(pairings: &mut Pairings, name: &str, opponent: &str) {
+    let opponents = match pairings.get_mut(name) {
+        Some(opponents) => opponents,
+        None => pairings.entry(name.to_string()).or_default(),
+    };
+    match opponents.get_mut(opponent) {
+        Some(games) => *games += 1,
+        None => {
+            opponents.insert(opponent.to_string(), 1);
+        }
+    }
+}
+
+/// The `k` players with the most games in `counts`, most first (ties by
+/// name). One pass with a `k`-entry min-heap, so it is only run on the
+/// progress tick, not per game.
+fn top_players(counts: &HashMap<String, Tally>, k: usize) -> Vec<(String, u32)> {
+    let mut heap = BinaryHeap::with_capacity(k + 1);
+    for (name, tally) in counts {
+        heap.push(Reverse((tally.games, Reverse(name.as_str()))));
+        if heap.len() > k {
+            heap.pop();
+        }
+    }
+    heap.into_sorted_vec()
+        .into_iter()
+        .map(|Reverse((games, Reverse(name)))| (name.to_string(), games))
+        .collect()
+}
+
+/// Saves the pass 2 checkpoints of one dataset to the index.
+struct Pass2Journal<'a> {
+    db: &'a Database,
+    url: &'a str,
+}
+
+impl FlushJournal for Pass2Journal<'_> {
+    fn record(&self, files: &[FileCheckpoint]) -> Result<()> {
+        self.db.save_pass2_checkpoint(self.url, files)
+    }
+}
+
+/// Outcome of pass 2 for one dataset.
+struct Extraction {
+    /// Game entries written (a game counts once per qualifying side).
+    extracted: u64,
+    /// Qualifying sides of valid games seen, before any cap or sampling.
+    /// Matches pass 1's `qualifying_games` when both passes agree.
+    seen: u64,
+    /// Games written per player.
+    written: HashMap<String, u32>,
+    /// Extraction stopped early because the writer's output limit was reached.
+    truncated: bool,
+    /// Extraction stopped at `Config::max_scan_games`, like pass 1.
+    scan_limited: bool,
+    /// `Config::memory_ceiling_bytes`.
+    memory_ceiling: Option<u64>,
+    /// Games per player to pass over because a resumed run already wrote
+    /// them (`PlayerWriter::resumed_games`). They still count as written.
+    skip: HashMap<String, u32>,
+    /// Rows for `games.index`, one per game counted as written.
+    index: Option<GamesIndex>,
+}
+
+/// Multiplier from buffered PGN bytes to the memory they are assumed to
+/// take: growing buffers can reserve up to twice what they hold.
+const MEMORY_HEADROOM: u64 = 2;
+
+/// Whether `buffered` bytes of game text would reach `ceiling`.
+fn over_memory_ceiling(buffered: usize, ceiling: Option<u64>) -> bool {
+    ceiling.is_some_and(|ceiling| buffered as u64 * MEMORY_HEADROOM >= ceiling)
+}
+
+impl Extraction {
+    /// Add a game to the writer, flushing early if the buffer nears the
+    /// memory ceiling, and report the output size whenever a flush happened.
+    /// With dedup, `site` is recorded for the player here, once the game is
+    /// certain to be written; a game whose site is recorded already (a
+    /// repeat among sampled games) is passed over.
+    fn write(&mut self, writer: &mut PlayerWriter, player: &str, site: &str, pgn: &str, sink: &dyn EventSink) -> Result<()> {
+        if !writer.first_copy(player, site) {
+            return Ok(());
+        }
+        match self.skip.get_mut(player) {
+            Some(left) if *left > 0 => *left -= 1,
+            _ => {
+                let before = writer.bytes_written();
+                writer.add_game(player, pgn)?;
+                if over_memory_ceiling(writer.buffered_bytes(), self.memory_ceiling) {
+                    writer.flush_all()?;
+                }
+                if writer.bytes_written() != before {
+                    sink.send(UiEvent::WriteProgress { bytes_written: writer.bytes_written() });
+                }
+                self.truncated = writer.limit_reached();
+            }
+        }
+        if let Some(index) = self.index.as_mut() {
+            index.add(player, pgn)?;
+        }
+        self.extracted += 1;
+        *self.written.entry(player.to_string()).or_insert(0) += 1;
+        Ok(())
+    }
+}
+
+/// Pass 2: write the valid games of qualifying players. With `source_tag`
+/// each written game gets a `RooktSource` header naming its dataset; with
+/// `index` each one also gets a row there (see `Extraction::index`).
 fn pass2_extract(
-    zst_path: &Path,
+    reader: impl BufRead,
     config: &Config,
     qualifying: &HashSet<String>,
+    source_tag: Option<&str>,
     writer: &mut PlayerWriter,
+    index: Option<GamesIndex>,
     sink: Arc<dyn EventSink>,
-) -> Result<u64> {
-    let reader = open_zst_reader(zst_path, sink.clone())?;
-    let mut parser = PgnParser::new(reader);
-    let mut extracted = 0u64;
+) -> Result<Extraction> {
+    let filter = GameFilter::new(config)?;
+    let mut parser = PgnParser::new(reader)
+        .with_opening_plies(filter.opening_plies())
+        .with_verbatim(config.verbatim_pgn);
+    let mut out = Extraction {
+        extracted: 0,
+        seen: 0,
+        written: HashMap::new(),
+        truncated: false,
+        scan_limited: false,
+        memory_ceiling: config.memory_ceiling_bytes,
+        skip: writer.resumed_games(),
+        index,
+    };
+    let mut taken: HashMap<String, u32> = HashMap::new();
+    let cap = config.max_monthly_games;
+    let mut sampler = config.sample_per_player.map(|k| PlayerSampler::new(k, config.sample_seed));
 
-    while let Some(game) = parser.next_game()? {
-        if !is_valid_game(&game.info, config) {
+    'games: for (scanned, game) in parser.games().enumerate() {
+        if config.max_scan_games.is_some_and(|max| scanned as u64 >= max) {
+            out.scan_limited = true;
+            break;
+        }
+        let game = game?;
+        if !filter.is_valid(&game.info) {
             continue;
         }
 
-        let white_ok = qualifying.contains(&game.info.white);
-        let black_ok = qualifying.contains(&game.info.black);
-
-        if white_ok {
-            writer.add_game(&game.info.white, &game.raw_pgn)?;
-            extracted += 1;
+        let (white, black) = group_keys(&game.info, config.group_by);
+        let white_q = qualifying.contains(white);
+        let black_q = qualifying.contains(black);
+        out.seen += white_q as u64 + black_q as u64;
+        if config.require_both_players && !(white_q && black_q) {
+            continue;
         }
-        if black_ok {
-            writer.add_game(&game.info.black, &game.raw_pgn)?;
-            extracted += 1;
+        // A duplicate never takes a capped slot. Its site is only recorded
+        // once the game is written, not when the cap or sampler drops it
+        let site = game.info.site.as_str();
+        let white_ok = white_q && !writer.has_site(white, site) && take_slot(&mut taken, white, cap);
+        let black_ok = black_q && !writer.has_site(black, site) && take_slot(&mut taken, black, cap);
+        let wanted = white_ok || black_ok;
+        let mut pgn = Cow::Borrowed(game.raw_pgn.as_str());
+        if config.strip_comments && wanted {
+            pgn = Cow::Owned(strip_comments(&pgn));
+        }
+        if let Some(source) = source_tag.filter(|_| wanted) {
+            pgn = Cow::Owned(with_source_tag(&pgn, source));
+        }
+
+        for (ok, player) in [(white_ok, white), (black_ok, black)] {
+            if !ok {
+                continue;
+            }
+            match sampler.as_mut() {
+                Some(sampler) => {
+                    sampler.offer(player, &pgn);
+                    // Samples cannot be written before the stream ends
+                    if over_memory_ceiling(sampler.buffered_bytes(), config.memory_ceiling_bytes) {
+                        anyhow::bail!(
+                            "Sampled games ({:.1} MB) would exceed the memory ceiling; \
+                             lower the sample size or raise the ceiling",
+                            sampler.buffered_bytes() as f64 / 1_048_576.0
+                        );
+                    }
+                }
+                None => {
+                    out.write(writer, player, site, &pgn, &*sink)?;
+                    if out.truncated {
+                        break 'games;
+                    }
+                }
+            }
         }
 
-        if extracted % 100_000 == 0 && extracted > 0 {
+        let extracted = out.extracted;
+
+        if extracted.is_multiple_of(config.progress_interval_games) && extracted > 0 {
             sink.send(UiEvent::Pass2Progress { games_extracted: extracted });
         }
-        if extracted % 500_000 == 0 {
+        if extracted.is_multiple_of(config.check_interval_games) {
             sink.check()?;
         }
     }
 
-    Ok(extracted)
+    // Sampled games are only known once the whole stream has been seen
+    if let Some(sampler) = sampler {
+        'players: for (player, games) in sampler.into_samples() {
+            for pgn in &games {
+                let site = games_index::header(pgn, "Site").unwrap_or("");
+                out.write(writer, &player, site, pgn, &*sink)?;
+                if out.truncated {
+                    break 'players;
+                }
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+/// A fresh stage of `games.index` rows for `month`, when the index is on.
+fn games_index(config: &Config, month: &str) -> Result<Option<GamesIndex>> {
+    config
+        .games_index
+        .map(|format| GamesIndex::create(&config.games_index_path(), format, month))
+        .transpose()
+}
+
+/// Insert a `[RooktSource "<source>"]` header after the last header line of
+/// `pgn` (before the blank line that ends the headers), in the game's own
+/// line ending.
+fn with_source_tag(pgn: &str, source: &str) -> String {
+    let eol = if pgn.contains("\r\n") { "\r\n" } else { "\n" };
+    let headers_end: usize = pgn
+        .split_inclusive('\n')
+        .take_while(|line| line.starts_with('['))
+        .map(str::len)
+        .sum();
+    let (headers, rest) = pgn.split_at(headers_end);
+    let mut out = String::with_capacity(pgn.len() + source.len() + 20);
+    out.push_str(headers);
+    if !headers.is_empty() && !headers.ends_with('\n') {
+        out.push_str(eol);
+    }
+    out.push_str(&format!("[RooktSource \"{}\"]{}", source, eol));
+    out.push_str(rest);
+    out
+}
+
+/// Remove `{ ... }` comments from the movetext of `pgn` (headers are left
+/// alone), collapsing the spaces around them. Comments may span lines; a
+/// line left with nothing but comments is dropped. Blank lines and line
+/// endings are kept, so verbatim games stay verbatim apart from the cuts.
+fn strip_comments(pgn: &str) -> String {
+    let mut out = String::with_capacity(pgn.len());
+    let mut in_headers = true;
+    let mut in_comment = false;
+    for line in pgn.split_inclusive('\n') {
+        let content = line.trim_end_matches(['\r', '\n']);
+        let eol = &line[content.len()..];
+        if in_headers && content.starts_with('[') {
+            out.push_str(line);
+            continue;
+        }
+        in_headers = false;
+        if content.trim().is_empty() && !in_comment {
+            out.push_str(line);
+            continue;
+        }
+
+        let mut kept = String::with_capacity(content.len());
+        for c in content.chars() {
+            match c {
+                '{' if !in_comment => {
+                    in_comment = true;
+                    kept.push(' ');
+                }
+                '}' if in_comment => in_comment = false,
+                _ if in_comment => {}
+                _ => kept.push(c),
+            }
+        }
+        let mut words = kept.split_whitespace();
+        if let Some(first) = words.next() {
+            out.push_str(first);
+            for word in words {
+                out.push(' ');
+                out.push_str(word);
+            }
+            out.push_str(eol);
+        }
+    }
+    out
+}
+
+/// Count one more written game for `player`, returning false once `cap` is reached.
+fn take_slot(written: &mut HashMap<String, u32>, player: &str, cap: Option<u32>) -> bool {
+    let Some(cap) = cap else { return true };
+    match written.get_mut(player) {
+        Some(n) if *n >= cap => false,
+        Some(n) => {
+            *n += 1;
+            true
+        }
+        None => {
+            written.insert(player.to_string(), 1);
+            cap > 0
+        }
+    }
 }
 
 fn cleanup_empty_dirs(dir: &Path) -> Result<()> {
@@ -281,3 +1748,1557 @@ fn cleanup_empty_dirs(dir: &Path) -> Result<()> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::IndexFormat;
+
+    const BOT_PGN: &str = r#"[Event "Rated Blitz game"]
+[White "SomeBot"]
+[Black "PlayerB"]
+[WhiteTitle "BOT"]
+[TimeControl "300+0"]
+
+1. e4 { [%clk 0:05:00] } 1... e5 { [%clk 0:05:00] } 1-0
+"#;
+
+    fn test_config() -> Config {
+        let mut config = Config::default_blitz_300();
+        config.min_full_moves = 1;
+        config
+    }
+
+    struct NullSink;
+
+    impl EventSink for NullSink {
+        fn send(&self, _event: UiEvent) {}
+        fn check(&self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rookt_test_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// `test_config` for an end-to-end run of `urls` with its output, temp
+    /// files and index under `dir`, where one game qualifies a player.
+    fn dataset_config(dir: &Path, urls: Vec<String>) -> Config {
+        let mut config = test_config();
+        config.dataset_urls = urls;
+        config.output_dir = dir.to_path_buf();
+        config.temp_dir = dir.join("temp");
+        config.db_path = dir.join("index.db");
+        config.min_monthly_games = 1;
+        config.min_total_games = 1;
+        config
+    }
+
+    fn open(path: &Path) -> impl BufRead {
+        open_input_reader(path, &test_config(), Arc::new(NullSink)).unwrap()
+    }
+
+    fn count_games(path: &Path) -> usize {
+        let reader = open_input_reader(path, &test_config(), Arc::new(NullSink)).unwrap();
+        PgnParser::new(reader).infos().collect::<Result<Vec<_>>>().unwrap().len()
+    }
+
+    #[test]
+    fn test_input_decoders() {
+        let dir = temp_dir("input_decoders");
+        let pgn = format!("{}\n{}", BOT_PGN, BOT_PGN);
+
+        let plain = dir.join("2025-01.pgn");
+        fs::write(&plain, &pgn).unwrap();
+        assert_eq!(count_games(&plain), 2);
+
+        let zst = dir.join("2025-01.pgn.zst");
+        fs::write(&zst, zstd::encode_all(pgn.as_bytes(), 3).unwrap()).unwrap();
+        assert_eq!(count_games(&zst), 2);
+
+        let gz = dir.join("2025-01.pgn.gz");
+        let mut encoder = flate2::write::GzEncoder::new(File::create(&gz).unwrap(), Default::default());
+        std::io::Write::write_all(&mut encoder, pgn.as_bytes()).unwrap();
+        encoder.finish().unwrap();
+        assert_eq!(count_games(&gz), 2);
+
+        // Memory-mapped, and falling back to buffered for an empty file
+        let mut mapped = test_config();
+        mapped.use_mmap = true;
+        for path in [&plain, &zst, &gz] {
+            let reader = open_input_reader(path, &mapped, Arc::new(NullSink)).unwrap();
+            let games = PgnParser::new(reader).infos().collect::<Result<Vec<_>>>().unwrap();
+            assert_eq!(games.len(), 2, "{}", path.display());
+        }
+        let empty = dir.join("2025-02.pgn");
+        fs::write(&empty, b"").unwrap();
+        let sink = Arc::new(RecordSink::default());
+        let reader = open_input_reader(&empty, &mapped, sink.clone()).unwrap();
+        assert_eq!(PgnParser::new(reader).infos().collect::<Result<Vec<_>>>().unwrap().len(), 0);
+        assert!(matches!(&sink.0.lock().unwrap()[0], UiEvent::Log(msg) if msg.contains("reading it buffered")));
+
+        let other = dir.join("2025-01.pgn.bz2");
+        fs::write(&other, &pgn).unwrap();
+        assert!(open_input_reader(&other, &test_config(), Arc::new(NullSink)).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_zstd_window_log_max() {
+        let dir = temp_dir("zstd_window");
+        let zst = dir.join("2025-01.pgn.zst");
+        // A long-distance-matching frame declaring a 256 MB (2^28) window
+        let mut encoder = zstd::Encoder::new(File::create(&zst).unwrap(), 3).unwrap();
+        encoder.long_distance_matching(true).unwrap();
+        encoder.window_log(28).unwrap();
+        std::io::Write::write_all(&mut encoder, [BOT_PGN; 2].join("\n").as_bytes()).unwrap();
+        encoder.finish().unwrap();
+
+        let mut config = test_config();
+        config.zstd_window_log_max = 27; // zstd's built-in limit
+        let reader = open_input_reader(&zst, &config, Arc::new(NullSink)).unwrap();
+        assert!(PgnParser::new(reader).infos().any(|info| info.is_err()));
+
+        assert_eq!(count_games(&zst), 2, "the default accepts large windows");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_threaded_decode_matches_inline() {
+        let dir = temp_dir("threaded_decode");
+        let zst = dir.join("2025-01.pgn.zst");
+        // Several decode chunks' worth, so chunk boundaries split games
+        let pgn = [BOT_PGN; 20_000].join("\n");
+        fs::write(&zst, zstd::encode_all(pgn.as_bytes(), 3).unwrap()).unwrap();
+
+        let read_all = |decode_threads: usize| {
+            let mut config = test_config();
+            config.decode_threads = decode_threads;
+            let mut text = String::new();
+            open_input_reader(&zst, &config, Arc::new(NullSink))
+                .unwrap()
+                .read_to_string(&mut text)
+                .unwrap();
+            text
+        };
+        assert!(pgn.len() > 3 * DECODE_CHUNK_BYTES);
+        assert_eq!(read_all(2), pgn);
+        assert_eq!(read_all(2), read_all(1));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Decode + parse throughput of inline vs threaded decoding, each read
+    /// buffered and memory-mapped. Run with
+    /// `cargo test --release bench_threaded_decode -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_threaded_decode() {
+        let dir = temp_dir("bench_decode");
+        let zst = dir.join("2025-01.pgn.zst");
+        let pgn = [BOT_PGN; 1_000_000].join("\n");
+        fs::write(&zst, zstd::encode_all(pgn.as_bytes(), 3).unwrap()).unwrap();
+
+        for (decode_threads, use_mmap) in [(1, false), (2, false), (1, true), (2, true)] {
+            let mut config = test_config();
+            config.decode_threads = decode_threads;
+            config.use_mmap = use_mmap;
+            let started = Instant::now();
+            let reader = open_input_reader(&zst, &config, Arc::new(NullSink)).unwrap();
+            let games = PgnParser::new(reader).infos().count();
+            println!(
+                "decode_threads={} use_mmap={}: {} games in {:.2?} ({:.0} MB/s decoded)",
+                decode_threads,
+                use_mmap,
+                games,
+                started.elapsed(),
+                pgn.len() as f64 / 1_048_576.0 / started.elapsed().as_secs_f64()
+            );
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_max_monthly_games_caps_extraction() {
+        let dir = temp_dir("max_monthly_games");
+        let input = dir.join("2025-01.pgn");
+        fs::write(&input, [BOT_PGN; 3].join("\n")).unwrap();
+
+        let mut config = test_config();
+        config.max_monthly_games = Some(2);
+        let qualifying: HashSet<String> = ["PlayerB".to_string()].into();
+        let mut writer = PlayerWriter::new(dir.join("players"), usize::MAX);
+
+        let extraction = pass2_extract(open(&input), &config, &qualifying, None, &mut writer, None, Arc::new(NullSink)).unwrap();
+        assert_eq!(extraction.extracted, 2);
+        assert_eq!(extraction.written["PlayerB"], 2);
+        assert_eq!(extraction.seen, 3, "seen counts games before the cap");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_output_limit_stops_extraction() {
+        let dir = temp_dir("output_limit");
+        let input = dir.join("2025-01.pgn");
+        fs::write(&input, [BOT_PGN; 3].join("\n")).unwrap();
+
+        let config = test_config();
+        let qualifying: HashSet<String> = ["PlayerB".to_string()].into();
+        // Flush after every game, so the 1-byte budget is spent by the first
+        let mut writer = PlayerWriter::new(dir.join("players"), 0).with_output_limit(Some(1));
+
+        let extraction = pass2_extract(open(&input), &config, &qualifying, None, &mut writer, None, Arc::new(NullSink)).unwrap();
+        assert!(extraction.truncated);
+        assert_eq!(extraction.extracted, 1);
+        assert!(writer.bytes_written() > 0);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn fixture_game(event: &str, white: &str, black: &str) -> String {
+        format!(
+            "[Event \"{}\"]\n[White \"{}\"]\n[Black \"{}\"]\n[TimeControl \"300+0\"]\n\n\
+             1. e4 {{ [%clk 0:05:00] }} 1... e5 {{ [%clk 0:05:00] }} 1-0\n\n",
+            event, white, black
+        )
+    }
+
+    #[test]
+    fn test_group_by_team() {
+        let dir = temp_dir("group_by_team");
+        let input = dir.join("2025-01.pgn");
+        let team_game = |white: &str, black: &str, teams: &str| {
+            fixture_game("Rated Blitz game", white, black).replacen("[TimeControl", &format!("{}[TimeControl", teams), 1)
+        };
+        let pgn = [
+            team_game("Alice", "Bob", "[WhiteTeam \"Rooks\"]\n[BlackTeam \"Pawns\"]\n"),
+            team_game("Carol", "Alice", "[WhiteTeam \"Pawns\"]\n[BlackTeam \"Rooks\"]\n"),
+            // Within one team: counted and written once
+            team_game("Alice", "Dave", "[WhiteTeam \"Rooks\"]\n[BlackTeam \"Rooks\"]\n"),
+            // No team headers: skipped
+            fixture_game("Rated Blitz game", "Alice", "Bob"),
+        ];
+        fs::write(&input, pgn.concat()).unwrap();
+
+        let mut config = test_config();
+        config.group_by = GroupBy::Team;
+        let (counts, _, _, _) = pass1_count(open(&input), &config, Arc::new(NullSink)).unwrap();
+        let mut games: Vec<(&str, u32)> = counts.iter().map(|(name, t)| (name.as_str(), t.games)).collect();
+        games.sort();
+        assert_eq!(games, [("Pawns", 2), ("Rooks", 3)]);
+
+        let qualifying: HashSet<String> = ["Rooks".to_string()].into();
+        let mut writer = PlayerWriter::new(dir.join("players"), usize::MAX);
+        let extraction = pass2_extract(open(&input), &config, &qualifying, None, &mut writer, None, Arc::new(NullSink)).unwrap();
+        writer.flush_all().unwrap();
+        assert_eq!((extraction.seen, extraction.written["Rooks"]), (3, 3));
+        assert!(writer.player_path("Rooks").exists());
+        assert!(!writer.player_path("Alice").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_split_pgn_writes_player_files_without_an_index() {
+        let dir = temp_dir("split_pgn");
+        let input = dir.join("lichess_db_standard_rated_2025-01.pgn");
+        let pgn = [
+            fixture_game("Rated Blitz game", "Alice", "Bob"),
+            fixture_game("Rated Blitz game", "Carol", "Alice"),
+            fixture_game("Rated Rapid game", "Alice", "Bob"),
+        ];
+        fs::write(&input, pgn.concat()).unwrap();
+        let mut config = test_config();
+        config.output_dir = dir.join("out");
+        config.min_monthly_games = 2;
+
+        let summary = split_pgn(&input, &config, Arc::new(NullSink)).unwrap();
+        assert_eq!((summary.players, summary.games_written), (1, 2), "only Alice plays 2 blitz games");
+        let writer = PlayerWriter::new(config.players_dir(), 0);
+        let file = File::open(writer.player_path("Alice")).unwrap();
+        assert_eq!(summary.bytes_written, file.metadata().unwrap().len());
+        assert!(!writer.player_path("Bob").exists());
+        assert!(!config.db_path.exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_bench_pass1_measures_a_local_file() {
+        let dir = temp_dir("bench_pass1");
+        let input = dir.join("lichess_db_standard_rated_2025-01.pgn.zst");
+        let pgn = [
+            fixture_game("Rated Blitz game", "Alice", "Bob"),
+            fixture_game("Rated Blitz game", "Carol", "Alice"),
+            fixture_game("Rated Rapid game", "Alice", "Bob"),
+        ]
+        .concat();
+        fs::write(&input, zstd::encode_all(pgn.as_bytes(), 3).unwrap()).unwrap();
+        let config = test_config();
+
+        let report = bench_pass1(input.to_str().unwrap(), &config, Arc::new(NullSink)).unwrap();
+        assert_eq!((report.games_scanned, report.valid_games), (3, 2));
+        assert_eq!(report.decoded_bytes, pgn.len() as u64);
+        assert_eq!(report.compressed_bytes, fs::metadata(&input).unwrap().len());
+        assert!(report.games_per_sec() > 0.0 && report.decoded_mb_per_sec() > 0.0);
+        assert!(bench_pass1(dir.join("missing.pgn").to_str().unwrap(), &config, Arc::new(NullSink)).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_require_both_players() {
+        let dir = temp_dir("require_both");
+        let input = dir.join("2025-01.pgn");
+        let pgn = [
+            fixture_game("Rated Blitz game", "Alice", "Bob"),
+            fixture_game("Rated Blitz game", "Carol", "Alice"),
+        ];
+        fs::write(&input, pgn.concat()).unwrap();
+        let qualifying: HashSet<String> = ["Alice".to_string(), "Bob".to_string()].into();
+
+        let extract = |require_both: bool| {
+            let mut config = test_config();
+            config.require_both_players = require_both;
+            let mut writer = PlayerWriter::new(dir.join(format!("players_{}", require_both)), usize::MAX);
+            pass2_extract(open(&input), &config, &qualifying, None, &mut writer, None, Arc::new(NullSink)).unwrap()
+        };
+
+        let either = extract(false);
+        assert_eq!((either.written["Alice"], either.written["Bob"]), (2, 1));
+        let both = extract(true);
+        assert_eq!((both.written["Alice"], both.written["Bob"]), (1, 1), "Carol does not qualify");
+        assert_eq!(both.seen, either.seen, "skipped games are still seen");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_round_trip_player_files_match_index() {
+        let dir = temp_dir("round_trip");
+        let mut config = dataset_config(&dir, vec!["https://example.org/lichess_db_standard_rated_2025-01.pgn".into()]);
+        config.min_monthly_games = 2;
+        config.offline = true;
+
+        // Alice 3, Bob 2, Carol 2 valid games; Dave plays once (does not
+        // qualify) and the rapid game is filtered out
+        let pgn = [
+            fixture_game("Rated Blitz game", "Alice", "Bob"),
+            fixture_game("Rated Blitz game", "Carol", "Alice"),
+            fixture_game("Rated Rapid game", "Alice", "Carol"),
+            fixture_game("Rated Blitz game", "Bob", "Carol"),
+            fixture_game("Rated Blitz game", "Alice", "Dave"),
+        ]
+        .concat();
+        fs::create_dir_all(&config.temp_dir).unwrap();
+        fs::write(input_path(&config, &config.dataset_urls[0]), pgn).unwrap();
+
+        let sink = Arc::new(RecordSink::default());
+        let stats = run_with_sink(&config, sink.clone()).unwrap();
+        assert_eq!(stats.datasets_processed, 1);
+        let events = sink.0.lock().unwrap();
+        assert!(events.iter().any(|e| matches!(
+            e,
+            UiEvent::DatasetComplete { qualifying_players: 3, games_extracted: 7, .. }
+        )));
+        let position = |f: fn(&UiEvent) -> bool| events.iter().position(f).unwrap();
+        assert!(
+            position(|e| matches!(e, UiEvent::Preparing)) < position(|e| matches!(e, UiEvent::Pass1Started)),
+            "the dashboard shows the file being opened before pass 1"
+        );
+
+        let db = Database::open(&config.db_path).unwrap();
+        let players = db.find_players_by_prefix("", 100).unwrap();
+        let names: Vec<&str> = players.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["Alice", "Bob", "Carol"]);
+
+        let writer = PlayerWriter::new(config.players_dir(), 0);
+        for (name, total) in &players {
+            let file = File::open(writer.player_path(name)).unwrap();
+            let decoder = zstd::stream::read::Decoder::new(file).unwrap();
+            let mut parser = PgnParser::new(BufReader::new(decoder));
+            let mut games = 0;
+            while let Some(game) = parser.next_game().unwrap() {
+                assert!(game.info.white == *name || game.info.black == *name);
+                assert_eq!(game.info.event, "Rated Blitz game");
+                games += 1;
+            }
+            assert_eq!(games, *total, "{} file vs index", name);
+        }
+        assert_eq!(stats.games_saved, 7);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_tag_source_header() {
+        let dir = temp_dir("tag_source");
+        let mut config = dataset_config(&dir, vec!["https://example.org/lichess_db_standard_rated_2025-01.pgn".into()]);
+        config.offline = true;
+        config.tag_source = true;
+        fs::create_dir_all(&config.temp_dir).unwrap();
+        fs::write(
+            input_path(&config, &config.dataset_urls[0]),
+            fixture_game("Rated Blitz game", "Alice", "Bob"),
+        )
+        .unwrap();
+
+        run_with_sink(&config, Arc::new(NullSink)).unwrap();
+
+        let writer = PlayerWriter::new(config.players_dir(), 0);
+        let file = File::open(writer.player_path("Alice")).unwrap();
+        let mut parser = PgnParser::new(BufReader::new(zstd::stream::read::Decoder::new(file).unwrap()));
+        let game = parser.next_game().unwrap().unwrap();
+        assert!(game.raw_pgn.contains("]\n[RooktSource \"lichess_db_standard_rated_2025-01\"]\n\n1."));
+        assert_eq!((game.info.white.as_str(), game.info.black.as_str()), ("Alice", "Bob"));
+        assert_eq!(game.info.event, "Rated Blitz game");
+        assert!(parser.next_game().unwrap().is_none());
+
+        // Verbatim games keep their own line endings
+        let tagged = with_source_tag("[Event \"x\"]\r\n\r\n1. e4 1-0\r\n", "src");
+        assert_eq!(tagged, "[Event \"x\"]\r\n[RooktSource \"src\"]\r\n\r\n1. e4 1-0\r\n");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_strip_comments() {
+        let dir = temp_dir("strip_comments");
+        let mut config = dataset_config(&dir, vec!["https://example.org/lichess_db_standard_rated_2025-01.pgn".into()]);
+        config.offline = true;
+        config.strip_comments = true;
+        fs::create_dir_all(&config.temp_dir).unwrap();
+        let source = "[Event \"Rated Blitz game\"]\n[White \"Alice\"]\n[Black \"Bob\"]\n[TimeControl \"300+0\"]\n\n\
+                      1. e4 { [%eval 0.2] [%clk 0:05:00] } 1... e5 { [%clk 0:05:00] } 2. Nf3 { a comment\n\
+                      over two lines } 2... Nc6 { [%clk 0:04:58] } 1/2-1/2\n\n";
+        fs::write(input_path(&config, &config.dataset_urls[0]), source).unwrap();
+
+        run_with_sink(&config, Arc::new(NullSink)).unwrap();
+
+        let writer = PlayerWriter::new(config.players_dir(), 0);
+        let file = File::open(writer.player_path("Alice")).unwrap();
+        let mut written = String::new();
+        zstd::stream::read::Decoder::new(file).unwrap().read_to_string(&mut written).unwrap();
+        let headers = source.split("\n\n").next().unwrap();
+        assert_eq!(written, format!("{}\n\n1. e4 1... e5 2. Nf3\n2... Nc6 1/2-1/2\n\n", headers));
+
+        // The stripped form parses to the same game, minus the clocks
+        let original = PgnParser::new(Cursor::new(source)).next_game().unwrap().unwrap();
+        let stripped = PgnParser::new(Cursor::new(written)).next_game().unwrap().unwrap();
+        let movetext = |pgn: &str| parser::san_moves(pgn.split_once("\n\n").unwrap().1);
+        assert_eq!(stripped.info.white, original.info.white);
+        assert_eq!(movetext(&stripped.raw_pgn), ["e4", "e5", "Nf3", "Nc6"]);
+        assert_eq!(movetext(&stripped.raw_pgn), movetext(&original.raw_pgn));
+        assert_eq!((original.info.half_move_count, stripped.info.half_move_count), (3, 0));
+
+        // Verbatim line endings and blank lines survive
+        assert_eq!(
+            strip_comments("[Event \"x\"]\r\n\r\n1. e4 {c} e5 { [%clk 0:05:00] }\r\n{ only a comment }\r\n1-0\r\n\r\n"),
+            "[Event \"x\"]\r\n\r\n1. e4 e5\r\n1-0\r\n\r\n"
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_temp_dir_outside_output_dir() {
+        let dir = temp_dir("separate_temp");
+        let url = "https://example.org/lichess_db_standard_rated_2025-01.pgn".to_string();
+        let mut config = dataset_config(&dir.join("out"), vec![url]);
+        config.temp_dir = dir.join("scratch");
+        config.offline = true;
+        fs::create_dir_all(&config.temp_dir).unwrap();
+        let input = input_path(&config, &config.dataset_urls[0]);
+        fs::write(&input, fixture_game("Rated Blitz game", "Alice", "Bob")).unwrap();
+
+        let stats = run_with_sink(&config, Arc::new(NullSink)).unwrap();
+        assert_eq!((stats.datasets_processed, stats.games_saved), (1, 2));
+        assert!(!input.exists(), "input is dropped from the temp dir once processed");
+        assert!(!config.output_dir.join("temp").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// In-memory datasets keyed by URL; records how it was used.
+    #[derive(Default)]
+    struct MemorySource {
+        datasets: HashMap<String, Vec<u8>>,
+        opens: std::cell::RefCell<Vec<String>>,
+        discarded: Vec<String>,
+        /// Open number -> byte offset where that reader fails with a timeout.
+        fail_at: HashMap<usize, usize>,
+    }
+
+    /// A read that always times out, like a stalled disk.
+    struct TimedOut;
+
+    impl Read for TimedOut {
+        fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+            Err(ErrorKind::TimedOut.into())
+        }
+    }
+
+    impl InputSource for MemorySource {
+        fn prepare(&mut self, _: usize, url: &str, _: &Database, _: &Arc<dyn EventSink>) -> Result<Fetch> {
+            Ok(match self.datasets.contains_key(url) {
+                true => Fetch::Ready { size_bytes: self.datasets[url].len() as u64 },
+                false => Fetch::Unavailable("not in memory".into()),
+            })
+        }
+
+        fn open(&self, url: &str, _: Arc<dyn EventSink>) -> Result<Box<dyn BufRead>> {
+            let mut opens = self.opens.borrow_mut();
+            let data = self.datasets[url].clone();
+            let fail_at = self.fail_at.get(&opens.len()).copied();
+            opens.push(url.to_string());
+            Ok(match fail_at {
+                Some(offset) => Box::new(BufReader::new(Cursor::new(data[..offset].to_vec()).chain(TimedOut))),
+                None => Box::new(Cursor::new(data)),
+            })
+        }
+
+        fn discard(&mut self, url: &str) -> Result<()> {
+            self.discarded.push(url.to_string());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_pipeline_runs_from_memory_source() {
+        let dir = temp_dir("memory_source");
+        let urls: Vec<String> = (1..=3)
+            .map(|m| format!("https://example.org/lichess_db_standard_rated_2025-{:02}.pgn.zst", m))
+            .collect();
+        let mut config = dataset_config(&dir, urls.clone());
+        config.min_total_games = 3;
+
+        // Alice plays in both months, Bob only in the first; March is missing
+        let mut source = MemorySource::default();
+        let january = [
+            fixture_game("Rated Blitz game", "Alice", "Bob"),
+            fixture_game("Rated Blitz game", "Bob", "Alice"),
+        ];
+        source.datasets.insert(urls[0].clone(), january.concat().into_bytes());
+        source.datasets.insert(urls[1].clone(), fixture_game("Rated Blitz game", "Alice", "Carol").into_bytes());
+
+        let stats = run_with_source(&config, &mut source, Arc::new(NullSink)).unwrap();
+        assert_eq!(stats.datasets_processed, 2);
+        assert_eq!(stats.games_saved, 6);
+        assert_eq!(*source.opens.borrow(), [&*urls[0], &urls[0], &urls[1], &urls[1]], "one open per pass");
+        assert_eq!(source.discarded, &urls[..2]);
+
+        let db = Database::open(&config.db_path).unwrap();
+        assert_eq!(db.find_players_by_prefix("", 10).unwrap(), [("Alice".to_string(), 3)]);
+        assert!(!db.is_dataset_processed(&urls[2]).unwrap());
+        assert_eq!(db.dataset_size(&urls[1]).unwrap(), Some(source.datasets[&urls[1]].len() as u64));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_flat_and_sharded_players_dir() {
+        for shard in [true, false] {
+            let dir = temp_dir(&format!("shard_output_{}", shard));
+            let url = "https://example.org/lichess_db_standard_rated_2025-01.pgn.zst".to_string();
+            let mut config = dataset_config(&dir, vec![url.clone()]);
+            config.min_total_games = 2;
+            config.shard_output = shard;
+            fs::create_dir_all(dir.join("players/notes")).unwrap();
+
+            // Bob plays once and is pruned
+            let mut source = MemorySource::default();
+            let games = [
+                fixture_game("Rated Blitz game", "Alice", "Bob"),
+                fixture_game("Rated Blitz game", "Carol", "Alice"),
+            ];
+            source.datasets.insert(url, games.concat().into_bytes());
+            run_with_source(&config, &mut source, Arc::new(NullSink)).unwrap();
+
+            let players = config.players_dir();
+            let (alice, carol) = match shard {
+                true => (players.join("al/Alice.pgn.zst"), players.join("ca/Carol.pgn.zst")),
+                false => (players.join("Alice.pgn.zst"), players.join("Carol.pgn.zst")),
+            };
+            assert_eq!(count_games(&alice), 2, "shard={}", shard);
+            assert!(!carol.exists() && !players.join("bo").exists() && !players.join("Bob.pgn.zst").exists());
+            // Pruning empties shard directories, but leaves a flat directory's
+            // subdirectories alone
+            let names: Vec<String> = fs::read_dir(&players)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+                .collect();
+            match shard {
+                true => assert_eq!(names, ["al"]),
+                false => assert_eq!(names.len(), 2, "{:?}", names),
+            }
+
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_read_errors_restart_the_pass() {
+        let dir = temp_dir("read_retries");
+        let url = "https://example.org/lichess_db_standard_rated_2025-01.pgn.zst".to_string();
+        let mut config = dataset_config(&dir, vec![url.clone()]);
+        config.write_buffer_max_bytes = 1;
+
+        // Each read fails after the first game, once in each pass
+        let first = fixture_game("Rated Blitz game", "Alice", "Bob");
+        let pgn = [first.clone(), fixture_game("Rated Blitz game", "Bob", "Alice")].concat();
+        let mut source = MemorySource::default();
+        source.datasets.insert(url.clone(), pgn.into_bytes());
+        source.fail_at = HashMap::from([(0, first.len() + 10), (2, first.len() + 10)]);
+        let sink = Arc::new(RecordSink::default());
+        let stats = run_with_source(&config, &mut source, sink.clone()).unwrap();
+
+        assert_eq!(source.opens.borrow().len(), 4, "each pass ran twice");
+        assert_eq!(stats.games_saved, 4);
+        let writer = PlayerWriter::new(config.players_dir(), 0);
+        assert_eq!(count_games(&writer.player_path("Alice")), 2, "the failed pass 2 was undone");
+        assert_eq!(count_games(&writer.player_path("Bob")), 2);
+        let retries = sink.0.lock().unwrap().iter().filter(|e| matches!(e, UiEvent::Log(msg) if msg.contains("restarting it (1/2)"))).count();
+        assert_eq!(retries, 2);
+
+        // Without retries the dataset fails
+        fs::remove_dir_all(&dir).unwrap();
+        config.decode_retries = 0;
+        source.opens.borrow_mut().clear();
+        let stats = run_with_source(&config, &mut source, Arc::new(NullSink)).unwrap();
+        assert_eq!(stats.failed_datasets.len(), 1);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_failed_pass2_is_undone_before_later_months() {
+        let dir = temp_dir("failed_pass2");
+        let urls: Vec<String> = (1..=2)
+            .map(|m| format!("https://example.org/lichess_db_standard_rated_2025-{:02}.pgn.zst", m))
+            .collect();
+        let mut config = dataset_config(&dir, urls.clone());
+        config.write_buffer_max_bytes = 1;
+        config.decode_retries = 0;
+        config.games_index = Some(IndexFormat::Csv);
+
+        // January's pass 2 fails after its first game was flushed; February
+        // then appends to the same files
+        let first = fixture_game("Rated Blitz game", "Alice", "Bob");
+        let mut source = MemorySource::default();
+        let january = [first.clone(), fixture_game("Rated Blitz game", "Bob", "Alice")].concat();
+        source.datasets.insert(urls[0].clone(), january.into_bytes());
+        source.datasets.insert(urls[1].clone(), fixture_game("Rated Blitz game", "Alice", "Bob").into_bytes());
+        source.fail_at = HashMap::from([(1, first.len() + 10)]);
+        let stats = run_with_source(&config, &mut source, Arc::new(NullSink)).unwrap();
+        assert_eq!(stats.failed_datasets.len(), 1);
+        let writer = PlayerWriter::new(config.players_dir(), 0);
+        assert_eq!(count_games(&writer.player_path("Alice")), 1, "only February's game is left");
+        let db = Database::open(&config.db_path).unwrap();
+        assert!(db.pass2_checkpoint(&urls[0]).unwrap().is_empty());
+        drop(db);
+
+        // The retry starts January over without touching February's games
+        source.fail_at.clear();
+        let stats = run_with_source(&config, &mut source, Arc::new(NullSink)).unwrap();
+        assert_eq!(stats.datasets_processed, 1);
+        assert_eq!(count_games(&writer.player_path("Alice")), 3);
+        assert_eq!(count_games(&writer.player_path("Bob")), 3);
+        let index = fs::read_to_string(config.games_index_path()).unwrap();
+        assert_eq!(index.lines().count(), 1 + 6, "header and one row per game entry");
+        assert_eq!(index.lines().filter(|line| line.contains(",2025-02,")).count(), 2);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_interrupted_pass2_resumes_without_duplicates() {
+        let url = "https://example.org/lichess_db_standard_rated_2025-01.pgn.zst".to_string();
+        let games = [
+            fixture_game("Rated Blitz game", "Alice", "Bob"),
+            fixture_game("Rated Blitz game", "Bob", "Alice"),
+            fixture_game("Rated Blitz game", "Alice", "Carol"),
+        ];
+        let pgn = games.concat();
+        let setup = |name: &str| {
+            let dir = temp_dir(name);
+            let mut config = dataset_config(&dir, vec![url.clone()]);
+            config.write_buffer_max_bytes = 1; // a flush (and checkpoint) per game
+            config.decode_retries = 0;
+            config.games_index = Some(IndexFormat::Csv);
+            let mut source = MemorySource::default();
+            source.datasets.insert(url.clone(), pgn.clone().into_bytes());
+            (dir, config, source)
+        };
+
+        let (clean_dir, config, mut source) = setup("resume_clean");
+        run_with_source(&config, &mut source, Arc::new(NullSink)).unwrap();
+        let clean = PlayerWriter::new(config.players_dir(), 0);
+
+        // Pass 2 dies partway through the second game (with --fail-fast the
+        // run stops there, like a crash, instead of undoing the month)
+        let (dir, mut config, mut source) = setup("resume_interrupted");
+        source.fail_at = HashMap::from([(1, games[0].len() + 20)]);
+        config.fail_fast = true;
+        assert!(run_with_source(&config, &mut source, Arc::new(NullSink)).is_err());
+        config.fail_fast = false;
+        let writer = PlayerWriter::new(config.players_dir(), 0);
+        assert_eq!(count_games(&writer.player_path("Alice")), 1);
+        {
+            let db = Database::open(&config.db_path).unwrap();
+            assert_eq!(db.pass2_checkpoint(&url).unwrap().iter().map(|f| f.games).sum::<u32>(), 2);
+        }
+        // ...and a torn frame after the checkpoint, as if killed mid-write
+        let mut file = fs::OpenOptions::new().append(true).open(writer.player_path("Bob")).unwrap();
+        file.write_all(&[0x28, 0xb5, 0x2f]).unwrap();
+        drop(file);
+        // ...and rows of an append the index never recorded
+        fs::write(config.games_index_path(), "player,month\nAlice,2025-01\n").unwrap();
+
+        source.fail_at.clear();
+        let sink = Arc::new(RecordSink::default());
+        let stats = run_with_source(&config, &mut source, sink.clone()).unwrap();
+        assert!(stats.failed_datasets.is_empty());
+        assert_eq!(stats.games_saved, 6);
+        let resumed = sink.0.lock().unwrap().iter().any(|e| matches!(e, UiEvent::Log(msg) if msg.contains("2 games written by an interrupted run")));
+        assert!(resumed);
+        for player in ["Alice", "Bob", "Carol"] {
+            assert_eq!(
+                fs::read(writer.player_path(player)).unwrap(),
+                fs::read(clean.player_path(player)).unwrap(),
+                "{}'s file matches an uninterrupted run",
+                player
+            );
+        }
+        let index = fs::read_to_string(config.games_index_path()).unwrap();
+        assert_eq!(index, fs::read_to_string(clean_dir.join("games.index")).unwrap());
+        assert_eq!(index.lines().count(), 1 + 6);
+        let db = Database::open(&config.db_path).unwrap();
+        assert_eq!(db.get_total_games().unwrap(), 6);
+        assert!(db.pass2_checkpoint(&url).unwrap().is_empty());
+        drop(db);
+
+        fs::remove_dir_all(clean_dir).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_games_index_matches_written_games() {
+        let dir = temp_dir("games_index");
+        let url = |m: u32| format!("https://example.org/lichess_db_standard_rated_2025-{:02}.pgn.zst", m);
+        let mut config = dataset_config(&dir, vec![url(1), url(2)]);
+        config.max_monthly_games = Some(2);
+        config.games_index = Some(IndexFormat::Ndjson);
+
+        let game = |white, black, site: &str| {
+            fixture_game("Rated Blitz game", white, black)
+                .replacen("[TimeControl", &format!("[Site \"{}\"]\n[WhiteElo \"1500\"]\n[TimeControl", site), 1)
+        };
+        let mut source = MemorySource::default();
+        let january: Vec<String> = (0..4).map(|i| game("Alice", "Bob", &format!("s{}", i))).collect();
+        source.datasets.insert(url(1), january.concat().into_bytes());
+        source.datasets.insert(url(2), game("Carol", "Alice", "s9").into_bytes());
+        let stats = run_with_source(&config, &mut source, Arc::new(NullSink)).unwrap();
+
+        let index = fs::read_to_string(config.games_index_path()).unwrap();
+        let rows: Vec<serde_json::Value> = index.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(rows.len() as u64, stats.games_saved, "one row per game entry written");
+        let writer = PlayerWriter::new(config.players_dir(), 0);
+        for player in ["Alice", "Bob", "Carol"] {
+            let listed = rows.iter().filter(|row| row["player"] == player).count();
+            assert_eq!(listed, count_games(&writer.player_path(player)), "{}", player);
+        }
+        let carol = rows.iter().find(|row| row["player"] == "Carol").unwrap();
+        assert_eq!((carol["month"].as_str(), carol["site"].as_str()), (Some("2025-02"), Some("s9")));
+        assert_eq!((carol["white_elo"].as_u64(), carol["black_elo"].is_null()), (Some(1500), true));
+        assert!(!dir.join("games.index.part").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_dedup_games_by_site() {
+        let dir = temp_dir("dedup_games");
+        let url = |m: u32| format!("https://example.org/lichess_db_standard_rated_2025-{:02}.pgn.zst", m);
+        let mut config = dataset_config(&dir, vec![url(1), url(2)]);
+        config.dedup_games = true;
+        let game = |site: &str| {
+            fixture_game("Rated Blitz game", "Alice", "Bob")
+                .replacen("[White", &format!("[Site \"https://lichess.org/{}\"]\n[White", site), 1)
+        };
+
+        // The same game twice in January and again in February (overlapping exports)
+        let mut source = MemorySource::default();
+        source.datasets.insert(url(1), [game("aaaa"), game("aaaa"), game("bbbb")].concat().into_bytes());
+        source.datasets.insert(url(2), [game("bbbb"), game("cccc")].concat().into_bytes());
+        run_with_source(&config, &mut source, Arc::new(NullSink)).unwrap();
+
+        let writer = PlayerWriter::new(config.players_dir(), 0);
+        assert_eq!(count_games(&writer.player_path("Alice")), 3);
+        assert_eq!(count_games(&writer.player_path("Bob")), 3);
+        let db = Database::open(&config.db_path).unwrap();
+        assert_eq!(db.get_total_games().unwrap(), 6, "the index counts what was written");
+        drop(db);
+
+        // The sites are kept in the index, so a later run skips them too
+        config.dataset_urls.push(url(3));
+        source.datasets.insert(url(3), [game("aaaa"), game("dddd")].concat().into_bytes());
+        run_with_source(&config, &mut source, Arc::new(NullSink)).unwrap();
+        assert_eq!(count_games(&writer.player_path("Alice")), 4);
+
+        // Without dedup every copy is written
+        fs::remove_dir_all(&dir).unwrap();
+        config.dedup_games = false;
+        run_with_source(&config, &mut source, Arc::new(NullSink)).unwrap();
+        assert_eq!(count_games(&writer.player_path("Alice")), 7);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_dedup_keeps_sites_of_capped_and_unsampled_games() {
+        let game = |site: &str| {
+            fixture_game("Rated Blitz game", "Alice", "Bob")
+                .replacen("[White", &format!("[Site \"https://lichess.org/{}\"]\n[White", site), 1)
+        };
+        let url = |m: u32| format!("https://example.org/lichess_db_standard_rated_2025-{:02}.pgn.zst", m);
+        let both = [game("aaaa"), game("bbbb"), game("cccc")].concat();
+        for sampled in [false, true] {
+            let dir = temp_dir(&format!("dedup_capped_{}", sampled));
+            let mut config = dataset_config(&dir, vec![url(1), url(2)]);
+            config.dedup_games = true;
+            match sampled {
+                true => config.sample_per_player = Some(1),
+                false => config.max_monthly_games = Some(1),
+            }
+
+            // Both months hold the same three games: each writes one, and
+            // February's is one January dropped, not a repeat
+            let mut source = MemorySource::default();
+            source.datasets.insert(url(1), both.clone().into_bytes());
+            source.datasets.insert(url(2), both.clone().into_bytes());
+            run_with_source(&config, &mut source, Arc::new(NullSink)).unwrap();
+
+            let writer = PlayerWriter::new(config.players_dir(), 0);
+            let reader = open_input_reader(&writer.player_path("Alice"), &test_config(), Arc::new(NullSink)).unwrap();
+            let sites: Vec<String> = PgnParser::new(reader).infos().map(|info| info.unwrap().site).collect();
+            assert_eq!(sites.len(), 2, "sampled={}", sampled);
+            assert_ne!(sites[0], sites[1], "sampled={}", sampled);
+
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_sample_datasets_is_reproducible() {
+        let mut config = test_config();
+        config.sample_datasets = Some(3);
+        let months = |config: &Config| config.datasets().iter().map(|url| extract_month(url)).collect::<Vec<_>>();
+
+        let picked = months(&config);
+        assert_eq!(picked.len(), 3);
+        assert!(picked.is_sorted(), "kept in date order: {:?}", picked);
+        assert_eq!(months(&config), picked, "same seed, same months");
+        config.sample_seed = 1;
+        assert_ne!(months(&config), picked);
+
+        config.max_datasets = Some(2);
+        assert_eq!(months(&config).len(), 2, "max datasets applies to the sample");
+        config.sample_datasets = Some(50);
+        assert_eq!(months(&config).len(), 2);
+        config.max_datasets = None;
+        assert_eq!(config.datasets(), config.dataset_urls, "a sample of all is everything");
+    }
+
+    #[test]
+    fn test_max_datasets_truncates_the_run() {
+        let dir = temp_dir("max_datasets");
+        let urls: Vec<String> = (1..=3)
+            .map(|m| format!("https://example.org/lichess_db_standard_rated_2025-{:02}.pgn.zst", m))
+            .collect();
+        let mut config = dataset_config(&dir, urls.clone());
+        config.max_datasets = Some(2);
+
+        let mut source = MemorySource::default();
+        for url in &urls {
+            source.datasets.insert(url.clone(), fixture_game("Rated Blitz game", "Alice", "Bob").into_bytes());
+        }
+        let sink = Arc::new(RecordSink::default());
+        let stats = run_with_source(&config, &mut source, sink.clone()).unwrap();
+        assert_eq!(stats.datasets_processed, 2);
+        let events = sink.0.lock().unwrap();
+        let started: Vec<_> = events
+            .iter()
+            .filter_map(|e| match e {
+                UiEvent::DatasetStarted { index, total, .. } => Some((*index, *total)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(started, [(0, 2), (1, 2)]);
+        assert!(!source.opens.borrow().contains(&urls[2]));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_two_phase_skips_players_below_the_total() {
+        // Alice plays in both months; Bob and Carol in one each
+        let months = [("Alice", "Bob"), ("Carol", "Alice")];
+        let run = |two_phase: bool| {
+            let dir = temp_dir(&format!("two_phase_{}", two_phase));
+            let mut config = dataset_config(&dir, (1..=2)
+                .map(|m| format!("https://example.org/lichess_db_standard_rated_2025-{:02}.pgn.zst", m))
+                .collect());
+            config.min_total_games = 2;
+            config.two_phase = two_phase;
+
+            let mut source = MemorySource::default();
+            for (url, (white, black)) in config.dataset_urls.iter().zip(months) {
+                source.datasets.insert(url.clone(), fixture_game("Rated Blitz game", white, black).into_bytes());
+            }
+            let sink = Arc::new(RecordSink::default());
+            let stats = run_with_source(&config, &mut source, sink.clone()).unwrap();
+            let players = Database::open(&config.db_path).unwrap().find_players_by_prefix("", 10).unwrap();
+            assert_eq!(players, [("Alice".to_string(), 2)]);
+            assert_eq!(source.discarded, config.dataset_urls);
+            fs::remove_dir_all(dir).unwrap();
+            (stats, sink.0.lock().unwrap().clone())
+        };
+
+        let (stats, _) = run(false);
+        assert_eq!((stats.games_saved, stats.players_pruned), (4, 2));
+
+        let (stats, events) = run(true);
+        assert_eq!((stats.datasets_processed, stats.games_saved, stats.players_pruned), (2, 2, 0));
+        let counted = events.iter().position(|e| matches!(e, UiEvent::CountingComplete { .. })).unwrap();
+        assert!(matches!(events[counted], UiEvent::CountingComplete { datasets: 2, qualifying_players: 1 }));
+        assert!(events[..counted].iter().all(|e| !matches!(e, UiEvent::Pass2Started)));
+        assert_eq!(events[counted..].iter().filter(|e| matches!(e, UiEvent::Pass1Started)).count(), 0);
+    }
+
+    #[test]
+    fn test_qualifying_log_appends_each_dataset() {
+        let dir = temp_dir("qualifying_log");
+        let mut config = dataset_config(&dir, (1..=2)
+            .map(|m| format!("https://example.org/lichess_db_standard_rated_2025-{:02}.pgn.zst", m))
+            .collect());
+        let log = dir.join("qualifying.ndjson");
+        config.qualifying_log = Some(log.clone());
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&log, "{\"earlier\":true}\n").unwrap();
+
+        let mut source = MemorySource::default();
+        for (url, (white, black)) in config.dataset_urls.iter().zip([("Bob", "Alice"), ("Alice", "Carol")]) {
+            source.datasets.insert(url.clone(), fixture_game("Rated Blitz game", white, black).into_bytes());
+        }
+        run_with_source(&config, &mut source, Arc::new(NullSink)).unwrap();
+
+        let data = fs::read_to_string(&log).unwrap();
+        let lines: Vec<serde_json::Value> = data.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(lines[0], serde_json::json!({ "earlier": true }), "existing lines are kept");
+        let entries: Vec<(&str, &str, u64)> = lines[1..]
+            .iter()
+            .map(|l| (l["player"].as_str().unwrap(), l["month"].as_str().unwrap(), l["games"].as_u64().unwrap()))
+            .collect();
+        assert_eq!(
+            entries,
+            [("Alice", "2025-01", 1), ("Bob", "2025-01", 1), ("Alice", "2025-02", 1), ("Carol", "2025-02", 1)]
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_expected_written_applies_cap_and_sample() {
+        let mut config = test_config();
+        let tally = Tally { games: 30, ..Tally::default() };
+        assert_eq!(tally.expected_written(&config), 30);
+        config.max_monthly_games = Some(20);
+        assert_eq!(tally.expected_written(&config), 20);
+        config.sample_per_player = Some(5);
+        assert_eq!(tally.expected_written(&config), 5);
+    }
+
+    #[test]
+    fn test_phase_changes_are_saved_at_once() {
+        let dir = temp_dir("tracker_phase");
+        let path = dir.join(ProgressSnapshot::FILE_NAME);
+        let mut tracker = Tracker::new(path.clone());
+        tracker.emit(&NullSink, UiEvent::Pass1Started);
+        tracker.emit(&NullSink, UiEvent::Pass1Progress { games_scanned: 10, valid_games: 5, unique_players: 3 });
+        tracker.emit(&NullSink, UiEvent::Pass2Started);
+        assert_eq!(ProgressSnapshot::load(&path).unwrap().phase, crate::stats::Phase::Pass2, "saved within the interval");
+        tracker.emit(&NullSink, UiEvent::PruneStarted { to_remove: 0 });
+        assert_eq!(ProgressSnapshot::load(&path).unwrap().phase, crate::stats::Phase::Pruning);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_progress_matches_files_on_disk() {
+        let dir = temp_dir("write_progress");
+        let url = "https://example.org/lichess_db_standard_rated_2025-01.pgn.zst".to_string();
+        let mut config = dataset_config(&dir, vec![url.clone()]);
+        // Flush after every game
+        config.write_buffer_max_bytes = 1;
+
+        let pgn = [fixture_game("Rated Blitz game", "Alice", "Bob"), fixture_game("Rated Blitz game", "Bob", "Carol")];
+        let mut source = MemorySource::default();
+        source.datasets.insert(url, pgn.concat().into_bytes());
+        let sink = Arc::new(RecordSink::default());
+        let stats = run_with_source(&config, &mut source, sink.clone()).unwrap();
+
+        let reported: Vec<u64> = sink
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|e| match e {
+                UiEvent::WriteProgress { bytes_written } => Some(*bytes_written),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(reported.len(), 4, "one flush per written entry");
+        assert!(reported.is_sorted());
+        let on_disk: u64 = crate::writer::player_files(&config.players_dir())
+            .unwrap()
+            .iter()
+            .map(|path| fs::metadata(path).unwrap().len())
+            .sum();
+        assert_eq!(reported.last(), Some(&on_disk));
+        assert_eq!(stats.output_bytes, on_disk);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_results_are_attributed_to_each_side() {
+        let dir = temp_dir("results");
+        let url = "https://example.org/lichess_db_standard_rated_2025-01.pgn.zst".to_string();
+        let mut config = dataset_config(&dir, vec![url.clone()]);
+        config.record_results = true;
+
+        let game = |white, black, result| {
+            fixture_game("Rated Blitz game", white, black)
+                .replacen("[TimeControl", &format!("[Result \"{}\"]\n[TimeControl", result), 1)
+        };
+        let pgn = [
+            game("Alice", "Bob", "1-0"),
+            game("Bob", "Alice", "1/2-1/2"),
+            game("Alice", "Carol", "0-1"),
+            game("Carol", "Bob", "0-1"),
+            game("Bob", "Carol", "*"),
+        ];
+        let mut source = MemorySource::default();
+        source.datasets.insert(url, pgn.concat().into_bytes());
+        run_with_source(&config, &mut source, Arc::new(NullSink)).unwrap();
+
+        let db = Database::open(&config.db_path).unwrap();
+        let record = |name| db.player_results(name).unwrap().unwrap();
+        assert_eq!(record("Alice"), (1, 1, 1));
+        assert_eq!(record("Bob"), (1, 1, 1), "the unfinished game counts nothing");
+        assert_eq!(record("Carol"), (1, 0, 1));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_pairings_are_tracked_for_written_players() {
+        let dir = temp_dir("pairings");
+        let url = "https://example.org/lichess_db_standard_rated_2025-01.pgn.zst".to_string();
+        let mut config = dataset_config(&dir, vec![url.clone()]);
+        config.min_monthly_games = 2;
+        config.track_pairings = true;
+
+        let pgn = [
+            fixture_game("Rated Blitz game", "Alice", "Bob"),
+            fixture_game("Rated Blitz game", "Bob", "Alice"),
+            fixture_game("Rated Blitz game", "Alice", "Bob"),
+            fixture_game("Rated Blitz game", "Alice", "Carol"),
+            fixture_game("Rated Blitz game", "Carol", "Bob"),
+            fixture_game("Rated Blitz game", "Dave", "Alice"),
+            fixture_game("Rated Rapid game", "Alice", "Carol"),
+        ];
+        let mut source = MemorySource::default();
+        source.datasets.insert(url, pgn.concat().into_bytes());
+        run_with_source(&config, &mut source, Arc::new(NullSink)).unwrap();
+
+        let db = Database::open(&config.db_path).unwrap();
+        let opponents = |name| db.head_to_head(name, 10).unwrap();
+        let expected = [("Bob".to_string(), 3), ("Carol".to_string(), 1), ("Dave".to_string(), 1)];
+        assert_eq!(opponents("Alice"), expected, "invalid games are not counted");
+        assert_eq!(opponents("Carol"), [("Alice".to_string(), 1), ("Bob".to_string(), 1)]);
+        assert!(opponents("Dave").is_empty(), "only written players are stored");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_memory_ceiling_forces_early_flushes() {
+        let dir = temp_dir("memory_ceiling");
+        let input = dir.join("2025-01.pgn");
+        fs::write(&input, [BOT_PGN; 4].join("\n")).unwrap();
+        let qualifying: HashSet<String> = ["PlayerB".to_string()].into();
+        let frames = |path: &Path| {
+            let data = fs::read(path).unwrap();
+            data.windows(4).filter(|w| *w == [0x28, 0xB5, 0x2F, 0xFD]).count()
+        };
+
+        // The buffer is far larger than the input, so only the ceiling flushes
+        let mut config = test_config();
+        config.memory_ceiling_bytes = Some(1);
+        let mut writer = PlayerWriter::new(dir.join("players"), usize::MAX);
+        pass2_extract(open(&input), &config, &qualifying, None, &mut writer, None, Arc::new(NullSink)).unwrap();
+        assert_eq!(writer.buffered_bytes(), 0);
+        assert_eq!(frames(&writer.player_path("PlayerB")), 4, "one flush per game");
+
+        config.memory_ceiling_bytes = Some(1 << 30);
+        let mut writer = PlayerWriter::new(dir.join("players_roomy"), usize::MAX);
+        pass2_extract(open(&input), &config, &qualifying, None, &mut writer, None, Arc::new(NullSink)).unwrap();
+        assert!(writer.buffered_bytes() > 0, "nothing flushed below the ceiling");
+
+        // A sample cannot be flushed early, so reaching the ceiling fails
+        config.memory_ceiling_bytes = Some(1);
+        config.sample_per_player = Some(2);
+        let mut writer = PlayerWriter::new(dir.join("players_sampled"), usize::MAX);
+        let err = pass2_extract(open(&input), &config, &qualifying, None, &mut writer, None, Arc::new(NullSink))
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("memory ceiling"), "{}", err);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_no_qualifying_players() {
+        let dir = temp_dir("no_qualifying");
+        let url = "https://example.org/lichess_db_standard_rated_2025-01.pgn.zst".to_string();
+        let mut config = dataset_config(&dir, vec![url.clone()]);
+        config.event_filter = "Rated Classical game".into();
+
+        let mut source = MemorySource::default();
+        source.datasets.insert(url.clone(), fixture_game("Rated Blitz game", "Alice", "Bob").into_bytes());
+        let sink = Arc::new(RecordSink::default());
+        let stats = run_with_source(&config, &mut source, sink.clone()).unwrap();
+
+        assert_eq!((stats.games_scanned, stats.valid_games, stats.games_saved), (1, 0, 0));
+        assert_eq!((stats.final_players, stats.players_pruned), (0, 0));
+        assert_eq!(source.opens.borrow().len(), 1, "pass 2 is skipped");
+        let events = sink.0.lock().unwrap();
+        assert!(events.iter().any(|e| matches!(e, UiEvent::Log(msg) if msg == "No qualifying players for 2025-01; nothing extracted")));
+        assert!(events.iter().any(|e| matches!(e, UiEvent::PruneComplete { remaining: 0, removed: 0 })));
+        // The month is still done: re-running it would find nothing either
+        assert!(Database::open(&config.db_path).unwrap().is_dataset_processed(&url).unwrap());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Records every event it is sent.
+    #[derive(Default)]
+    struct RecordSink(std::sync::Mutex<Vec<UiEvent>>);
+
+    impl EventSink for RecordSink {
+        fn send(&self, event: UiEvent) {
+            self.0.lock().unwrap().push(event);
+        }
+        fn check(&self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Cancels on the first check and records every event it is sent.
+    #[derive(Default)]
+    struct CancelSink(std::sync::Mutex<Vec<UiEvent>>);
+
+    impl EventSink for CancelSink {
+        fn send(&self, event: UiEvent) {
+            self.0.lock().unwrap().push(event);
+        }
+        fn check(&self) -> Result<()> {
+            Err(Cancelled.into())
+        }
+    }
+
+    #[test]
+    fn test_cancel_is_reported_and_saved() {
+        let dir = temp_dir("cancel");
+        let config = dataset_config(&dir, test_config().dataset_urls);
+        let sink = Arc::new(CancelSink::default());
+
+        let err = run_with_source(&config, &mut MemorySource::default(), sink.clone()).unwrap_err();
+        assert!(err.is::<Cancelled>());
+        let events = sink.0.lock().unwrap();
+        assert!(matches!(events.last(), Some(UiEvent::Cancelled)));
+        assert!(!events.iter().any(|e| matches!(e, UiEvent::Error(_) | UiEvent::Finished)));
+        assert!(dir.join(ProgressSnapshot::FILE_NAME).exists());
+        assert!(!dir.join("index.db-wal").exists(), "closed on cancel");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_index_is_closed_after_a_run() {
+        let dir = temp_dir("close_index");
+        let url = "https://example.org/lichess_db_standard_rated_2025-01.pgn.zst".to_string();
+        let config = dataset_config(&dir, vec![url.clone()]);
+
+        let mut source = MemorySource::default();
+        source.datasets.insert(url.clone(), fixture_game("Rated Blitz game", "Alice", "Bob").into_bytes());
+        run_with_source(&config, &mut source, Arc::new(NullSink)).unwrap();
+
+        assert!(!dir.join("index.db-wal").exists());
+        let db = Database::open_read_only(&config.db_path).unwrap();
+        assert!(db.is_dataset_processed(&url).unwrap());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_top_players() {
+        let counts: HashMap<String, Tally> = [("a", 1), ("b", 2), ("c", 3), ("d", 2), ("e", 1)]
+            .into_iter()
+            .map(|(name, games)| (name.to_string(), Tally { games, ..Tally::default() }))
+            .collect();
+        assert_eq!(top_players(&counts, 3), [("c".to_string(), 3), ("b".to_string(), 2), ("d".to_string(), 2)]);
+        assert_eq!(top_players(&counts, 10).len(), 5);
+        assert!(top_players(&HashMap::new(), 3).is_empty());
+    }
+
+    #[test]
+    fn test_extract_month() {
+        let base = "https://database.lichess.org/standard/lichess_db_standard_rated_2025-03";
+        assert_eq!(extract_month(&format!("{}.pgn.zst", base)), "2025-03");
+        assert_eq!(extract_month(&format!("{}.pgn.gz", base)), "2025-03");
+        assert_eq!(extract_month(&format!("{}.pgn", base)), "2025-03");
+    }
+
+    fn is_valid_game(info: &GameInfo, config: &Config) -> bool {
+        GameFilter::new(config).unwrap().is_valid(info)
+    }
+
+    #[test]
+    fn test_any_of_criteria() {
+        let game = |event: &str, tc: &str, moves: usize| {
+            let movetext: String = (1..=moves).map(|n| format!("{}. Nf3 {{ [%clk 0:05:00] }} {}... Nf6 {{ [%clk 0:05:00] }} ", n, n)).collect();
+            let pgn = format!("[Event \"{}\"]\n[White \"A\"]\n[Black \"B\"]\n[TimeControl \"{}\"]\n\n{}1-0\n", event, tc, movetext);
+            PgnParser::new(Cursor::new(pgn)).next_info().unwrap().unwrap()
+        };
+        let blitz = game("Rated Blitz game", "300+0", 30);
+        let rapid = game("Rated Rapid game", "600+0", 30);
+        let short_rapid = game("Rated Rapid game", "600+0", 10);
+        let classical = game("Rated Classical game", "1800+0", 30);
+
+        // Default: everything is required
+        let mut config = test_config();
+        config.event_filter = "Rated Blitz game".into();
+        config.time_control_filter = Some("600+0".into());
+        config.min_full_moves = 30;
+        assert!(!is_valid_game(&blitz, &config));
+        assert!(!is_valid_game(&rapid, &config));
+
+        // Blitz (by event) or rapid (by time control), always 30+ moves
+        config.any_of = vec![Criterion::Event, Criterion::TimeControl];
+        assert!(is_valid_game(&blitz, &config));
+        assert!(is_valid_game(&rapid, &config));
+        assert!(!is_valid_game(&short_rapid, &config), "min moves stays mandatory");
+        assert!(!is_valid_game(&classical, &config));
+
+        // Exclusions still apply, and an unset criterion does not satisfy the group
+        config.time_control_filter = None;
+        assert!(!is_valid_game(&rapid, &config));
+        assert!(is_valid_game(&blitz, &config));
+        config.event_filter.clear();
+        assert!(is_valid_game(&classical, &config), "an empty group passes");
+
+        // Short games of the regex's events, or long games of any event
+        config.event_filter_regex = Some("Rated (Blitz|Rapid) game".into());
+        config.any_of = vec![Criterion::MinMoves, Criterion::Event];
+        assert!(is_valid_game(&short_rapid, &config));
+        assert!(is_valid_game(&classical, &config));
+        // No minimum: min moves is unset, so the event has to match
+        config.min_full_moves = 0;
+        assert!(!is_valid_game(&classical, &config));
+        assert!(is_valid_game(&short_rapid, &config));
+
+        assert_eq!(Criterion::parse_list(" Event, time-control ,").unwrap(), [Criterion::Event, Criterion::TimeControl]);
+        assert!(Criterion::parse_list("speed").is_err());
+    }
+
+    #[test]
+    fn test_exclude_bots() {
+        let mut parser = PgnParser::new(Cursor::new(BOT_PGN));
+        let info = parser.next_info().unwrap().unwrap();
+
+        let mut config = test_config();
+        assert!(is_valid_game(&info, &config));
+
+        config.exclude_bots = true;
+        assert!(!is_valid_game(&info, &config));
+    }
+
+    #[test]
+    fn test_exclude_non_standard_start() {
+        let pgn = |extra: &str| BOT_PGN.replace("[TimeControl", &format!("{}[TimeControl", extra));
+        let odds = pgn("[SetUp \"1\"]\n[FEN \"rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBN1 w Qkq - 0 1\"]\n");
+        let initial = pgn("[SetUp \"1\"]\n[FEN \"rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1\"]\n");
+        let info = |pgn: &str| PgnParser::new(Cursor::new(pgn.to_string())).next_info().unwrap().unwrap();
+
+        let mut config = test_config();
+        assert!(is_valid_game(&info(&odds), &config));
+
+        config.exclude_non_standard_start = true;
+        assert!(!is_valid_game(&info(&odds), &config));
+        assert!(!is_valid_game(&info(&pgn("[SetUp \"1\"]\n")), &config), "SetUp without a FEN");
+        assert!(is_valid_game(&info(&initial), &config), "the initial FEN is a standard start");
+        assert!(is_valid_game(&info(BOT_PGN), &config));
+    }
+
+    #[test]
+    fn test_final_clock_filters() {
+        let game = |white: &str, black: &str| {
+            format!(
+                "[Event \"Rated Blitz game\"]\n[White \"A\"]\n[Black \"B\"]\n[TimeControl \"300+0\"]\n\n\
+                 1. e4 {{ [%clk 0:05:00] }} 1... e5 {{ [%clk 0:05:00] }} \
+                 2. Qh5 {{ [%clk 0:{}] }} 2... Nc6 {{ [%clk 0:{}] }} 1-0\n",
+                white, black
+            )
+        };
+        let info = |pgn: String| PgnParser::new(Cursor::new(pgn)).next_info().unwrap().unwrap();
+        let scramble = info(game("00:03", "00:08"));
+        let one_low = info(game("00:03", "02:30"));
+        let unclocked = GameInfo { white_clock: None, black_clock: None, ..info(game("00:03", "00:08")) };
+
+        let mut config = test_config();
+        assert!(is_valid_game(&unclocked, &config));
+
+        config.min_final_clock = Some(10);
+        assert!(!is_valid_game(&scramble, &config), "both below 10s");
+        assert!(is_valid_game(&one_low, &config), "one side had time left");
+        assert!(!is_valid_game(&unclocked, &config));
+
+        config.min_final_clock = None;
+        config.max_final_clock = Some(10);
+        assert!(is_valid_game(&scramble, &config));
+        assert!(!is_valid_game(&one_low, &config));
+    }
+
+    #[test]
+    fn test_rated_filter() {
+        let events = [
+            "Rated Blitz game",
+            "Casual Blitz game",
+            "Rated Rapid game",
+            "Rated Blitz tournament https://lichess.org/tournament/abc",
+            "Casual Correspondence game",
+            "Custom event",
+        ];
+        let kept = |config: &Config| -> Vec<&str> {
+            events
+                .iter()
+                .filter(|event| {
+                    let pgn = fixture_game(event, "A", "B");
+                    let info = PgnParser::new(Cursor::new(pgn)).next_info().unwrap().unwrap();
+                    is_valid_game(&info, config)
+                })
+                .copied()
+                .collect()
+        };
+
+        let mut config = test_config();
+        config.event_filter = String::new();
+        assert_eq!(kept(&config).len(), events.len(), "empty event filter accepts any");
+
+        config.rated_filter = Some(true);
+        assert_eq!(kept(&config), [events[0], events[2], events[3]]);
+        config.rated_filter = Some(false);
+        assert_eq!(kept(&config), [events[1], events[4]]);
+
+        // Combined with an exact event it can only narrow further
+        config.event_filter = "Rated Blitz game".into();
+        assert!(kept(&config).is_empty());
+    }
+
+    #[test]
+    fn test_event_filter_regex() {
+        let events = ["Rated Blitz game", "Rated Rapid game", "Casual Blitz game", "Rated Blitz game (arena)"];
+        let mut config = test_config();
+        config.event_filter_regex = Some("Rated (Blitz|Rapid) game".into());
+        let filter = GameFilter::new(&config).unwrap();
+        let kept: Vec<&str> = events
+            .iter()
+            .filter(|event| {
+                let pgn = fixture_game(event, "A", "B");
+                filter.is_valid(&PgnParser::new(Cursor::new(pgn)).next_info().unwrap().unwrap())
+            })
+            .copied()
+            .collect();
+        // The exact filter ("Rated Blitz game") is overridden; the match is whole-string
+        assert_eq!(kept, [events[0], events[1]]);
+
+        config.event_filter_regex = Some("Rated (Blitz".into());
+        let err = GameFilter::new(&config).err().unwrap();
+        assert!(format!("{:#}", err).starts_with("Invalid event regex 'Rated (Blitz'"), "{:#}", err);
+    }
+
+    #[test]
+    fn test_min_avg_half_moves_qualification() {
+        let mut config = test_config();
+        config.min_monthly_games = 2;
+        let short = Tally { games: 4, half_moves: 4 * 39, ..Tally::default() };
+        let long = Tally { games: 4, half_moves: 4 * 40, ..Tally::default() };
+        assert!(short.qualifies(&config), "no average required by default");
+
+        config.min_avg_half_moves = Some(40.0);
+        assert!(!short.qualifies(&config));
+        assert!(long.qualifies(&config));
+        assert!(!Tally { games: 1, half_moves: 100, ..Tally::default() }.qualifies(&config), "still needs the monthly count");
+    }
+
+    #[test]
+    fn test_max_scan_games() {
+        let dir = temp_dir("max_scan_games");
+        // The limit counts every game read, the invalid one too
+        let pgn = [
+            fixture_game("Rated Blitz game", "Alice", "Bob"),
+            fixture_game("Rated Bullet game", "Alice", "Bob"),
+            fixture_game("Rated Blitz game", "Bob", "Alice"),
+            fixture_game("Rated Blitz game", "Alice", "Carol"),
+        ]
+        .concat();
+        let mut config = test_config();
+        config.max_scan_games = Some(3);
+
+        let sink = Arc::new(RecordSink::default());
+        let (counts, _, scanned, _) = pass1_count(Cursor::new(pgn.as_bytes()), &config, sink.clone()).unwrap();
+        assert_eq!(scanned, 3);
+        assert_eq!((counts["Alice"].games, counts["Bob"].games), (2, 2));
+        assert!(!counts.contains_key("Carol"));
+        assert!(sink.0.lock().unwrap().iter().any(|e| matches!(e, UiEvent::Log(msg) if msg.contains("only the first 3 games"))));
+
+        let qualifying: HashSet<String> = ["Alice".to_string()].into();
+        let mut writer = PlayerWriter::new(dir.clone(), usize::MAX);
+        let extraction =
+            pass2_extract(Cursor::new(pgn.as_bytes()), &config, &qualifying, None, &mut writer, None, Arc::new(NullSink)).unwrap();
+        assert_eq!((extraction.extracted, extraction.seen), (2, 2), "same games as pass 1");
+        assert!(extraction.scan_limited);
+
+        config.max_scan_games = Some(4);
+        let extraction =
+            pass2_extract(Cursor::new(pgn.as_bytes()), &config, &qualifying, None, &mut writer, None, Arc::new(NullSink)).unwrap();
+        assert!(!extraction.scan_limited, "the whole file fits");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_min_unique_opponents() {
+        // Farmer plays only Alt; Alice also meets Bob and Carol
+        let games = [
+            ("Farmer", "Alt"),
+            ("Alt", "Farmer"),
+            ("Farmer", "Alt"),
+            ("Alice", "Bob"),
+            ("Carol", "Alice"),
+            ("Alice", "Farmer"),
+        ];
+        let pgn: String = games.iter().map(|(w, b)| fixture_game("Rated Blitz game", w, b)).collect();
+        let mut config = test_config();
+        config.min_monthly_games = 1;
+        config.min_unique_opponents = Some(2);
+
+        for approximate in [false, true] {
+            config.approximate_opponents = approximate;
+            let (counts, _, _, _) = pass1_count(Cursor::new(pgn.as_bytes()), &config, Arc::new(NullSink)).unwrap();
+            let mut qualifying: Vec<&str> =
+                counts.iter().filter(|(_, t)| t.qualifies(&config)).map(|(n, _)| n.as_str()).collect();
+            qualifying.sort_unstable();
+            assert_eq!(qualifying, ["Alice", "Farmer"], "approximate: {}", approximate);
+            assert_eq!(counts["Alt"].opponents, 1);
+            assert_eq!(counts["Alt"].games, 3, "enough games, one opponent");
+        }
+
+        let mut sketch = Opponents::new(true);
+        for i in 0..100 {
+            sketch.add(&format!("opponent{}", i), 0);
+            sketch.add(&format!("opponent{}", i), 0);
+        }
+        assert!((90..=110).contains(&sketch.count()), "{}", sketch.count());
+        let mut exact = Opponents::new(false);
+        for i in 0..10 {
+            exact.add(&format!("opponent{}", i), 3);
+        }
+        assert_eq!(exact.count(), 3, "stops at the threshold");
+    }
+
+    #[test]
+    fn test_require_titles() {
+        let mut parser = PgnParser::new(Cursor::new(BOT_PGN));
+        let info = parser.next_info().unwrap().unwrap();
+
+        let mut config = test_config();
+        config.require_titles = Some(vec!["GM".into(), "IM".into()]);
+        assert!(!is_valid_game(&info, &config));
+
+        // Exact match only: "BO" must not match "BOT"
+        config.require_titles = Some(vec!["BO".into()]);
+        assert!(!is_valid_game(&info, &config));
+
+        config.require_titles = Some(vec!["BOT".into()]);
+        assert!(is_valid_game(&info, &config));
+    }
+
+    #[test]
+    fn test_move_prefix() {
+        let mut config = test_config();
+        let accepts = |config: &Config| {
+            let filter = GameFilter::new(config).unwrap();
+            let mut parser = PgnParser::new(Cursor::new(BOT_PGN)).with_opening_plies(filter.opening_plies());
+            let info = parser.next_info().unwrap().unwrap();
+            filter.is_valid(&info)
+        };
+
+        config.move_prefix = Some("1. e4 e5".into());
+        assert!(accepts(&config));
+        config.move_prefix = Some("1. e4".into());
+        assert!(accepts(&config));
+        config.move_prefix = Some("1. e4 c5".into());
+        assert!(!accepts(&config));
+        // Prefix longer than the game itself
+        config.move_prefix = Some("1. e4 e5 2. Nf3".into());
+        assert!(!accepts(&config));
+        // No moves at all would match every game
+        for empty in ["1.", "1-0", "  "] {
+            config.move_prefix = Some(empty.into());
+            assert!(GameFilter::new(&config).is_err(), "{}", empty);
+        }
+    }
+
+    #[test]
+    fn test_date_range_boundaries() {
+        let mut config = test_config();
+        config.date_from = Some("2025.08.09".into());
+        config.date_to = Some("2025.08.10".into());
+
+        let in_range = |date: &str| date_in_range(date, &config);
+        assert!(!in_range("2025.08.08"));
+        assert!(in_range("2025.08.09"));
+        assert!(in_range("2025.08.10"));
+        assert!(!in_range("2025.08.11"));
+        assert!(!in_range("????.??.??"));
+        assert!(!in_range(""));
+
+        config.date_from = None;
+        assert!(date_in_range("2025.01.01", &config));
+        assert!(!date_in_range("2025.08.11", &config));
+
+        config.date_to = None;
+        assert!(date_in_range("????.??.??", &config));
+    }
+}
@@ -0,0 +1,280 @@
+use crate::config::{Config, Criterion};
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+
+/// Keys a profile may set, in the order `rookt` documents them. Profiles
+/// hold game filters and thresholds only: paths, datasets and tuning stay
+/// with the flags and `ROOKT_*` variables.
+const KEYS: [&str; 18] = [
+    "event",
+    "event_regex",
+    "rated",
+    "time_control",
+    "exclude_bots",
+    "exclude_non_standard_start",
+    "titles",
+    "date_from",
+    "date_to",
+    "move_prefix",
+    "min_moves",
+    "min_final_clock",
+    "max_final_clock",
+    "any_of",
+    "min_monthly_games",
+    "min_total_games",
+    "min_avg_half_moves",
+    "min_unique_opponents",
+];
+
+/// Apply the filter profile `name` (see `profile_path`) to `config`.
+/// Returns the file it was read from. The whole profile is checked before
+/// anything is applied, so a bad profile leaves `config` as it was.
+pub fn apply(config: &mut Config, name: &str) -> Result<PathBuf> {
+    let path = profile_path(name, |key| std::env::var(key).ok())?;
+    let text = fs::read_to_string(&path).with_context(|| format!("Cannot read profile {}", path.display()))?;
+    apply_text(config, &text).with_context(|| format!("Invalid profile {}", path.display()))?;
+    Ok(path)
+}
+
+/// The file of profile `name`: `<dir>/<name>.profile` in the profiles
+/// directory, or `name` itself when it is a path (holds a separator or
+/// ends in `.profile`). The directory is `$ROOKT_CONFIG_DIR/profiles`,
+/// else `rookt/profiles` under `$XDG_CONFIG_HOME`, `~/.config` or (on
+/// Windows) `%APPDATA%`.
+fn profile_path(name: &str, var: impl Fn(&str) -> Option<String>) -> Result<PathBuf> {
+    if name.contains(['/', '\\']) || name.ends_with(".profile") {
+        return Ok(PathBuf::from(name));
+    }
+    if name.is_empty() {
+        anyhow::bail!("--profile needs a profile name");
+    }
+    let config_dir = var("ROOKT_CONFIG_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| var("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()).map(|dir| PathBuf::from(dir).join("rookt")))
+        .or_else(|| var("HOME").filter(|dir| !dir.is_empty()).map(|dir| PathBuf::from(dir).join(".config/rookt")))
+        .or_else(|| var("APPDATA").filter(|dir| !dir.is_empty()).map(|dir| PathBuf::from(dir).join("rookt")))
+        .context("No config directory for profiles: set ROOKT_CONFIG_DIR or pass a profile path")?;
+    let dir = config_dir.join("profiles");
+    let path = dir.join(format!("{}.profile", name));
+    if !path.is_file() {
+        let mut known: Vec<String> = fs::read_dir(&dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.strip_suffix(".profile").map(String::from))
+            .collect();
+        known.sort();
+        let known = if known.is_empty() { "none".to_string() } else { known.join(", ") };
+        anyhow::bail!("No profile '{}' ({} not found; profiles there: {})", name, path.display(), known);
+    }
+    Ok(path)
+}
+
+/// Apply a profile's `key = value` lines. Blank lines and lines starting
+/// with `#` are skipped; an empty value clears an optional filter (any
+/// event, time control, ...), as in the TUI.
+fn apply_text(config: &mut Config, text: &str) -> Result<()> {
+    let mut staged = Config::default_blitz_300();
+    copy_filters(config, &mut staged);
+    let mut seen = HashSet::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .with_context(|| format!("line {}: expected key = value", number + 1))?;
+        let (key, value) = (key.trim(), value.trim());
+        if !KEYS.contains(&key) {
+            anyhow::bail!("line {}: unknown key '{}', expected one of {}", number + 1, key, KEYS.join(", "));
+        }
+        if !seen.insert(key) {
+            anyhow::bail!("line {}: '{}' is set twice", number + 1, key);
+        }
+        set(&mut staged, key, value).with_context(|| format!("line {}: {}", number + 1, key))?;
+    }
+    if let (Some(from), Some(to)) = (&staged.date_from, &staged.date_to)
+        && from > to
+    {
+        anyhow::bail!("date_from must be before or equal to date_to");
+    }
+    if let (Some(min), Some(max)) = (staged.min_final_clock, staged.max_final_clock)
+        && min > max
+    {
+        anyhow::bail!("min_final_clock must be at most max_final_clock");
+    }
+    copy_filters(&staged, config);
+    Ok(())
+}
+
+/// Set one profile key on `config`.
+fn set(config: &mut Config, key: &str, value: &str) -> Result<()> {
+    let optional = |value: &str| Some(value.to_string()).filter(|v| !v.is_empty());
+    match key {
+        "event" => config.event_filter = value.to_string(),
+        "event_regex" => {
+            if !value.is_empty() {
+                crate::pipeline::event_regex(value)?;
+            }
+            config.event_filter_regex = optional(value);
+        }
+        "rated" => {
+            config.rated_filter = match value.to_ascii_lowercase().as_str() {
+                "" | "any" => None,
+                "rated" => Some(true),
+                "casual" => Some(false),
+                _ => anyhow::bail!("expected any, rated or casual"),
+            }
+        }
+        "time_control" => config.time_control_filter = optional(value),
+        "exclude_bots" => config.exclude_bots = yes_no(value)?,
+        "exclude_non_standard_start" => config.exclude_non_standard_start = yes_no(value)?,
+        "titles" => {
+            let titles: Vec<String> = value.split(',').map(str::trim).filter(|t| !t.is_empty()).map(String::from).collect();
+            config.require_titles = Some(titles).filter(|t| !t.is_empty());
+        }
+        "date_from" => config.date_from = date(value)?,
+        "date_to" => config.date_to = date(value)?,
+        "move_prefix" => {
+            if !value.is_empty() && crate::parser::san_moves(value).is_empty() {
+                anyhow::bail!("no moves, e.g. 1. e4 c5");
+            }
+            config.move_prefix = optional(value);
+        }
+        "min_moves" => config.min_full_moves = number(value)?,
+        "min_final_clock" => config.min_final_clock = optional_number(value)?,
+        "max_final_clock" => config.max_final_clock = optional_number(value)?,
+        "any_of" => config.any_of = Criterion::parse_list(value)?,
+        "min_monthly_games" => config.min_monthly_games = number(value)?,
+        "min_total_games" => config.min_total_games = number(value)?,
+        "min_avg_half_moves" => {
+            config.min_avg_half_moves = match value {
+                "" => None,
+                v => Some(v.parse::<f64>().ok().filter(|n| *n > 0.0).context("expected a positive number")?),
+            }
+        }
+        "min_unique_opponents" => config.min_unique_opponents = optional_number(value)?,
+        _ => unreachable!("checked against KEYS"),
+    }
+    Ok(())
+}
+
+/// Copy the settings a profile can change from `from` to `to`.
+fn copy_filters(from: &Config, to: &mut Config) {
+    to.event_filter = from.event_filter.clone();
+    to.event_filter_regex = from.event_filter_regex.clone();
+    to.rated_filter = from.rated_filter;
+    to.time_control_filter = from.time_control_filter.clone();
+    to.exclude_bots = from.exclude_bots;
+    to.exclude_non_standard_start = from.exclude_non_standard_start;
+    to.require_titles = from.require_titles.clone();
+    to.date_from = from.date_from.clone();
+    to.date_to = from.date_to.clone();
+    to.move_prefix = from.move_prefix.clone();
+    to.min_full_moves = from.min_full_moves;
+    to.min_final_clock = from.min_final_clock;
+    to.max_final_clock = from.max_final_clock;
+    to.any_of = from.any_of.clone();
+    to.min_monthly_games = from.min_monthly_games;
+    to.min_total_games = from.min_total_games;
+    to.min_avg_half_moves = from.min_avg_half_moves;
+    to.min_unique_opponents = from.min_unique_opponents;
+}
+
+fn yes_no(value: &str) -> Result<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "yes" | "true" => Ok(true),
+        "no" | "false" => Ok(false),
+        _ => anyhow::bail!("expected yes or no"),
+    }
+}
+
+fn number(value: &str) -> Result<u32> {
+    value.parse().context("expected a non-negative integer")
+}
+
+fn optional_number(value: &str) -> Result<Option<u32>> {
+    if value.is_empty() { Ok(None) } else { number(value).map(Some) }
+}
+
+/// A `YYYY-MM-DD` (or `YYYY.MM.DD`) date as the `YYYY.MM.DD` games use.
+fn date(value: &str) -> Result<Option<String>> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+    let parts: Vec<&str> = value.split(['-', '.']).collect();
+    let field = |i: usize, len: usize, range: std::ops::RangeInclusive<u32>| {
+        parts.get(i).filter(|part| part.len() == len).and_then(|part| part.parse().ok()).filter(|n| range.contains(n))
+    };
+    match (parts.len(), field(0, 4, 0..=9999), field(1, 2, 1..=12), field(2, 2, 1..=31)) {
+        (3, Some(year), Some(month), Some(day)) => Ok(Some(format!("{:04}.{:02}.{:02}", year, month, day))),
+        _ => anyhow::bail!("invalid date '{}', expected YYYY-MM-DD", value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_sets_filters_and_rejects_mistakes() {
+        let mut config = Config::default_blitz_300();
+        config.output_dir = PathBuf::from("/kept");
+        let profile = "# Rapid games of titled players\n\
+            event = Rated Rapid game\n\
+            time_control =\n\
+            titles = GM, IM\n\
+            date_from = 2025-03-01\n\
+            min_moves = 20\n\
+            any_of = event,titles\n\
+            min_total_games = 10\n";
+        apply_text(&mut config, profile).unwrap();
+        assert_eq!(config.event_filter, "Rated Rapid game");
+        assert_eq!(config.time_control_filter, None, "empty means any");
+        assert_eq!(config.require_titles, Some(vec!["GM".to_string(), "IM".to_string()]));
+        assert_eq!(config.date_from.as_deref(), Some("2025.03.01"));
+        assert_eq!((config.min_full_moves, config.min_total_games), (20, 10));
+        assert_eq!(config.any_of, [Criterion::Event, Criterion::Titles]);
+        assert_eq!(config.min_monthly_games, 25, "unset keeps what was there");
+        assert_eq!(config.output_dir, PathBuf::from("/kept"));
+
+        for (bad, message) in [
+            ("output_dir = /tmp", "unknown key"),
+            ("min_moves = 5\nmin_moves = 6", "set twice"),
+            ("min_moves = many", "line 1: min_moves"),
+            ("event_regex = Rated (Blitz", "Invalid event regex"),
+            ("date_from = 2025-02-01\ndate_to = 2025-01-01", "before or equal"),
+            ("min_final_clock = 60\nmax_final_clock = 30", "at most max_final_clock"),
+            ("rated", "key = value"),
+            ("move_prefix = 1.", "no moves"),
+        ] {
+            let err = apply_text(&mut config, bad).unwrap_err();
+            assert!(format!("{:#}", err).contains(message), "{}: {:#}", bad, err);
+        }
+        assert_eq!(config.min_full_moves, 20, "a rejected profile changes nothing");
+        assert_eq!(config.date_from.as_deref(), Some("2025.03.01"));
+    }
+
+    #[test]
+    fn test_profile_path_resolution() {
+        let dir = std::env::temp_dir().join(format!("rookt_test_profiles_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("profiles")).unwrap();
+        fs::write(dir.join("profiles/rapid.profile"), "event = Rated Rapid game\n").unwrap();
+        let config_dir = dir.to_string_lossy().into_owned();
+        let var = |key: &str| (key == "ROOKT_CONFIG_DIR").then(|| config_dir.clone());
+
+        assert_eq!(profile_path("rapid", var).unwrap(), dir.join("profiles/rapid.profile"));
+        let err = profile_path("blitz", var).unwrap_err().to_string();
+        assert!(err.contains("profiles there: rapid"), "{}", err);
+        assert_eq!(profile_path("./mine.profile", var).unwrap(), PathBuf::from("./mine.profile"));
+        let xdg = |key: &str| (key == "XDG_CONFIG_HOME").then(|| "/xdg".to_string());
+        assert!(profile_path("rapid", xdg).unwrap_err().to_string().contains("/xdg/rookt/profiles/rapid.profile"));
+        assert!(profile_path("rapid", |_| None).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+
+/// SplitMix64 generator. Tiny and stable across builds, so a given seed
+/// always selects the same games.
+pub struct SplitMix64(u64);
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform value in `0..n` (multiply-shift; `n` must be non-zero).
+    pub fn below(&mut self, n: u64) -> u64 {
+        ((self.next_u64() as u128 * n as u128) >> 64) as u64
+    }
+}
+
+/// One player's reservoir: games kept so far, tagged with stream position.
+#[derive(Default)]
+struct Reservoir {
+    seen: u64,
+    games: Vec<(u64, String)>,
+}
+
+/// Uniform per-player sampling of at most `k` games from a stream
+/// (reservoir sampling, Algorithm R), keyed by player name.
+pub struct PlayerSampler {
+    k: usize,
+    rng: SplitMix64,
+    position: u64,
+    reservoirs: HashMap<String, Reservoir>,
+    /// PGN bytes held across all reservoirs.
+    buffered_bytes: usize,
+}
+
+impl PlayerSampler {
+    pub fn new(k: u32, seed: u64) -> Self {
+        Self {
+            k: k as usize,
+            rng: SplitMix64::new(seed),
+            position: 0,
+            reservoirs: HashMap::new(),
+            buffered_bytes: 0,
+        }
+    }
+
+    /// Offer one of `player`'s games to their reservoir.
+    pub fn offer(&mut self, player: &str, pgn: &str) {
+        self.position += 1;
+        if self.k == 0 {
+            return;
+        }
+        let reservoir = match self.reservoirs.get_mut(player) {
+            Some(r) => r,
+            None => self.reservoirs.entry(player.to_string()).or_default(),
+        };
+        reservoir.seen += 1;
+        if reservoir.games.len() < self.k {
+            reservoir.games.push((self.position, pgn.to_string()));
+            self.buffered_bytes += pgn.len();
+        } else {
+            let j = self.rng.below(reservoir.seen) as usize;
+            if j < self.k {
+                let old = std::mem::replace(&mut reservoir.games[j], (self.position, pgn.to_string()));
+                self.buffered_bytes = self.buffered_bytes - old.1.len() + pgn.len();
+            }
+        }
+    }
+
+    /// PGN bytes currently held in the reservoirs.
+    pub fn buffered_bytes(&self) -> usize {
+        self.buffered_bytes
+    }
+
+    /// Consume the sampler, yielding each player's sampled games in their
+    /// original stream order.
+    pub fn into_samples(self) -> impl Iterator<Item = (String, Vec<String>)> {
+        self.reservoirs.into_iter().map(|(player, mut reservoir)| {
+            reservoir.games.sort_unstable_by_key(|(pos, _)| *pos);
+            (player, reservoir.games.into_iter().map(|(_, pgn)| pgn).collect())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(k: u32, seed: u64) -> Vec<String> {
+        let mut sampler = PlayerSampler::new(k, seed);
+        for i in 0..100 {
+            sampler.offer("PlayerA", &format!("game {:03}", i));
+        }
+        sampler.offer("PlayerB", "only game");
+        let mut samples: HashMap<String, Vec<String>> = sampler.into_samples().collect();
+        assert_eq!(samples.remove("PlayerB").unwrap(), ["only game"]);
+        samples.remove("PlayerA").unwrap()
+    }
+
+    #[test]
+    fn test_sample_size_order_and_seed() {
+        let a = sample(10, 7);
+        assert_eq!(a.len(), 10);
+        let mut sorted = a.clone();
+        sorted.sort();
+        assert_eq!(a, sorted, "samples keep stream order");
+
+        assert_eq!(a, sample(10, 7), "same seed, same sample");
+        assert_ne!(a, sample(10, 8));
+    }
+}
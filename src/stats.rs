@@ -0,0 +1,158 @@
+use crate::events::UiEvent;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// Pipeline phase within the current dataset.
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub enum Phase {
+    #[default]
+    Downloading,
+    /// The input is ready and its decoder is starting up.
+    Preparing,
+    Pass1,
+    Pass2,
+    Pruning,
+    Done,
+}
+
+/// Cumulative counters for a whole pipeline run (all datasets).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RunStats {
+    /// Datasets fully processed during this run (excludes skipped ones).
+    pub datasets_processed: u64,
+    /// Datasets skipped because they were already marked processed.
+    pub datasets_skipped: u64,
+    /// Names of datasets that failed during this run.
+    pub failed_datasets: Vec<String>,
+    /// Total games scanned in pass 1.
+    pub games_scanned: u64,
+    /// Games that passed the filters in pass 1.
+    pub valid_games: u64,
+    /// Qualifying players summed over datasets (a player counts once per month).
+    pub qualifying_players: u64,
+    /// Game entries written in pass 2 (a game counts once per qualifying side).
+    pub games_saved: u64,
+    /// Compressed bytes appended to player files.
+    pub output_bytes: u64,
+    /// Players removed by the final prune.
+    pub players_pruned: u64,
+    /// Players remaining after the final prune.
+    pub final_players: u64,
+    /// Wall-clock time of the run.
+    pub elapsed: Duration,
+}
+
+impl RunStats {
+    /// Fold a pipeline event into the totals. Events that carry no
+    /// cumulative information are ignored.
+    pub fn record(&mut self, event: &UiEvent) {
+        match event {
+            UiEvent::DatasetSkipped { .. } => self.datasets_skipped += 1,
+            UiEvent::DatasetComplete { .. } => self.datasets_processed += 1,
+            UiEvent::DatasetFailed { name, .. } => self.failed_datasets.push(name.clone()),
+            UiEvent::Pass1Complete { total_scanned, valid_games, qualifying_players, .. } => {
+                self.games_scanned += total_scanned;
+                self.valid_games += valid_games;
+                self.qualifying_players += qualifying_players;
+            }
+            UiEvent::Pass2Complete { total_extracted, bytes_written } => {
+                self.games_saved += total_extracted;
+                self.output_bytes += bytes_written;
+            }
+            UiEvent::PruneComplete { remaining, removed } => {
+                self.players_pruned = *removed;
+                self.final_players = *remaining;
+            }
+            _ => {}
+        }
+    }
+}
+
+impl fmt::Display for RunStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "rookt run summary")?;
+        writeln!(
+            f,
+            "Datasets processed: {} ({} skipped)",
+            self.datasets_processed, self.datasets_skipped
+        )?;
+        if !self.failed_datasets.is_empty() {
+            writeln!(
+                f,
+                "Datasets failed:    {} ({})",
+                self.failed_datasets.len(),
+                self.failed_datasets.join(", ")
+            )?;
+        }
+        writeln!(f, "Games scanned:      {}", self.games_scanned)?;
+        writeln!(f, "Valid games:        {}", self.valid_games)?;
+        writeln!(f, "Qualifying players: {}", self.qualifying_players)?;
+        writeln!(f, "Games saved:        {}", self.games_saved)?;
+        writeln!(f, "Output written:     {:.2} GB", self.output_bytes as f64 / 1_073_741_824.0)?;
+        writeln!(f, "Players pruned:     {}", self.players_pruned)?;
+        writeln!(f, "Final players:      {}", self.final_players)?;
+        writeln!(f, "Wall-clock time:    {}", fmt_duration(self.elapsed))
+    }
+}
+
+// ── Progress snapshot ───────────────────────────────────────────────────────
+
+/// Run totals plus the current position, persisted to `.progress.json` so a
+/// restarted dashboard can show where the last run got to.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProgressSnapshot {
+    pub stats: RunStats,
+    pub phase: Phase,
+    pub dataset_index: usize,
+    pub total_datasets: usize,
+    pub dataset_name: String,
+}
+
+impl ProgressSnapshot {
+    /// File name of the snapshot inside the output directory.
+    pub const FILE_NAME: &'static str = ".progress.json";
+
+    /// Fold a pipeline event into the totals and current position.
+    pub fn record(&mut self, event: &UiEvent) {
+        self.stats.record(event);
+        match event {
+            UiEvent::DatasetStarted { index, total, name } => {
+                self.dataset_index = *index;
+                self.total_datasets = *total;
+                self.dataset_name = name.clone();
+                self.phase = Phase::Downloading;
+            }
+            UiEvent::Preparing => self.phase = Phase::Preparing,
+            UiEvent::Pass1Started => self.phase = Phase::Pass1,
+            UiEvent::Pass2Started => self.phase = Phase::Pass2,
+            UiEvent::PruneStarted { .. } => self.phase = Phase::Pruning,
+            UiEvent::Finished => self.phase = Phase::Done,
+            _ => {}
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read_to_string(path)
+            .with_context(|| format!("Cannot read {}", path.display()))?;
+        serde_json::from_str(&data).with_context(|| format!("Invalid snapshot {}", path.display()))
+    }
+
+    /// Write atomically (temp file + rename) so a crash never leaves a torn file.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+/// Format a duration as `1h 02m 03s`.
+pub fn fmt_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    format!("{}h {:02}m {:02}s", secs / 3600, (secs / 60) % 60, secs % 60)
+}
@@ -0,0 +1,49 @@
+/// Total physical memory, or None where it cannot be read.
+#[cfg(unix)]
+pub fn total_ram_bytes() -> Option<u64> {
+    // SAFETY: sysconf only reads system constants
+    let (pages, page_size) = unsafe { (libc::sysconf(libc::_SC_PHYS_PAGES), libc::sysconf(libc::_SC_PAGESIZE)) };
+    match (u64::try_from(pages), u64::try_from(page_size)) {
+        (Ok(pages), Ok(page_size)) if pages > 0 && page_size > 0 => Some(pages.saturating_mul(page_size)),
+        _ => None,
+    }
+}
+
+#[cfg(not(unix))]
+pub fn total_ram_bytes() -> Option<u64> {
+    None
+}
+
+/// The most memory this process has held resident so far (its peak RSS),
+/// or None where it cannot be read.
+#[cfg(unix)]
+pub fn peak_memory_bytes() -> Option<u64> {
+    // SAFETY: getrusage fills the zeroed struct it is given
+    let usage = unsafe {
+        let mut usage: libc::rusage = std::mem::zeroed();
+        if libc::getrusage(libc::RUSAGE_SELF, &mut usage) != 0 {
+            return None;
+        }
+        usage
+    };
+    let max_rss = u64::try_from(usage.ru_maxrss).ok()?;
+    // Bytes on macOS, kilobytes elsewhere
+    Some(if cfg!(target_os = "macos") { max_rss } else { max_rss * 1024 })
+}
+
+#[cfg(not(unix))]
+pub fn peak_memory_bytes() -> Option<u64> {
+    None
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_figures_are_plausible() {
+        let ram = total_ram_bytes().unwrap();
+        let peak = peak_memory_bytes().unwrap();
+        assert!(peak > 0 && peak < ram, "peak {} of {} bytes", peak, ram);
+    }
+}
@@ -1,7 +1,15 @@
-use crate::config::Config;
+use crate::config::{self, ArchiveFormat, Config, GroupBy, IndexFormat, OutputLayout};
+use crate::database::Database;
 use crate::events::{PipelineControl, UiEvent};
-use std::path::PathBuf;
+use crate::parser;
+use crate::pipeline;
+pub use crate::stats::Phase;
+use crate::stats::{fmt_duration, ProgressSnapshot, RunStats};
+use crate::system::total_ram_bytes;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
 
 // ── Screens ─────────────────────────────────────────────────────────────────
 
@@ -15,28 +23,32 @@ pub enum Screen {
 pub enum RunState {
     Idle,
     Running,
+    /// Pause requested; the pipeline hasn't reached a check yet.
+    Pausing,
     Paused,
     Finished,
-    #[allow(dead_code)]
     Cancelled,
     Error(String),
 }
 
-#[derive(PartialEq, Clone)]
-pub enum Phase {
-    Downloading,
-    Pass1,
-    Pass2,
-    Pruning,
-    Done,
-}
-
 // ── Config field ────────────────────────────────────────────────────────────
 
+#[derive(PartialEq, Clone, Copy)]
+pub enum FieldKind {
+    /// Free-form text edited in place.
+    Text,
+    /// Boolean flipped with Enter/Space; value is "yes" or "no".
+    Toggle,
+}
+
 pub struct ConfigField {
     pub label: &'static str,
     pub value: String,
     pub hint: &'static str,
+    pub kind: FieldKind,
+    /// Checks the trimmed value as it is typed; an error is shown in place
+    /// of the hint (see `App::field_error`).
+    pub check: fn(&str) -> Option<String>,
 }
 
 // ── App state ───────────────────────────────────────────────────────────────
@@ -49,7 +61,12 @@ pub struct App {
     pub selected: usize,
     pub editing: bool,
     pub edit_cursor: usize,
+    /// First form line shown; kept so the selection stays in view.
+    pub field_scroll: usize,
     pub validation_error: Option<String>,
+    /// Months already processed by the index in the configured output
+    /// dir, cached by index path (see `processed_months`).
+    processed: Option<(PathBuf, Vec<String>)>,
 
     // Dashboard state
     pub run_state: RunState,
@@ -57,31 +74,56 @@ pub struct App {
     pub current_dataset: usize,
     pub total_datasets: usize,
     pub dataset_name: String,
+    /// The run counts every dataset before extracting any.
+    pub two_phase: bool,
+    /// Two-phase only: counting is done and the datasets are being extracted.
+    pub extracting: bool,
 
     // Download
-    pub dl_total: u64,
+    /// Size of the current download; None when the server sent no
+    /// content-length, so progress can only be shown as bytes so far.
+    pub dl_total: Option<u64>,
     pub dl_read: u64,
+    /// Sizes of prefetch downloads still running, by dataset index.
+    pub prefetch_totals: HashMap<usize, Option<u64>>,
+    /// Compressed sizes of datasets seen this run, by dataset index.
+    pub dataset_sizes: HashMap<usize, u64>,
 
-    // File progress (compressed .zst bytes)
+    // File progress (compressed bytes drive the gauge)
     pub file_total: u64,
     pub file_read: u64,
+    /// Uncompressed PGN bytes produced so far in the current pass.
+    pub file_decoded: u64,
+    /// When the current pass started reading, for decoded throughput.
+    pub file_started: Option<Instant>,
 
     // Pass 1 (current dataset)
     pub p1_scanned: u64,
     pub p1_valid: u64,
     pub p1_players: u64,
 
+    /// Pass 1 leaders (most valid games so far).
+    pub top_players: Vec<(String, u32)>,
+
     // Pass 2 (current dataset)
     pub p2_extracted: u64,
+    /// Compressed bytes flushed so far in the pass 2 in progress.
+    pub p2_bytes: u64,
 
     // Cumulative totals
-    pub cum_qualifying: u64,
-    pub cum_games_saved: u64,
-    pub final_players: u64,
+    pub stats: RunStats,
+
+    /// Stats panel hidden to give the logs its rows (toggled with `s`).
+    pub stats_hidden: bool,
 
     // Logs
     pub logs: Vec<String>,
     pub log_scroll: usize,
+    /// Log lines that fit in the log panel, as of the last render.
+    pub last_log_height: usize,
+    /// Most log lines kept; older ones are dropped so long runs do not
+    /// grow memory without bound.
+    pub max_log_lines: usize,
 
     // Communication
     pub event_rx: Option<mpsc::Receiver<UiEvent>>,
@@ -95,41 +137,109 @@ impl App {
         Self {
             screen: Screen::Config,
             fields: vec![
-                ConfigField { label: "Event Filter", value: "Rated Blitz game".into(), hint: "e.g. Rated Blitz game" },
-                ConfigField { label: "Time Control", value: "300+0".into(), hint: "empty = any, e.g. 300+0" },
-                ConfigField { label: "Min Full Moves", value: "30".into(), hint: "30 = 60 half-moves" },
-                ConfigField { label: "Min Games/Month", value: "25".into(), hint: "per player per month" },
-                ConfigField { label: "Min Games Total", value: "100".into(), hint: "across all datasets" },
-                ConfigField { label: "Dataset Start", value: "2025-01".into(), hint: "YYYY-MM" },
-                ConfigField { label: "Dataset End", value: "2025-12".into(), hint: "YYYY-MM" },
-                ConfigField { label: "Output Directory", value: r"D:\pgn_output".into(), hint: "must have enough space" },
-                ConfigField { label: "Buffer Size (GB)", value: "2.0".into(), hint: "RAM buffer before flush" },
+                ConfigField { label: "Event Filter", value: "Rated Blitz game".into(), hint: "empty = any, e.g. Rated Blitz game", kind: FieldKind::Text, check: unchecked },
+                ConfigField { label: "Time Control", value: "300+0".into(), hint: "empty = any, e.g. 300+0", kind: FieldKind::Text, check: unchecked },
+                ConfigField { label: "Min Full Moves", value: "30".into(), hint: "30 = 60 half-moves", kind: FieldKind::Text, check: |v| whole_number::<u32>(v, 0) },
+                ConfigField { label: "Min Games/Month", value: "25".into(), hint: "per player per month", kind: FieldKind::Text, check: |v| whole_number::<u32>(v, 0) },
+                ConfigField { label: "Min Games Total", value: "100".into(), hint: "across all datasets", kind: FieldKind::Text, check: |v| whole_number::<u32>(v, 0) },
+                ConfigField { label: "Dataset Start", value: "2025-01".into(), hint: "YYYY-MM", kind: FieldKind::Text, check: |v| parse_month(v).err() },
+                ConfigField { label: "Dataset End", value: "2025-12".into(), hint: "YYYY-MM", kind: FieldKind::Text, check: |v| parse_month(v).err() },
+                ConfigField { label: "Output Directory", value: r"D:\pgn_output".into(), hint: "must have enough space", kind: FieldKind::Text, check: unchecked },
+                ConfigField { label: "Buffer Size (GB)", value: "2.0".into(), hint: "RAM buffer before flush, GB or % of RAM", kind: FieldKind::Text, check: |v| parse_buffer_size(v, total_ram_bytes()).err() },
+                ConfigField { label: "Exclude Bots", value: "no".into(), hint: "skip games with a BOT player", kind: FieldKind::Toggle, check: unchecked },
+                ConfigField { label: "Required Titles", value: String::new(), hint: "empty = any, e.g. GM,IM", kind: FieldKind::Text, check: unchecked },
+                ConfigField { label: "Date From", value: String::new(), hint: "empty = any, YYYY-MM-DD", kind: FieldKind::Text, check: |v| parse_date(v).err() },
+                ConfigField { label: "Date To", value: String::new(), hint: "empty = any, YYYY-MM-DD", kind: FieldKind::Text, check: |v| parse_date(v).err() },
+                ConfigField { label: "Move Prefix", value: String::new(), hint: "empty = any, e.g. 1. e4 c5 2. Nf3", kind: FieldKind::Text, check: |v| (!v.is_empty() && parser::san_moves(v).is_empty()).then(|| "no moves, e.g. 1. e4 c5".into()) },
+                ConfigField { label: "Flush Every (s)", value: "0".into(), hint: "0 = only when buffer is full", kind: FieldKind::Text, check: |v| whole_number::<u64>(v, 0) },
+                ConfigField { label: "Parallel Downloads", value: "1".into(), hint: "1 = no prefetch, 2 = one ahead", kind: FieldKind::Text, check: |v| whole_number::<u64>(v, 1) },
+                ConfigField { label: "Max Games/Month", value: String::new(), hint: "empty = no cap, per player", kind: FieldKind::Text, check: |v| optional(v, |v| whole_number::<u32>(v, 0)) },
+                ConfigField { label: "Sample/Player", value: String::new(), hint: "empty = all, random K per month", kind: FieldKind::Text, check: |v| optional(v, |v| whole_number::<u32>(v, 0)) },
+                ConfigField { label: "Sample Seed", value: "0".into(), hint: "same seed = same sample", kind: FieldKind::Text, check: |v| whole_number::<u64>(v, 0) },
+                ConfigField { label: "Max Output (GB)", value: String::new(), hint: "empty = no limit, stops early", kind: FieldKind::Text, check: |v| optional(v, positive_number) },
+                ConfigField { label: "Offline", value: "no".into(), hint: "use cached temp/ files only", kind: FieldKind::Toggle, check: unchecked },
+                ConfigField { label: "Verbatim PGN", value: "no".into(), hint: "keep source bytes exactly", kind: FieldKind::Toggle, check: unchecked },
+                ConfigField { label: "Progress Every", value: "100000".into(), hint: "games between progress updates", kind: FieldKind::Text, check: |v| whole_number::<u64>(v, 1) },
+                ConfigField { label: "Check Every", value: "500000".into(), hint: "games between pause/cancel checks", kind: FieldKind::Text, check: |v| whole_number::<u64>(v, 1) },
+                ConfigField { label: "Min Active Months", value: String::new(), hint: "empty = any, distinct months", kind: FieldKind::Text, check: |v| optional(v, |v| whole_number::<u32>(v, 1)) },
+                ConfigField { label: "User Agent", value: config::default_user_agent(), hint: "sent with every download", kind: FieldKind::Text, check: unchecked },
+                ConfigField { label: "Extra Headers", value: String::new(), hint: "Name: value; Name2: value2", kind: FieldKind::Text, check: |v| parse_headers(v).err() },
+                ConfigField { label: "Proxy", value: String::new(), hint: "empty = *_PROXY env, http://host:port", kind: FieldKind::Text, check: unchecked },
+                ConfigField { label: "Max Download (MB/s)", value: String::new(), hint: "empty = unlimited, all downloads", kind: FieldKind::Text, check: |v| optional(v, positive_number) },
+                ConfigField { label: "Per-Month Folders", value: "no".into(), hint: "players/<YYYY-MM>/<name>.pgn.zst", kind: FieldKind::Toggle, check: unchecked },
+                ConfigField { label: "Zstd Window Log", value: "31".into(), hint: "max decoder window, 10-31", kind: FieldKind::Text, check: zstd_window_log },
+                ConfigField { label: "Decode Threads", value: "1".into(), hint: "2 = decode beside the parser", kind: FieldKind::Text, check: |v| whole_number::<u64>(v, 1) },
+                ConfigField { label: "Rated/Casual", value: "any".into(), hint: "any, rated or casual (Event prefix)", kind: FieldKind::Text, check: |v| parse_rated(v).err() },
+                ConfigField { label: "Read Buffer (KB)", value: "256".into(), hint: "decoder → parser buffer", kind: FieldKind::Text, check: |v| whole_number::<u64>(v, 1) },
+                ConfigField { label: "Download Buf (KB)", value: "64".into(), hint: "bytes per network read", kind: FieldKind::Text, check: |v| whole_number::<u64>(v, 1) },
+                ConfigField { label: "Min Avg Half-Moves", value: String::new(), hint: "empty = any, per player", kind: FieldKind::Text, check: |v| optional(v, positive_number) },
+                ConfigField { label: "Lowercase Names", value: "no".into(), hint: "lowercase output file names", kind: FieldKind::Toggle, check: unchecked },
+                ConfigField { label: "Max Datasets", value: String::new(), hint: "empty = all, first N months", kind: FieldKind::Text, check: |v| optional(v, |v| whole_number::<usize>(v, 1)) },
+                ConfigField { label: "Archive", value: "none".into(), hint: "none, tar or tar.zst after the run", kind: FieldKind::Text, check: |v| parse_archive(v).err() },
+                ConfigField { label: "Event Regex", value: String::new(), hint: "empty = off, whole Event, overrides filter", kind: FieldKind::Text, check: |v| optional(v, |v| pipeline::event_regex(v).err().map(|_| "invalid regex".into())) },
+                ConfigField { label: "Tag Source", value: "no".into(), hint: "add a RooktSource header per game", kind: FieldKind::Toggle, check: unchecked },
+                ConfigField { label: "Two-Phase", value: "no".into(), hint: "count all months, then extract", kind: FieldKind::Toggle, check: unchecked },
+                ConfigField { label: "Temp Directory", value: String::new(), hint: "empty = <output>/temp, e.g. a fast SSD", kind: FieldKind::Text, check: unchecked },
+                ConfigField { label: "W/D/L Records", value: "no".into(), hint: "store wins/draws/losses per player", kind: FieldKind::Toggle, check: unchecked },
+                ConfigField { label: "Memory Ceiling (GB)", value: String::new(), hint: "empty = off, flush early near it", kind: FieldKind::Text, check: |v| optional(v, positive_number) },
+                ConfigField { label: "Qualifying Log", value: String::new(), hint: "empty = off, NDJSON file per dataset", kind: FieldKind::Text, check: unchecked },
+                ConfigField { label: "Both Qualify", value: "no".into(), hint: "only games where both players qualify", kind: FieldKind::Toggle, check: unchecked },
+                ConfigField { label: "Standard Start", value: "no".into(), hint: "skip games with a custom FEN", kind: FieldKind::Toggle, check: unchecked },
+                ConfigField { label: "Group By", value: "player".into(), hint: "player, or team (WhiteTeam/BlackTeam)", kind: FieldKind::Text, check: |v| parse_group_by(v).err() },
+                ConfigField { label: "Sample Datasets", value: String::new(), hint: "empty = all, N random months by seed", kind: FieldKind::Text, check: |v| optional(v, |v| whole_number::<usize>(v, 1)) },
+                ConfigField { label: "Min Final Clock (s)", value: String::new(), hint: "empty = any, drop both-sides-below", kind: FieldKind::Text, check: |v| optional(v, |v| whole_number::<u32>(v, 0)) },
+                ConfigField { label: "Max Final Clock (s)", value: String::new(), hint: "empty = any, keep only both-at-or-below", kind: FieldKind::Text, check: |v| optional(v, |v| whole_number::<u32>(v, 0)) },
+                ConfigField { label: "Elo Bucket Width", value: String::new(), hint: "empty = off, players/1200-1399/ by average Elo", kind: FieldKind::Text, check: |v| optional(v, |v| whole_number::<u32>(v, 1)) },
+                ConfigField { label: "Decode Retries", value: "2".into(), hint: "restarts of a pass after a read error", kind: FieldKind::Text, check: |v| whole_number::<u32>(v, 0) },
+                ConfigField { label: "Min Opponents", value: String::new(), hint: "empty = any, distinct per month", kind: FieldKind::Text, check: |v| optional(v, |v| whole_number::<u32>(v, 1)) },
+                ConfigField { label: "Approx Opponents", value: "no".into(), hint: "32-byte sketch per player, not exact", kind: FieldKind::Toggle, check: unchecked },
+                ConfigField { label: "Memory-Map Input", value: "no".into(), hint: "mmap local inputs (Unix), else buffered", kind: FieldKind::Toggle, check: unchecked },
+                ConfigField { label: "Max Scan Games", value: String::new(), hint: "empty = all, first N games per file", kind: FieldKind::Text, check: |v| optional(v, |v| whole_number::<u64>(v, 1)) },
+                ConfigField { label: "Strip Comments", value: "no".into(), hint: "drop { [%clk] } / { [%eval] } from moves", kind: FieldKind::Toggle, check: unchecked },
+                ConfigField { label: "Any Of", value: String::new(), hint: "empty = all required, e.g. event,time-control", kind: FieldKind::Text, check: |v| config::Criterion::parse_list(v).err().map(|_| "unknown criterion".into()) },
+                ConfigField { label: "Dedup Games", value: "no".into(), hint: "skip games a file has, by Site URL", kind: FieldKind::Toggle, check: unchecked },
+                ConfigField { label: "Track Pairings", value: "no".into(), hint: "games per opponent, for rookt head-to-head", kind: FieldKind::Toggle, check: unchecked },
+                ConfigField { label: "Games Index", value: "none".into(), hint: "none, csv or ndjson list of written games", kind: FieldKind::Text, check: |v| parse_index_format(v).err() },
+                ConfigField { label: "Write Threads", value: "1".into(), hint: "threads compressing files in a flush", kind: FieldKind::Text, check: |v| whole_number::<usize>(v, 1) },
+                ConfigField { label: "Shard Output", value: "yes".into(), hint: "players/<ab>/ folders, no = all in players/", kind: FieldKind::Toggle, check: unchecked },
             ],
             selected: 0,
             editing: false,
             edit_cursor: 0,
+            field_scroll: 0,
             validation_error: None,
+            processed: None,
 
             run_state: RunState::Idle,
             phase: Phase::Downloading,
             current_dataset: 0,
             total_datasets: 0,
             dataset_name: String::new(),
+            two_phase: false,
+            extracting: false,
 
-            dl_total: 0,
+            dl_total: None,
             dl_read: 0,
+            prefetch_totals: HashMap::new(),
+            dataset_sizes: HashMap::new(),
             file_total: 0,
             file_read: 0,
+            file_decoded: 0,
+            file_started: None,
             p1_scanned: 0,
             p1_valid: 0,
             p1_players: 0,
+            top_players: Vec::new(),
             p2_extracted: 0,
-            cum_qualifying: 0,
-            cum_games_saved: 0,
-            final_players: 0,
+            p2_bytes: 0,
+            stats: RunStats::default(),
+
+            stats_hidden: false,
 
             logs: Vec::new(),
             log_scroll: 0,
+            last_log_height: 10,
+            max_log_lines: 5000,
 
             event_rx: None,
             control: None,
@@ -146,54 +256,235 @@ impl App {
         self.selected == self.fields.len()
     }
 
+    /// Path of the progress snapshot for the currently configured output dir.
+    pub fn snapshot_path(&self) -> PathBuf {
+        PathBuf::from(self.fields[7].value.trim()).join(ProgressSnapshot::FILE_NAME)
+    }
+
+    /// Months the index in the configured output dir has already processed,
+    /// which a run will skip; empty without an index. Read-only, and only
+    /// re-read when the output dir changes.
+    pub fn processed_months(&mut self) -> &[String] {
+        let db_path = PathBuf::from(self.fields[7].value.trim()).join("index.db");
+        if self.processed.as_ref().is_none_or(|(path, _)| *path != db_path) {
+            let datasets = match db_path.is_file() {
+                true => Database::open_read_only(&db_path).and_then(|db| db.list_processed()).unwrap_or_default(),
+                false => Vec::new(),
+            };
+            let months = datasets.iter().map(|(url, _)| pipeline::extract_month(url)).collect();
+            self.processed = Some((db_path, months));
+        }
+        &self.processed.as_ref().unwrap().1
+    }
+
+    /// Load the last run's snapshot and show it read-only on the dashboard.
+    pub fn load_snapshot(&mut self) -> Result<(), String> {
+        let path = self.snapshot_path();
+        let snapshot = ProgressSnapshot::load(&path).map_err(|e| e.to_string())?;
+        self.stats = snapshot.stats;
+        self.phase = snapshot.phase;
+        self.current_dataset = snapshot.dataset_index;
+        self.total_datasets = snapshot.total_datasets;
+        self.dataset_name = snapshot.dataset_name;
+        self.run_state = RunState::Idle;
+        self.screen = Screen::Dashboard;
+        self.add_log(format!("Loaded last snapshot from {}", path.display()));
+        Ok(())
+    }
+
+    /// Format problem with field `index`'s current value, if any. A cheap
+    /// per-field check for live feedback while typing; rules spanning
+    /// several fields (start before end) are left to `build_config`.
+    pub fn field_error(&self, index: usize) -> Option<String> {
+        let field = &self.fields[index];
+        (field.check)(field.value.trim())
+    }
+
+    /// Whether any field currently fails `field_error` (Start is disabled).
+    pub fn has_invalid_fields(&self) -> bool {
+        (0..self.fields.len()).any(|i| self.field_error(i).is_some())
+    }
+
+    /// Flip the selected toggle field between "yes" and "no".
+    pub fn toggle_selected(&mut self) {
+        let field = &mut self.fields[self.selected];
+        field.value = if field.value == "yes" { "no".into() } else { "yes".into() };
+    }
+
+    /// Append a log line. The view follows new lines while it is at the
+    /// bottom; scrolled up, it keeps showing the same lines.
     pub fn add_log(&mut self, msg: String) {
+        let following = self.log_scroll >= bottom_scroll(self.logs.len(), self.last_log_height);
         self.logs.push(msg);
-        // Auto-scroll to bottom
-        let visible = 10usize; // approximate visible log lines
-        if self.logs.len() > visible {
-            self.log_scroll = self.logs.len() - visible;
-        }
+        let excess = self.logs.len().saturating_sub(self.max_log_lines.max(1));
+        self.logs.drain(..excess);
+        self.log_scroll = if following {
+            bottom_scroll(self.logs.len(), self.last_log_height)
+        } else {
+            self.log_scroll.saturating_sub(excess)
+        };
     }
 
     fn reset_dataset_stats(&mut self) {
-        self.dl_total = 0;
+        self.dl_total = None;
         self.dl_read = 0;
-        self.file_total = 0;
-        self.file_read = 0;
+        self.reset_file_progress();
         self.p1_scanned = 0;
         self.p1_valid = 0;
         self.p1_players = 0;
+        self.top_players.clear();
         self.p2_extracted = 0;
+        self.p2_bytes = 0;
+    }
+
+    fn reset_file_progress(&mut self) {
+        self.file_total = 0;
+        self.file_read = 0;
+        self.file_decoded = 0;
+        self.file_started = Some(Instant::now());
+    }
+
+    /// Remember a dataset's compressed size (0 = unknown, e.g. no
+    /// content-length).
+    fn record_size(&mut self, index: usize, bytes: u64) {
+        if bytes > 0 {
+            self.dataset_sizes.insert(index, bytes);
+        }
+    }
+
+    /// Run progress weighted by compressed dataset size, so a large month
+    /// moves the gauge more than a small one. Datasets of unknown size count
+    /// as the average known size; with no sizes at all this is the plain
+    /// dataset-count ratio.
+    /// A two-phase run goes over the datasets twice, counting then
+    /// extracting, and each round is half the gauge.
+    pub fn overall_ratio(&self) -> f64 {
+        if self.total_datasets == 0 {
+            return 0.0;
+        }
+        if !self.two_phase {
+            return self.round_ratio();
+        }
+        match self.phase {
+            Phase::Pruning | Phase::Done => 1.0,
+            _ => (self.extracting as u8 as f64 + self.round_ratio()) / 2.0,
+        }
+    }
+
+    /// Progress through the datasets in the current round.
+    fn round_ratio(&self) -> f64 {
+        if self.dataset_sizes.is_empty() {
+            return (self.current_dataset as f64 / self.total_datasets as f64).min(1.0);
+        }
+        let mean = self.dataset_sizes.values().sum::<u64>() as f64 / self.dataset_sizes.len() as f64;
+        let size = |i: usize| self.dataset_sizes.get(&i).map_or(mean, |&s| s as f64);
+        let total: f64 = (0..self.total_datasets).map(size).sum();
+        let done: f64 = (0..self.current_dataset).map(size).sum::<f64>()
+            + size(self.current_dataset) * self.dataset_fraction();
+        (done / total).min(1.0)
+    }
+
+    /// Share of the current dataset processed: each pass reads the file
+    /// once, so pass 1 covers the first half and pass 2 the second (or all
+    /// of it in a two-phase round).
+    fn dataset_fraction(&self) -> f64 {
+        let file = if self.file_total > 0 {
+            (self.file_read as f64 / self.file_total as f64).min(1.0)
+        } else {
+            0.0
+        };
+        match self.phase {
+            Phase::Downloading | Phase::Preparing => 0.0,
+            Phase::Pass1 | Phase::Pass2 if self.two_phase => file,
+            Phase::Pass1 => file / 2.0,
+            Phase::Pass2 => 0.5 + file / 2.0,
+            Phase::Pruning | Phase::Done => 1.0,
+        }
+    }
+
+    /// Compressed output written this run, including the pass 2 in progress.
+    pub fn output_bytes(&self) -> u64 {
+        self.stats.output_bytes + self.p2_bytes
+    }
+
+    /// Decompressed PGN throughput of the current pass, in bytes per second.
+    pub fn decoded_rate(&self) -> Option<f64> {
+        let secs = self.file_started?.elapsed().as_secs_f64();
+        (secs > 0.0 && self.file_decoded > 0).then(|| self.file_decoded as f64 / secs)
     }
 
     /// Process a pipeline event.
     pub fn handle_event(&mut self, event: UiEvent) {
+        self.stats.record(&event);
         match event {
             UiEvent::Log(msg) => self.add_log(msg),
 
+            UiEvent::DatasetSizes(sizes) => {
+                let known: Vec<u64> = sizes.iter().flatten().copied().collect();
+                self.add_log(format!(
+                    "Known sizes: {:.2} GB for {}/{} datasets",
+                    known.iter().sum::<u64>() as f64 / 1_073_741_824.0,
+                    known.len(),
+                    sizes.len()
+                ));
+                for (index, size) in sizes.into_iter().enumerate() {
+                    self.record_size(index, size.unwrap_or(0));
+                }
+            }
             UiEvent::DatasetStarted { index, total, name } => {
                 self.current_dataset = index;
                 self.total_datasets = total;
                 self.dataset_name = name.clone();
                 self.reset_dataset_stats();
+                // A prefetch may already be downloading this dataset
+                if let Some(total_bytes) = self.prefetch_totals.remove(&index) {
+                    self.phase = Phase::Downloading;
+                    self.dl_total = total_bytes;
+                }
                 self.add_log(format!("[{}/{}] Started: {}", index + 1, total, name));
             }
             UiEvent::DatasetSkipped { name } => {
                 self.add_log(format!("Skipped (already done): {}", name));
             }
-            UiEvent::DatasetComplete => {
-                self.add_log("Dataset complete.".into());
+            UiEvent::DatasetComplete { qualifying_players, games_extracted, duration } => {
+                self.add_log(format!(
+                    "Complete: {}: {} qualifying players, {} games extracted in {}",
+                    self.dataset_name,
+                    qualifying_players,
+                    games_extracted,
+                    fmt_duration(duration)
+                ));
+            }
+            UiEvent::DatasetFailed { name, error } => {
+                self.add_log(format!("ERROR: {} failed, continuing: {}", name, error));
+            }
+
+            UiEvent::DownloadStarted { index, total_bytes } if index != self.current_dataset => {
+                self.prefetch_totals.insert(index, total_bytes);
+                self.record_size(index, total_bytes.unwrap_or(0));
+            }
+            UiEvent::DownloadProgress { index, .. } if index != self.current_dataset => {}
+            UiEvent::DownloadComplete { index, size_bytes } if index != self.current_dataset => {
+                self.prefetch_totals.remove(&index);
+                self.record_size(index, size_bytes);
+                self.add_log(format!(
+                    "Prefetched dataset {} ({:.2} GB)",
+                    index + 1,
+                    size_bytes as f64 / 1_073_741_824.0
+                ));
             }
 
-            UiEvent::DownloadStarted { total_bytes } => {
+            UiEvent::DownloadStarted { index, total_bytes } => {
+                self.record_size(index, total_bytes.unwrap_or(0));
                 self.phase = Phase::Downloading;
                 self.dl_total = total_bytes;
                 self.dl_read = 0;
             }
-            UiEvent::DownloadProgress { bytes_read } => {
+            UiEvent::DownloadProgress { bytes_read, .. } => {
                 self.dl_read = bytes_read;
             }
-            UiEvent::DownloadComplete { size_bytes } => {
+            UiEvent::DownloadComplete { index, size_bytes } => {
+                self.record_size(index, size_bytes);
                 self.dl_read = size_bytes;
                 self.add_log(format!(
                     "Download complete ({:.2} GB)",
@@ -201,15 +492,19 @@ impl App {
                 ));
             }
 
-            UiEvent::FileProgress { bytes_read, total_bytes } => {
+            UiEvent::FileProgress { bytes_read, total_bytes, decoded_bytes } => {
                 self.file_read = bytes_read;
                 self.file_total = total_bytes;
+                self.file_decoded = decoded_bytes;
+                self.record_size(self.current_dataset, total_bytes);
             }
 
+            UiEvent::Preparing => {
+                self.phase = Phase::Preparing;
+            }
             UiEvent::Pass1Started => {
                 self.phase = Phase::Pass1;
-                self.file_read = 0;
-                self.file_total = 0;
+                self.reset_file_progress();
                 self.add_log("Pass 1: Counting games...".into());
             }
             UiEvent::Pass1Progress { games_scanned, valid_games, unique_players } => {
@@ -217,11 +512,13 @@ impl App {
                 self.p1_valid = valid_games;
                 self.p1_players = unique_players;
             }
+            UiEvent::TopPlayers(top) => {
+                self.top_players = top;
+            }
             UiEvent::Pass1Complete { total_scanned, valid_games, qualifying_players, qualifying_games } => {
                 self.p1_scanned = total_scanned;
                 self.p1_valid = valid_games;
                 self.p1_players = qualifying_players;
-                self.cum_qualifying += qualifying_players;
                 self.add_log(format!(
                     "Pass 1 done: {} scanned, {} valid, {} qualifying ({} games)",
                     fmt_count(total_scanned), fmt_count(valid_games),
@@ -229,18 +526,29 @@ impl App {
                 ));
             }
 
+            UiEvent::CountingComplete { datasets, qualifying_players } => {
+                self.extracting = true;
+                self.add_log(format!(
+                    "Counting done: {} players qualify across {} datasets",
+                    fmt_count(qualifying_players), datasets,
+                ));
+            }
+
             UiEvent::Pass2Started => {
                 self.phase = Phase::Pass2;
-                self.file_read = 0;
-                self.file_total = 0;
+                self.reset_file_progress();
                 self.add_log("Pass 2: Extracting games...".into());
             }
             UiEvent::Pass2Progress { games_extracted } => {
                 self.p2_extracted = games_extracted;
             }
-            UiEvent::Pass2Complete { total_extracted } => {
+            UiEvent::WriteProgress { bytes_written } => {
+                self.p2_bytes = bytes_written;
+            }
+            UiEvent::Pass2Complete { total_extracted, .. } => {
                 self.p2_extracted = total_extracted;
-                self.cum_games_saved += total_extracted;
+                // Now counted in the run totals
+                self.p2_bytes = 0;
                 self.add_log(format!("Pass 2 done: {} entries extracted", fmt_count(total_extracted)));
             }
 
@@ -249,7 +557,6 @@ impl App {
                 self.add_log(format!("Pruning {} players below threshold...", fmt_count(to_remove)));
             }
             UiEvent::PruneComplete { remaining, removed } => {
-                self.final_players = remaining;
                 self.add_log(format!(
                     "Prune done: {} removed, {} remaining",
                     fmt_count(removed), fmt_count(remaining),
@@ -262,6 +569,20 @@ impl App {
                 self.add_log("=== Pipeline finished ===".into());
             }
 
+            UiEvent::Paused => {
+                // Ignored if resumed before the pipeline got there
+                if self.run_state == RunState::Pausing {
+                    self.run_state = RunState::Paused;
+                }
+                self.add_log("Paused".into());
+            }
+            UiEvent::Resumed => self.add_log("Resumed".into()),
+
+            UiEvent::Cancelled => {
+                self.run_state = RunState::Cancelled;
+                self.add_log("=== Pipeline cancelled ===".into());
+            }
+
             UiEvent::Error(msg) => {
                 self.run_state = RunState::Error(msg.clone());
                 self.add_log(format!("ERROR: {}", msg));
@@ -271,10 +592,19 @@ impl App {
 
     /// Validate config fields and build a Config struct.
     pub fn build_config(&self) -> Result<Config, String> {
-        let event_filter = self.fields[0].value.clone();
-        if event_filter.is_empty() {
-            return Err("Event filter cannot be empty".into());
-        }
+        let event_filter = self.fields[0].value.trim().to_string();
+        let rated_filter = parse_rated(&self.fields[32].value)?;
+        let archive = parse_archive(&self.fields[38].value)?;
+        let games_index = parse_index_format(&self.fields[62].value)?;
+        let group_by = parse_group_by(&self.fields[48].value)?;
+        let any_of = config::Criterion::parse_list(&self.fields[59].value).map_err(|e| format!("{:#}", e))?;
+        let event_filter_regex = match self.fields[39].value.trim() {
+            "" => None,
+            v => {
+                pipeline::event_regex(v).map_err(|e| format!("{:#}", e))?;
+                Some(v.to_string())
+            }
+        };
 
         let time_control = if self.fields[1].value.trim().is_empty() {
             None
@@ -296,31 +626,286 @@ impl App {
         }
 
         let output_dir = PathBuf::from(self.fields[7].value.trim());
-        let buffer_gb: f64 = self.fields[8].value.trim().parse()
-            .map_err(|_| "Buffer size must be a number")?;
-        if buffer_gb <= 0.0 {
-            return Err("Buffer size must be positive".into());
+        let temp_dir = match self.fields[42].value.trim() {
+            "" => output_dir.join("temp"),
+            v => PathBuf::from(v),
+        };
+        writable_dir(&output_dir, "Output directory")?;
+        writable_dir(&temp_dir, "Temp directory")?;
+        let write_buffer_max_bytes = parse_buffer_size(&self.fields[8].value, total_ram_bytes())?;
+
+        let exclude_bots = self.fields[9].value == "yes";
+        let require_titles = parse_list(&self.fields[10].value);
+
+        let date_from = parse_date(&self.fields[11].value)?;
+        let date_to = parse_date(&self.fields[12].value)?;
+        if let (Some(from), Some(to)) = (&date_from, &date_to)
+            && from > to
+        {
+            return Err("Date from must be before or equal to date to".into());
+        }
+
+        let move_prefix = if self.fields[13].value.trim().is_empty() {
+            None
+        } else {
+            let prefix = self.fields[13].value.trim();
+            if parser::san_moves(prefix).is_empty() {
+                return Err("Move prefix has no moves, e.g. 1. e4 c5".into());
+            }
+            Some(prefix.to_string())
+        };
+
+        let flush_secs: u64 = self.fields[14].value.trim().parse()
+            .map_err(|_| "Flush interval must be a non-negative integer (seconds)")?;
+        let flush_interval = (flush_secs > 0).then(|| Duration::from_secs(flush_secs));
+
+        let download_concurrency: usize = self.fields[15].value.trim().parse()
+            .map_err(|_| "Parallel downloads must be a positive integer")?;
+        if download_concurrency == 0 {
+            return Err("Parallel downloads must be at least 1".into());
+        }
+
+        let max_monthly_games: Option<u32> = match self.fields[16].value.trim() {
+            "" => None,
+            v => Some(v.parse().map_err(|_| "Max games/month must be a positive integer")?),
+        };
+
+        let sample_per_player: Option<u32> = match self.fields[17].value.trim() {
+            "" => None,
+            v => Some(v.parse().map_err(|_| "Sample/player must be a positive integer")?),
+        };
+        let sample_seed: u64 = self.fields[18].value.trim().parse()
+            .map_err(|_| "Sample seed must be a non-negative integer")?;
+
+        let max_output_bytes = match self.fields[19].value.trim() {
+            "" => None,
+            v => {
+                let gb: f64 = v.parse().map_err(|_| "Max output must be a number (GB)")?;
+                if gb <= 0.0 {
+                    return Err("Max output must be positive".into());
+                }
+                Some((gb * 1_073_741_824.0) as u64)
+            }
+        };
+
+        let memory_ceiling_bytes = match self.fields[44].value.trim() {
+            "" => None,
+            v => {
+                let gb: f64 = v.parse().map_err(|_| "Memory ceiling must be a number (GB)")?;
+                if gb <= 0.0 {
+                    return Err("Memory ceiling must be positive".into());
+                }
+                Some((gb * 1_073_741_824.0) as u64)
+            }
+        };
+
+        let progress_interval_games: u64 = self.fields[22].value.trim().parse()
+            .map_err(|_| "Progress every must be a positive integer (games)")?;
+        let check_interval_games: u64 = self.fields[23].value.trim().parse()
+            .map_err(|_| "Check every must be a positive integer (games)")?;
+        if progress_interval_games == 0 || check_interval_games == 0 {
+            return Err("Progress and check intervals must be at least 1".into());
+        }
+
+        let min_active_months: Option<u32> = match self.fields[24].value.trim() {
+            "" => None,
+            v => Some(v.parse().ok().filter(|n| *n > 0).ok_or("Min active months must be a positive integer")?),
+        };
+
+        let max_datasets: Option<usize> = match self.fields[37].value.trim() {
+            "" => None,
+            v => match v.parse() {
+                Ok(n) if n > 0 => Some(n),
+                _ => return Err("Max datasets must be a positive integer".into()),
+            },
+        };
+
+        let min_final_clock: Option<u32> = match self.fields[50].value.trim() {
+            "" => None,
+            v => Some(v.parse().map_err(|_| "Min final clock must be a non-negative integer (seconds)")?),
+        };
+        let max_final_clock: Option<u32> = match self.fields[51].value.trim() {
+            "" => None,
+            v => Some(v.parse().map_err(|_| "Max final clock must be a non-negative integer (seconds)")?),
+        };
+        if let (Some(min), Some(max)) = (min_final_clock, max_final_clock)
+            && min > max
+        {
+            return Err("Min final clock must be at most max final clock".into());
+        }
+
+        let output_layout = match (self.fields[52].value.trim(), self.fields[29].value == "yes") {
+            ("", false) => OutputLayout::PerPlayer,
+            ("", true) => OutputLayout::PerPlayerPerMonth,
+            (_, true) => return Err("Elo buckets and per-month folders cannot be combined".into()),
+            (v, false) => match v.parse() {
+                Ok(width) if width > 0 => OutputLayout::ByEloBucket { width },
+                _ => return Err("Elo bucket width must be a positive integer".into()),
+            },
+        };
+
+        let max_scan_games: Option<u64> = match self.fields[57].value.trim() {
+            "" => None,
+            v => match v.parse() {
+                Ok(n) if n > 0 => Some(n),
+                _ => return Err("Max scan games must be a positive integer".into()),
+            },
+        };
+
+        let sample_datasets: Option<usize> = match self.fields[49].value.trim() {
+            "" => None,
+            v => match v.parse() {
+                Ok(n) if n > 0 => Some(n),
+                _ => return Err("Sample datasets must be a positive integer".into()),
+            },
+        };
+
+        let min_avg_half_moves: Option<f64> = match self.fields[35].value.trim() {
+            "" => None,
+            v => match v.parse::<f64>() {
+                Ok(n) if n > 0.0 => Some(n),
+                _ => return Err("Min average half-moves must be a positive number".into()),
+            },
+        };
+
+        let user_agent = self.fields[25].value.trim().to_string();
+        let extra_headers = parse_headers(&self.fields[26].value)?;
+        let proxy = match self.fields[27].value.trim() {
+            "" => None,
+            v => Some(v.to_string()),
+        };
+
+        let max_download_bytes_per_sec = match self.fields[28].value.trim() {
+            "" => None,
+            v => {
+                let mbps: f64 = v.parse().map_err(|_| "Max download must be a number (MB/s)")?;
+                if mbps <= 0.0 {
+                    return Err("Max download must be positive".into());
+                }
+                Some((mbps * 1_048_576.0) as u64)
+            }
+        };
+
+        let zstd_window_log_max: u32 = match self.fields[30].value.trim().parse() {
+            Ok(log @ 10..=31) => log,
+            _ => return Err("Zstd window log must be between 10 and 31".into()),
+        };
+
+        let decode_threads: usize = self.fields[31].value.trim().parse()
+            .map_err(|_| "Decode threads must be a positive integer")?;
+        if decode_threads == 0 {
+            return Err("Decode threads must be at least 1".into());
+        }
+
+        let write_threads: usize = self.fields[63].value.trim().parse()
+            .map_err(|_| "Write threads must be a positive integer")?;
+        if write_threads == 0 {
+            return Err("Write threads must be at least 1".into());
         }
 
+        let read_buffer_kb: usize = self.fields[33].value.trim().parse()
+            .map_err(|_| "Read buffer must be a positive integer (KB)")?;
+        let download_buffer_kb: usize = self.fields[34].value.trim().parse()
+            .map_err(|_| "Download buffer must be a positive integer (KB)")?;
+        if read_buffer_kb == 0 || download_buffer_kb == 0 {
+            return Err("Read and download buffers must be at least 1 KB".into());
+        }
+
+        let min_unique_opponents: Option<u32> = match self.fields[54].value.trim() {
+            "" => None,
+            v => match v.parse() {
+                Ok(n) if n > 0 => Some(n),
+                _ => return Err("Min opponents must be a positive integer".into()),
+            },
+        };
+
+        let decode_retries: u32 = self.fields[53].value.trim().parse()
+            .map_err(|_| "Decode retries must be a non-negative integer")?;
+
         let urls = generate_urls(start, end);
 
         Ok(Config {
             dataset_urls: urls,
-            temp_dir: output_dir.join("temp"),
+            max_datasets,
+            sample_datasets,
+            max_scan_games,
+            min_final_clock,
+            max_final_clock,
+            any_of,
+            temp_dir,
             db_path: output_dir.join("index.db"),
+            qualifying_log: match self.fields[45].value.trim() {
+                "" => None,
+                v => Some(PathBuf::from(v)),
+            },
             output_dir: output_dir.clone(),
             event_filter,
+            event_filter_regex,
+            rated_filter,
             time_control_filter: time_control,
+            exclude_bots,
+            exclude_non_standard_start: self.fields[47].value == "yes",
+            require_titles,
+            date_from,
+            date_to,
+            move_prefix,
             min_full_moves,
             min_monthly_games,
+            min_avg_half_moves,
+            min_unique_opponents,
+            approximate_opponents: self.fields[55].value == "yes",
+            lowercase_filenames: self.fields[36].value == "yes",
+            shard_output: self.fields[64].value == "yes",
+            archive,
+            max_monthly_games,
+            sample_per_player,
+            sample_seed,
+            max_output_bytes,
+            group_by,
+            output_layout,
+            verbatim_pgn: self.fields[21].value == "yes",
+            tag_source: self.fields[40].value == "yes",
+            strip_comments: self.fields[58].value == "yes",
+            dedup_games: self.fields[60].value == "yes",
+            two_phase: self.fields[41].value == "yes",
+            record_results: self.fields[43].value == "yes",
+            track_pairings: self.fields[61].value == "yes",
+            games_index,
+            require_both_players: self.fields[46].value == "yes",
             min_total_games,
-            write_buffer_max_bytes: (buffer_gb * 1_073_741_824.0) as usize,
+            min_active_months,
+            write_buffer_max_bytes,
+            memory_ceiling_bytes,
+            flush_interval,
+            progress_interval_games,
+            check_interval_games,
+            user_agent,
+            extra_headers,
+            proxy,
+            max_download_bytes_per_sec,
+            zstd_window_log_max,
+            decode_threads,
+            write_threads,
+            read_buffer_bytes: read_buffer_kb * 1024,
+            use_mmap: self.fields[56].value == "yes",
+            decode_retries,
+            download_buffer_bytes: download_buffer_kb * 1024,
+            download_concurrency,
+            offline: self.fields[20].value == "yes",
+            fail_fast: false,
+            strict: false,
         })
     }
 }
 
 // ── Helpers ─────────────────────────────────────────────────────────────────
 
+/// First log line to show so the last of `total` lines sits at the bottom
+/// of a panel `visible` lines tall.
+fn bottom_scroll(total: usize, visible: usize) -> usize {
+    total.saturating_sub(visible)
+}
+
 fn parse_month(s: &str) -> Result<(u32, u32), String> {
     let parts: Vec<&str> = s.trim().split('-').collect();
     if parts.len() != 2 {
@@ -334,6 +919,170 @@ fn parse_month(s: &str) -> Result<(u32, u32), String> {
     Ok((year, month))
 }
 
+/// Parse the rated/casual selector: `any` (or empty), `rated`, `casual`.
+fn parse_rated(s: &str) -> Result<Option<bool>, String> {
+    match s.trim().to_ascii_lowercase().as_str() {
+        "" | "any" => Ok(None),
+        "rated" => Ok(Some(true)),
+        "casual" => Ok(Some(false)),
+        _ => Err("expected any, rated or casual".into()),
+    }
+}
+
+/// Create `dir` if needed and check that a file can be written in it.
+fn writable_dir(dir: &Path, label: &str) -> Result<(), String> {
+    if dir.as_os_str().is_empty() {
+        return Err(format!("{} must not be empty", label));
+    }
+    let probe = dir.join(".rookt_write_test");
+    std::fs::create_dir_all(dir)
+        .and_then(|_| std::fs::write(&probe, b""))
+        .and_then(|_| std::fs::remove_file(&probe))
+        .map_err(|e| format!("{} {} is not writable: {}", label, dir.display(), e))
+}
+
+/// Parse the games index selector: `none` (or empty), `csv`, `ndjson`.
+fn parse_index_format(s: &str) -> Result<Option<IndexFormat>, String> {
+    match s.trim().to_ascii_lowercase().as_str() {
+        "" | "none" => Ok(None),
+        "csv" => Ok(Some(IndexFormat::Csv)),
+        "ndjson" => Ok(Some(IndexFormat::Ndjson)),
+        _ => Err("expected none, csv or ndjson".into()),
+    }
+}
+
+/// Parse the archive selector: `none` (or empty), `tar`, `tar.zst`.
+fn parse_archive(s: &str) -> Result<Option<ArchiveFormat>, String> {
+    match s.trim().to_ascii_lowercase().as_str() {
+        "" | "none" => Ok(None),
+        "tar" => Ok(Some(ArchiveFormat::Tar)),
+        "tar.zst" => Ok(Some(ArchiveFormat::TarZst)),
+        _ => Err("expected none, tar or tar.zst".into()),
+    }
+}
+
+/// Parse the grouping selector: `player` (or empty) or `team`.
+fn parse_group_by(s: &str) -> Result<GroupBy, String> {
+    match s.trim().to_ascii_lowercase().as_str() {
+        "" | "player" => Ok(GroupBy::Player),
+        "team" => Ok(GroupBy::Team),
+        _ => Err("expected player or team".into()),
+    }
+}
+
+/// Parse an optional `YYYY-MM-DD` (or `YYYY.MM.DD`) date into PGN's
+/// `YYYY.MM.DD` form. Empty input means no bound.
+fn parse_date(s: &str) -> Result<Option<String>, String> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Ok(None);
+    }
+    let parts: Vec<&str> = s.split(['-', '.']).collect();
+    let invalid = || format!("Invalid date '{}', expected YYYY-MM-DD", s);
+    if parts.len() != 3 || parts[0].len() != 4 || parts[1].len() != 2 || parts[2].len() != 2 {
+        return Err(invalid());
+    }
+    let year: u32 = parts[0].parse().map_err(|_| invalid())?;
+    let month: u32 = parts[1].parse().map_err(|_| invalid())?;
+    let day: u32 = parts[2].parse().map_err(|_| invalid())?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return Err(invalid());
+    }
+    Ok(Some(format!("{:04}.{:02}.{:02}", year, month, day)))
+}
+
+/// `None` if `value` is a whole number of type `T` no smaller than `min`.
+fn whole_number<T: std::str::FromStr + PartialOrd + From<u8>>(value: &str, min: u8) -> Option<String> {
+    match value.parse::<T>() {
+        Ok(n) if n >= T::from(min) => None,
+        Ok(_) => Some(format!("must be at least {}", min)),
+        Err(_) => Some("expected a whole number".into()),
+    }
+}
+
+/// `check` of a field with nothing to validate.
+fn unchecked(_: &str) -> Option<String> {
+    None
+}
+
+/// `check` of an optional field: empty passes, anything else must pass `check`.
+fn optional(value: &str, check: impl Fn(&str) -> Option<String>) -> Option<String> {
+    if value.is_empty() { None } else { check(value) }
+}
+
+/// `None` if `value` is a zstd window log the decoder accepts (10-31).
+fn zstd_window_log(value: &str) -> Option<String> {
+    match value.parse::<u32>() {
+        Ok(10..=31) => None,
+        _ => Some("expected 10-31".into()),
+    }
+}
+
+/// `None` if `value` is a number greater than zero.
+fn positive_number(value: &str) -> Option<String> {
+    match value.parse::<f64>() {
+        Ok(n) if n > 0.0 => None,
+        _ => Some("expected a positive number".into()),
+    }
+}
+
+/// Parse the write buffer size: GB (`2.0`) or a percentage of `ram`, the
+/// system's total memory (`50%`). Either must come to at least one byte
+/// and at most `ram`, when known.
+fn parse_buffer_size(value: &str, ram: Option<u64>) -> Result<usize, String> {
+    let value = value.trim();
+    let bytes = match value.strip_suffix('%') {
+        Some(percent) => {
+            let percent: f64 = percent.trim().parse().map_err(|_| "Buffer size must be a number (GB) or a percentage")?;
+            if percent <= 0.0 || percent > 100.0 {
+                return Err("Buffer size percentage must be above 0 and at most 100".into());
+            }
+            let ram = ram.ok_or("Cannot detect system RAM; give the buffer size in GB")?;
+            ram as f64 * percent / 100.0
+        }
+        None => {
+            let gb: f64 = value.parse().map_err(|_| "Buffer size must be a number (GB) or a percentage")?;
+            if gb <= 0.0 {
+                return Err("Buffer size must be positive".into());
+            }
+            gb * 1_073_741_824.0
+        }
+    };
+    if bytes < 1.0 {
+        return Err("Buffer size must be at least one byte".into());
+    }
+    if let Some(ram) = ram
+        && bytes > ram as f64
+    {
+        return Err(format!("Buffer size exceeds system RAM ({:.1} GB)", ram as f64 / 1_073_741_824.0));
+    }
+    Ok(bytes as usize)
+}
+
+/// Split a comma-separated list into trimmed, non-empty items. None if empty.
+fn parse_list(s: &str) -> Option<Vec<String>> {
+    let items: Vec<String> = s
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect();
+    if items.is_empty() { None } else { Some(items) }
+}
+
+/// Parse `Name: value; Name2: value2` into header pairs.
+fn parse_headers(s: &str) -> Result<Vec<(String, String)>, String> {
+    s.split(';')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| match item.split_once(':') {
+            Some((name, value)) if !name.trim().is_empty() => {
+                Ok((name.trim().to_string(), value.trim().to_string()))
+            }
+            _ => Err(format!("Invalid header '{}', expected Name: value", item)),
+        })
+        .collect()
+}
+
 fn generate_urls(start: (u32, u32), end: (u32, u32)) -> Vec<String> {
     let mut urls = Vec::new();
     let (mut y, mut m) = start;
@@ -367,3 +1116,218 @@ pub fn fmt_bytes(n: u64) -> String {
     else if n >= 1024 { format!("{:.1} KB", n as f64 / 1024.0) }
     else { format!("{} B", n) }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auto_scroll_uses_log_height() {
+        assert_eq!(bottom_scroll(5, 10), 0, "everything fits");
+        assert_eq!(bottom_scroll(25, 10), 15);
+        assert_eq!(bottom_scroll(25, 0), 25);
+
+        let mut app = App::new();
+        app.last_log_height = 4;
+        for i in 0..6 {
+            app.add_log(format!("line {}", i));
+        }
+        assert_eq!(app.log_scroll, 2);
+        // A taller panel (bigger terminal) keeps the last line at its bottom
+        app.last_log_height = 30;
+        app.add_log("line 6".into());
+        assert_eq!(app.log_scroll, 0);
+    }
+
+    #[test]
+    fn test_log_buffer_drops_oldest_lines() {
+        let mut app = App::new();
+        app.max_log_lines = 3;
+        app.last_log_height = 2;
+        for i in 0..5 {
+            app.add_log(format!("line {}", i));
+        }
+        assert_eq!(app.logs, ["line 2", "line 3", "line 4"]);
+        assert_eq!(app.log_scroll, 1, "still shows the last two lines");
+
+        // Scrolled up, new lines neither move the view nor change its lines
+        app.log_scroll = 0;
+        app.add_log("line 5".into());
+        assert_eq!(app.logs, ["line 3", "line 4", "line 5"]);
+        assert_eq!(app.log_scroll, 0, "line 2 dropped, line 3 at the top");
+        app.max_log_lines = 10;
+        app.add_log("line 6".into());
+        assert_eq!(app.log_scroll, 0);
+        app.log_scroll = 2;
+        app.add_log("line 7".into());
+        assert_eq!(app.log_scroll, 3, "back at the bottom, following again");
+    }
+
+    #[test]
+    fn test_buffer_size_in_gb_or_percent_of_ram() {
+        let ram = Some(8 * 1_073_741_824);
+        assert_eq!(parse_buffer_size("2.0", ram), Ok(2 * 1_073_741_824));
+        assert_eq!(parse_buffer_size(" 50% ", ram), Ok(4 * 1_073_741_824));
+        assert_eq!(parse_buffer_size("2", None), Ok(2 * 1_073_741_824), "GB need no RAM figure");
+        assert!(parse_buffer_size("50%", None).is_err());
+        assert!(parse_buffer_size("9", ram).unwrap_err().contains("exceeds system RAM"));
+        for invalid in ["0", "0%", "101%", "-5%", "half", "1e-12", "%"] {
+            assert!(parse_buffer_size(invalid, ram).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_temp_dir_separate_from_output() {
+        let base = std::env::temp_dir().join(format!("rookt_test_app_temp_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&base);
+        let mut app = App::new();
+        app.fields[7].value = base.join("out").display().to_string();
+
+        let config = app.build_config().unwrap();
+        assert_eq!(config.temp_dir, base.join("out").join("temp"));
+
+        app.fields[42].value = base.join("scratch").display().to_string();
+        let config = app.build_config().unwrap();
+        assert_eq!(config.temp_dir, base.join("scratch"));
+        assert!(config.temp_dir.is_dir(), "created by the check");
+
+        // A path below a regular file cannot be created
+        std::fs::write(base.join("file"), b"").unwrap();
+        app.fields[42].value = base.join("file").join("temp").display().to_string();
+        assert!(app.build_config().err().unwrap().starts_with("Temp directory"));
+
+        std::fs::remove_dir_all(base).unwrap();
+    }
+
+    #[test]
+    fn test_min_active_months_must_be_positive() {
+        let base = std::env::temp_dir().join(format!("rookt_test_app_active_{}", std::process::id()));
+        let mut app = App::new();
+        app.fields[7].value = base.display().to_string();
+        assert_eq!(app.build_config().unwrap().min_active_months, None);
+
+        app.fields[24].value = "0".into();
+        assert!(app.field_error(24).is_some());
+        assert!(app.build_config().err().unwrap().starts_with("Min active months"));
+        app.fields[24].value = "3".into();
+        assert_eq!(app.field_error(24), None);
+        assert_eq!(app.build_config().unwrap().min_active_months, Some(3));
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+
+    #[test]
+    fn test_final_clock_range_must_not_be_inverted() {
+        let base = std::env::temp_dir().join(format!("rookt_test_app_clock_{}", std::process::id()));
+        let mut app = App::new();
+        app.fields[7].value = base.display().to_string();
+        app.fields[50].value = "60".into();
+        app.fields[51].value = "30".into();
+        assert!(app.build_config().err().unwrap().starts_with("Min final clock must be at most"));
+        app.fields[51].value = "60".into();
+        let config = app.build_config().unwrap();
+        assert_eq!((config.min_final_clock, config.max_final_clock), (Some(60), Some(60)));
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+
+    #[test]
+    fn test_field_checks_agree_with_build_config() {
+        let base = std::env::temp_dir().join(format!("rookt_test_app_checks_{}", std::process::id()));
+        let mut app = App::new();
+        app.fields[7].value = base.display().to_string();
+        assert!(!app.has_invalid_fields());
+        app.build_config().unwrap();
+
+        let paths = [7, 42, 45];
+        for i in 0..app.fields.len() {
+            if app.fields[i].kind == FieldKind::Toggle || paths.contains(&i) {
+                continue;
+            }
+            let default = app.fields[i].value.clone();
+            for value in ["", "0", "1", "-1", "x", "1.5", "2025-13"] {
+                app.fields[i].value = value.into();
+                assert_eq!(
+                    app.field_error(i).is_some(),
+                    app.build_config().is_err(),
+                    "{} = {:?}: {:?}",
+                    app.fields[i].label,
+                    value,
+                    app.build_config().err()
+                );
+            }
+            app.fields[i].value = default;
+        }
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+
+    #[test]
+    fn test_generated_urls_have_no_duplicates() {
+        let urls = generate_urls((2024, 11), (2025, 2));
+        let months: Vec<String> = urls.iter().map(|url| pipeline::extract_month(url)).collect();
+        assert_eq!(months, ["2024-11", "2024-12", "2025-01", "2025-02"]);
+        assert_eq!(generate_urls((2025, 3), (2025, 3)).len(), 1);
+
+        let mut config = Config::default_blitz_300();
+        config.dataset_urls = generate_urls((2025, 1), (2025, 12));
+        assert!(config.duplicate_urls().is_empty());
+        assert_eq!(config.datasets(), config.dataset_urls);
+
+        // A month listed twice is reported and processed once
+        let march = config.dataset_urls[2].clone();
+        config.dataset_urls.push(march.clone());
+        assert_eq!(config.duplicate_urls(), [march.as_str()]);
+        assert_eq!(config.datasets().len(), 12);
+    }
+
+    #[test]
+    fn test_download_without_content_length_is_unknown() {
+        let mut app = App::new();
+        app.handle_event(UiEvent::DatasetStarted { index: 0, total: 1, name: String::new() });
+        app.handle_event(UiEvent::DownloadStarted { index: 1, total_bytes: None });
+        app.handle_event(UiEvent::DownloadStarted { index: 0, total_bytes: None });
+        app.handle_event(UiEvent::DownloadProgress { index: 0, bytes_read: 5 });
+        assert_eq!((app.dl_total, app.dl_read), (None, 5));
+        assert!(app.dataset_sizes.is_empty(), "no size was learned");
+
+        // The unknown prefetch total carries over when its dataset starts
+        app.handle_event(UiEvent::DatasetStarted { index: 1, total: 2, name: String::new() });
+        assert_eq!(app.phase, Phase::Downloading);
+        assert_eq!(app.dl_total, None);
+    }
+
+    #[test]
+    fn test_pause_waits_for_the_pipeline() {
+        let mut app = App::new();
+        app.run_state = RunState::Pausing;
+        app.handle_event(UiEvent::Pass1Progress { games_scanned: 10, valid_games: 5, unique_players: 3 });
+        assert!(app.run_state == RunState::Pausing, "still running until acknowledged");
+        app.handle_event(UiEvent::Paused);
+        assert!(app.run_state == RunState::Paused);
+
+        // Resumed before the pipeline blocked: a late acknowledgment is ignored
+        app.run_state = RunState::Running;
+        app.handle_event(UiEvent::Paused);
+        assert!(app.run_state == RunState::Running);
+    }
+
+    #[test]
+    fn test_two_phase_gauge_runs_each_round_over_half() {
+        let mut app = App::new();
+        app.two_phase = true;
+        let start = |app: &mut App, index| {
+            app.handle_event(UiEvent::DatasetStarted { index, total: 2, name: String::new() });
+            app.handle_event(UiEvent::FileProgress { bytes_read: 50, total_bytes: 100, decoded_bytes: 0 });
+        };
+
+        start(&mut app, 1);
+        app.phase = Phase::Pass1;
+        assert_eq!(app.overall_ratio(), 0.375, "half of the second dataset, counting");
+
+        app.handle_event(UiEvent::CountingComplete { datasets: 2, qualifying_players: 1 });
+        start(&mut app, 0);
+        app.phase = Phase::Pass2;
+        assert_eq!(app.overall_ratio(), 0.625, "half of the first dataset, extracting");
+    }
+}
@@ -1,13 +1,13 @@
 use crate::tui::app::App;
 use ratatui::{
-    layout::{Constraint, Direction, Layout, Rect},
+    layout::{Constraint, Direction, Layout, Margin, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
     widgets::{Block, Borders, Paragraph},
     Frame,
 };
 
-pub fn render(f: &mut Frame, app: &App) {
+pub fn render(f: &mut Frame, app: &mut App) {
     let area = f.area();
 
     let chunks = Layout::default()
@@ -15,7 +15,7 @@ pub fn render(f: &mut Frame, app: &App) {
         .margin(1)
         .constraints([
             Constraint::Length(3),  // Title
-            Constraint::Min(14),   // Form
+            Constraint::Min(5),    // Form (scrolls)
             Constraint::Length(3), // Error / status
             Constraint::Length(3),  // Help
         ])
@@ -23,7 +23,13 @@ pub fn render(f: &mut Frame, app: &App) {
 
     render_title(f, chunks[0]);
     render_form(f, chunks[1], app);
-    render_error(f, chunks[2], app);
+    let months = app.processed_months();
+    let processed = match months {
+        [] => None,
+        [month] => Some(format!("1 month ({})", month)),
+        [first, .., last] => Some(format!("{} months ({} to {})", months.len(), first, last)),
+    };
+    render_error(f, chunks[2], app, processed);
     render_help(f, chunks[3], app);
 }
 
@@ -36,28 +42,39 @@ fn render_title(f: &mut Frame, area: Rect) {
     f.render_widget(title, area);
 }
 
-fn render_form(f: &mut Frame, area: Rect, app: &App) {
+fn render_form(f: &mut Frame, area: Rect, app: &mut App) {
+    // One line per field, a spacer, then the Start button
+    let field_count = app.fields.len();
+    let total_lines = field_count + 2;
+    let viewport = area.height.saturating_sub(4) as usize; // borders + margin
+    let selected_line = if app.is_on_start_button() { field_count + 1 } else { app.selected };
+
+    // Scroll just enough to keep the selection in view
+    if selected_line < app.field_scroll {
+        app.field_scroll = selected_line;
+    } else if viewport > 0 && selected_line >= app.field_scroll + viewport {
+        app.field_scroll = selected_line + 1 - viewport;
+    }
+    app.field_scroll = app.field_scroll.min(total_lines.saturating_sub(viewport));
+
+    let above = app.field_scroll > 0;
+    let below = app.field_scroll + viewport < total_lines;
+    let title = match (above, below) {
+        (false, false) => " Settings ".to_string(),
+        (true, false) => " Settings ▲ ".to_string(),
+        (false, true) => " Settings ▼ ".to_string(),
+        (true, true) => " Settings ▲▼ ".to_string(),
+    };
     let block = Block::default()
         .borders(Borders::ALL)
         .border_style(Style::default().fg(Color::DarkGray))
-        .title(" Settings ");
+        .title(title);
 
     let inner = block.inner(area);
     f.render_widget(block, area);
 
-    let field_count = app.fields.len();
-    let mut constraints: Vec<Constraint> = app.fields.iter().map(|_| Constraint::Length(1)).collect();
-    constraints.push(Constraint::Length(1)); // spacer
-    constraints.push(Constraint::Length(1)); // start button
-    constraints.push(Constraint::Min(0));   // filler
-
-    let rows = Layout::default()
-        .direction(Direction::Vertical)
-        .margin(1)
-        .constraints(constraints)
-        .split(inner);
-
     let label_width = 18u16;
+    let mut lines: Vec<Line> = Vec::with_capacity(total_lines);
 
     for (i, field) in app.fields.iter().enumerate() {
         let selected = app.selected == i;
@@ -88,42 +105,65 @@ fn render_form(f: &mut Frame, area: Rect, app: &App) {
             field.value.clone()
         };
 
-        let line = Line::from(vec![
+        let hint = match app.field_error(i) {
+            Some(err) => Span::styled(format!("  ✗ {}", err), Style::default().fg(Color::Red)),
+            None => Span::styled(format!("  {}", field.hint), hint_style),
+        };
+
+        lines.push(Line::from(vec![
             Span::styled(
                 format!("{:>width$} │ ", field.label, width = label_width as usize),
                 label_style,
             ),
             Span::styled(value_display, value_style),
-            Span::styled(format!("  {}", field.hint), hint_style),
-        ]);
-
-        f.render_widget(Paragraph::new(line), rows[i]);
+            hint,
+        ]));
     }
 
+    lines.push(Line::default()); // spacer
+
     // Start button
-    let btn_idx = field_count + 1; // after spacer
     let selected = app.is_on_start_button();
-    let btn_style = if selected {
+    let disabled = app.has_invalid_fields();
+    let btn_style = if disabled {
+        Style::default().fg(Color::DarkGray).add_modifier(Modifier::CROSSED_OUT)
+    } else if selected {
         Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)
     } else {
         Style::default().fg(Color::DarkGray)
     };
     let arrow = if selected { "▶ " } else { "  " };
-    let line = Line::from(vec![
+    let mut spans = vec![
         Span::raw("                   "),
         Span::styled(format!("{}[ Start Processing ]", arrow), btn_style),
-    ]);
-    if btn_idx < rows.len() {
-        f.render_widget(Paragraph::new(line), rows[btn_idx]);
+    ];
+    if disabled {
+        spans.push(Span::styled("  fix the fields marked ✗", Style::default().fg(Color::Red)));
     }
+    lines.push(Line::from(spans));
+
+    let body = inner.inner(Margin { horizontal: 1, vertical: 1 });
+    f.render_widget(Paragraph::new(lines).scroll((app.field_scroll as u16, 0)), body);
 }
 
-fn render_error(f: &mut Frame, area: Rect, app: &App) {
+/// `processed` describes the months the existing index already holds.
+fn render_error(f: &mut Frame, area: Rect, app: &App, processed: Option<String>) {
     let msg = if let Some(err) = &app.validation_error {
         Line::from(Span::styled(
             format!(" ⚠ {}", err),
             Style::default().fg(Color::Red),
         ))
+    } else if let Some(processed) = processed {
+        let snapshot = if app.snapshot_path().exists() { " — press l for the last snapshot" } else { "" };
+        Line::from(Span::styled(
+            format!(" Index has {} processed; these are skipped{}.", processed, snapshot),
+            Style::default().fg(Color::Yellow),
+        ))
+    } else if app.snapshot_path().exists() {
+        Line::from(Span::styled(
+            " Previous run snapshot found — press l to view it.",
+            Style::default().fg(Color::Yellow),
+        ))
     } else {
         Line::from(Span::styled(
             " Ready to configure and start.",
@@ -139,7 +179,7 @@ fn render_help(f: &mut Frame, area: Rect, app: &App) {
     let help_text = if app.editing {
         " Type to edit │ Enter: Confirm │ Esc: Cancel "
     } else {
-        " ↑↓: Navigate │ Enter: Edit/Start │ Tab: Next │ q: Quit "
+        " ↑↓: Navigate │ Enter: Edit/Start │ Tab: Next │ l: Last snapshot │ q: Quit "
     };
     let help = Paragraph::new(Line::from(Span::styled(
         help_text,
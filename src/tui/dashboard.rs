@@ -7,16 +7,21 @@ use ratatui::{
     Frame,
 };
 
-pub fn render(f: &mut Frame, app: &App) {
+/// Frames for progress of unknown size, advanced as data arrives.
+const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+
+pub fn render(f: &mut Frame, app: &mut App) {
     let area = f.area();
 
+    // A hidden stats panel takes no rows, so the logs grow into its space
+    let stats_height = if app.stats_hidden { 0 } else { 8 };
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .margin(1)
         .constraints([
             Constraint::Length(3),  // Header
             Constraint::Length(5),  // Progress
-            Constraint::Length(8),  // Stats
+            Constraint::Length(stats_height),  // Stats
             Constraint::Min(6),    // Logs
             Constraint::Length(3),  // Controls
         ])
@@ -24,7 +29,9 @@ pub fn render(f: &mut Frame, app: &App) {
 
     render_header(f, chunks[0], app);
     render_progress(f, chunks[1], app);
-    render_stats(f, chunks[2], app);
+    if !app.stats_hidden {
+        render_stats(f, chunks[2], app);
+    }
     render_logs(f, chunks[3], app);
     render_controls(f, chunks[4], app);
 }
@@ -32,6 +39,7 @@ pub fn render(f: &mut Frame, app: &App) {
 fn render_header(f: &mut Frame, area: Rect, app: &App) {
     let state_span = match &app.run_state {
         RunState::Running => Span::styled(" RUNNING ", Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)),
+        RunState::Pausing => Span::styled(" PAUSING... ", Style::default().fg(Color::Yellow).add_modifier(Modifier::DIM)),
         RunState::Paused => Span::styled(" PAUSED ", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
         RunState::Finished => Span::styled(" FINISHED ", Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
         RunState::Cancelled => Span::styled(" CANCELLED ", Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)),
@@ -77,15 +85,12 @@ fn render_progress(f: &mut Frame, area: Rect, app: &App) {
         .split(inner);
 
     // Overall dataset progress
-    let overall_pct = if app.total_datasets > 0 {
-        (app.current_dataset as f64 / app.total_datasets as f64).min(1.0)
-    } else {
-        0.0
-    };
+    let overall_pct = app.overall_ratio();
     let overall_gauge = Gauge::default()
         .label(format!(
-            "Overall: {}/{} datasets",
-            app.current_dataset, app.total_datasets
+            "Overall: {}/{} datasets ({:.1}%)",
+            app.current_dataset, app.total_datasets,
+            overall_pct * 100.0
         ))
         .ratio(overall_pct)
         .gauge_style(Style::default().fg(Color::Cyan));
@@ -93,30 +98,39 @@ fn render_progress(f: &mut Frame, area: Rect, app: &App) {
 
     // File / Download progress
     let (file_pct, file_label) = match app.phase {
-        Phase::Downloading => {
-            let pct = if app.dl_total > 0 {
-                app.dl_read as f64 / app.dl_total as f64
-            } else {
-                0.0
-            };
-            (pct, format!(
+        Phase::Downloading => match app.dl_total {
+            Some(total) if total > 0 => (app.dl_read as f64 / total as f64, format!(
                 "Download: {} / {}",
                 fmt_bytes(app.dl_read),
-                fmt_bytes(app.dl_total)
-            ))
-        }
+                fmt_bytes(total)
+            )),
+            // No content-length: an empty bar would sit at 0%, so show a
+            // spinner that turns as data arrives instead
+            _ => {
+                let spinner = SPINNER[(app.dl_read / 1_048_576) as usize % SPINNER.len()];
+                (0.0, format!("Download: {} {} downloaded (size unknown)", spinner, fmt_bytes(app.dl_read)))
+            }
+        },
+        Phase::Preparing => (0.0, "File: opening...".into()),
         Phase::Pass1 | Phase::Pass2 => {
             let pct = if app.file_total > 0 {
                 app.file_read as f64 / app.file_total as f64
             } else {
                 0.0
             };
-            (pct, format!(
+            let mut label = format!(
                 "File: {} / {} ({:.1}%)",
                 fmt_bytes(app.file_read),
                 fmt_bytes(app.file_total),
                 pct * 100.0
-            ))
+            );
+            if let Some(rate) = app.decoded_rate() {
+                label.push_str(&format!(" — decoded {:.1} MB/s", rate / 1_048_576.0));
+                if app.file_read > 0 {
+                    label.push_str(&format!(", {:.1}x", app.file_decoded as f64 / app.file_read as f64));
+                }
+            }
+            (pct, label)
         }
         _ => (0.0, "Idle".into()),
     };
@@ -129,6 +143,7 @@ fn render_progress(f: &mut Frame, area: Rect, app: &App) {
     // Current phase
     let phase_text = match app.phase {
         Phase::Downloading => "Phase: Downloading dataset...".to_string(),
+        Phase::Preparing => "Phase: Preparing file...".to_string(),
         Phase::Pass1 => format!(
             "Phase: Pass 1 — Counting ({} scanned, {} valid, {} players)",
             fmt_count(app.p1_scanned), fmt_count(app.p1_valid), fmt_count(app.p1_players)
@@ -157,7 +172,11 @@ fn render_stats(f: &mut Frame, area: Rect, app: &App) {
 
     let cols = Layout::default()
         .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .constraints([
+            Constraint::Percentage(34),
+            Constraint::Percentage(33),
+            Constraint::Percentage(33),
+        ])
         .split(inner);
 
     // Current dataset stats
@@ -173,15 +192,27 @@ fn render_stats(f: &mut Frame, area: Rect, app: &App) {
     // Cumulative stats
     let total_stats = vec![
         Line::from(Span::styled(" Cumulative Totals", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))),
-        Line::from(format!("  Qualifying players: {}", fmt_count(app.cum_qualifying))),
-        Line::from(format!("  Games saved:        {}", fmt_count(app.cum_games_saved))),
-        Line::from(format!("  Final players:      {}", fmt_count(app.final_players))),
-        Line::from(""),
+        Line::from(format!("  Qualifying players: {}", fmt_count(app.stats.qualifying_players))),
+        Line::from(format!("  Games saved:        {}", fmt_count(app.stats.games_saved))),
+        Line::from(format!("  Final players:      {}", fmt_count(app.stats.final_players))),
+        Line::from(format!("  Output written:     {}", fmt_bytes(app.output_bytes()))),
     ];
     f.render_widget(Paragraph::new(total_stats), cols[1]);
+
+    // Pass 1 leaders
+    let mut top_lines = vec![Line::from(Span::styled(
+        " Most Active Players",
+        Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+    ))];
+    top_lines.extend(
+        app.top_players
+            .iter()
+            .map(|(name, games)| Line::from(format!("  {:<20} {}", name, fmt_count(*games as u64)))),
+    );
+    f.render_widget(Paragraph::new(top_lines), cols[2]);
 }
 
-fn render_logs(f: &mut Frame, area: Rect, app: &App) {
+fn render_logs(f: &mut Frame, area: Rect, app: &mut App) {
     let block = Block::default()
         .borders(Borders::ALL)
         .border_style(Style::default().fg(Color::DarkGray))
@@ -190,6 +221,7 @@ fn render_logs(f: &mut Frame, area: Rect, app: &App) {
     f.render_widget(block, area);
 
     let visible_height = inner.height as usize;
+    app.last_log_height = visible_height;
     let total = app.logs.len();
     let start = app.log_scroll.min(total.saturating_sub(visible_height));
 
@@ -214,10 +246,11 @@ fn render_logs(f: &mut Frame, area: Rect, app: &App) {
 
 fn render_controls(f: &mut Frame, area: Rect, app: &App) {
     let controls = match app.run_state {
-        RunState::Running => " [P] Pause  [Q] Quit  [↑↓] Scroll logs ",
-        RunState::Paused => " [R] Resume  [Q] Quit  [↑↓] Scroll logs ",
-        RunState::Finished | RunState::Cancelled | RunState::Error(_) => " [Q] Quit  [↑↓] Scroll logs ",
-        _ => "",
+        RunState::Running => " [P] Pause  [C] Cancel  [S] Stats  [Q] Quit  [↑↓] Scroll logs ",
+        RunState::Pausing => " Pausing at the next check...  [R] Resume  [C] Cancel  [S] Stats  [Q] Quit ",
+        RunState::Paused => " [R] Resume  [C] Cancel  [S] Stats  [Q] Quit  [↑↓] Scroll logs ",
+        RunState::Finished | RunState::Cancelled | RunState::Error(_) => " [S] Stats  [Q] Quit  [↑↓] Scroll logs ",
+        RunState::Idle => " Last snapshot  [Esc] Back  [S] Stats  [Q] Quit  [↑↓] Scroll logs ",
     };
     let para = Paragraph::new(Line::from(Span::styled(
         controls,
@@ -2,9 +2,9 @@ pub mod app;
 pub mod config_screen;
 pub mod dashboard;
 
-use crate::events::{ChannelSink, PipelineControl, UiEvent};
+use crate::events::{Cancelled, ChannelSink, PipelineControl, UiEvent};
 use crate::pipeline;
-use app::{App, RunState, Screen};
+use app::{App, FieldKind, RunState, Screen};
 use anyhow::Result;
 use crossterm::{
     event::{self, Event, KeyCode, KeyModifiers},
@@ -55,27 +55,27 @@ fn main_loop(
         }
 
         // Poll for input events (50ms timeout for ~20fps)
-        if event::poll(Duration::from_millis(50))? {
-            if let Event::Key(key) = event::read()? {
-                // Only handle key press events, not release/repeat (avoids double-input on Windows)
-                if key.kind != event::KeyEventKind::Press {
-                    continue;
-                }
+        if event::poll(Duration::from_millis(50))?
+            && let Event::Key(key) = event::read()?
+        {
+            // Only handle key press events, not release/repeat (avoids double-input on Windows)
+            if key.kind != event::KeyEventKind::Press {
+                continue;
+            }
 
-                // Ctrl+C always quits
-                if key.code == KeyCode::Char('c')
-                    && key.modifiers.contains(KeyModifiers::CONTROL)
-                {
-                    if let Some(control) = &app.control {
-                        control.cancel();
-                    }
-                    break;
+            // Ctrl+C always quits
+            if key.code == KeyCode::Char('c')
+                && key.modifiers.contains(KeyModifiers::CONTROL)
+            {
+                if let Some(control) = &app.control {
+                    control.cancel();
                 }
+                break;
+            }
 
-                match app.screen {
-                    Screen::Config => handle_config_key(app, key),
-                    Screen::Dashboard => handle_dashboard_key(app, key),
-                }
+            match app.screen {
+                Screen::Config => handle_config_key(app, key),
+                Screen::Dashboard => handle_dashboard_key(app, key),
             }
         }
 
@@ -99,12 +99,10 @@ fn handle_config_key(app: &mut App, key: event::KeyEvent) {
                 app.fields[app.selected].value.insert(cursor, c);
                 app.edit_cursor = cursor + 1;
             }
-            KeyCode::Backspace => {
-                if app.edit_cursor > 0 {
-                    let cursor = app.edit_cursor.min(app.fields[app.selected].value.len());
-                    app.fields[app.selected].value.remove(cursor - 1);
-                    app.edit_cursor = cursor - 1;
-                }
+            KeyCode::Backspace if app.edit_cursor > 0 => {
+                let cursor = app.edit_cursor.min(app.fields[app.selected].value.len());
+                app.fields[app.selected].value.remove(cursor - 1);
+                app.edit_cursor = cursor - 1;
             }
             KeyCode::Delete => {
                 let cursor = app.edit_cursor.min(app.fields[app.selected].value.len());
@@ -112,15 +110,11 @@ fn handle_config_key(app: &mut App, key: event::KeyEvent) {
                     app.fields[app.selected].value.remove(cursor);
                 }
             }
-            KeyCode::Left => {
-                if app.edit_cursor > 0 {
-                    app.edit_cursor -= 1;
-                }
+            KeyCode::Left if app.edit_cursor > 0 => {
+                app.edit_cursor -= 1;
             }
-            KeyCode::Right => {
-                if app.edit_cursor < app.fields[app.selected].value.len() {
-                    app.edit_cursor += 1;
-                }
+            KeyCode::Right if app.edit_cursor < app.fields[app.selected].value.len() => {
+                app.edit_cursor += 1;
             }
             KeyCode::Home => app.edit_cursor = 0,
             KeyCode::End => app.edit_cursor = app.fields[app.selected].value.len(),
@@ -131,15 +125,11 @@ fn handle_config_key(app: &mut App, key: event::KeyEvent) {
 
     match key.code {
         KeyCode::Char('q') => app.should_quit = true,
-        KeyCode::Up => {
-            if app.selected > 0 {
-                app.selected -= 1;
-            }
+        KeyCode::Up if app.selected > 0 => {
+            app.selected -= 1;
         }
-        KeyCode::Down => {
-            if app.selected < app.total_items() - 1 {
-                app.selected += 1;
-            }
+        KeyCode::Down if app.selected < app.total_items() - 1 => {
+            app.selected += 1;
         }
         KeyCode::Tab => {
             app.selected = (app.selected + 1) % app.total_items();
@@ -154,17 +144,31 @@ fn handle_config_key(app: &mut App, key: event::KeyEvent) {
         KeyCode::Enter => {
             if app.is_on_start_button() {
                 try_start_pipeline(app);
+            } else if app.fields[app.selected].kind == FieldKind::Toggle {
+                app.toggle_selected();
             } else {
                 app.editing = true;
                 app.edit_cursor = app.fields[app.selected].value.len();
             }
         }
+        KeyCode::Char(' ')
+            if !app.is_on_start_button() && app.fields[app.selected].kind == FieldKind::Toggle =>
+        {
+            app.toggle_selected();
+        }
         KeyCode::F(5) => try_start_pipeline(app),
+        KeyCode::Char('l') => {
+            app.validation_error = app.load_snapshot().err();
+        }
         _ => {}
     }
 }
 
 fn try_start_pipeline(app: &mut App) {
+    if app.has_invalid_fields() {
+        app.validation_error = Some("Fix the fields marked ✗ before starting".into());
+        return;
+    }
     match app.build_config() {
         Ok(config) => {
             app.validation_error = None;
@@ -183,13 +187,18 @@ fn start_pipeline(app: &mut App, config: crate::config::Config) {
 
     app.event_rx = Some(rx);
     app.control = Some(control);
+    app.stats = Default::default(); // drop any loaded snapshot
     app.screen = Screen::Dashboard;
     app.run_state = RunState::Running;
+    app.two_phase = config.two_phase;
+    app.extracting = false;
 
     std::thread::spawn(move || {
         let result = pipeline::run_with_sink(&config, sink);
         match result {
-            Ok(()) => { let _ = tx.send(UiEvent::Finished); }
+            Ok(_) => { let _ = tx.send(UiEvent::Finished); }
+            // The pipeline reports cancellation itself, as UiEvent::Cancelled
+            Err(e) if e.is::<Cancelled>() => {}
             Err(e) => { let _ = tx.send(UiEvent::Error(e.to_string())); }
         }
     });
@@ -205,31 +214,39 @@ fn handle_dashboard_key(app: &mut App, key: event::KeyEvent) {
             }
             app.should_quit = true;
         }
-        KeyCode::Char('p') => {
-            if app.run_state == RunState::Running {
-                if let Some(control) = &app.control {
-                    control.pause();
-                }
-                app.run_state = RunState::Paused;
-            }
+        KeyCode::Esc if app.run_state == RunState::Idle => {
+            // Leave the read-only snapshot view
+            app.screen = Screen::Config;
         }
-        KeyCode::Char('r') => {
-            if app.run_state == RunState::Paused {
-                if let Some(control) = &app.control {
-                    control.resume();
-                }
-                app.run_state = RunState::Running;
+        KeyCode::Char('p') if app.run_state == RunState::Running => {
+            if let Some(control) = &app.control {
+                control.pause();
             }
+            // Paused once the pipeline acknowledges it at its next check
+            app.run_state = RunState::Pausing;
         }
-        KeyCode::Up => {
-            if app.log_scroll > 0 {
-                app.log_scroll -= 1;
+        KeyCode::Char('c') if matches!(app.run_state, RunState::Running | RunState::Pausing | RunState::Paused) => {
+            // Stop the pipeline but keep the dashboard; it switches to
+            // Cancelled once the pipeline reports back
+            if let Some(control) = &app.control {
+                control.cancel();
             }
+            app.add_log("Cancelling at the next check...".into());
+        }
+        KeyCode::Char('s') => {
+            app.stats_hidden = !app.stats_hidden;
         }
-        KeyCode::Down => {
-            if app.log_scroll < app.logs.len().saturating_sub(1) {
-                app.log_scroll += 1;
+        KeyCode::Char('r') if matches!(app.run_state, RunState::Pausing | RunState::Paused) => {
+            if let Some(control) = &app.control {
+                control.resume();
             }
+            app.run_state = RunState::Running;
+        }
+        KeyCode::Up if app.log_scroll > 0 => {
+            app.log_scroll -= 1;
+        }
+        KeyCode::Down if app.log_scroll < app.logs.len().saturating_sub(1) => {
+            app.log_scroll += 1;
         }
         KeyCode::PageUp => {
             app.log_scroll = app.log_scroll.saturating_sub(10);
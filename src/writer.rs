@@ -1,51 +1,329 @@
+use crate::config::OutputLayout;
+use crate::dedup::SiteFilter;
 use anyhow::Result;
-use std::collections::HashMap;
+use std::cell::OnceCell;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::{self, OpenOptions};
-use std::io::Write;
-use std::path::PathBuf;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
 
 /// Buffered writer that accumulates games per player in memory
 /// and flushes them as compressed zstd frames to per-player files.
-pub struct PlayerWriter {
+///
+/// Ordering: within a player's file, games appear in the order they were
+/// passed to `add_game` (each flush appends one frame after the previous
+/// ones). A flush writes players in name order (per Elo bucket), so the same
+/// input always produces the same files, even when the output limit cuts a
+/// run short.
+pub struct PlayerWriter<'a> {
     players_dir: PathBuf,
-    buffer: HashMap<String, Vec<u8>>,
+    /// Keyed by (Elo bucket, player); the bucket is empty for other layouts.
+    /// Holds the games' text and how many games it is.
+    buffer: BTreeMap<(String, String), (Vec<u8>, u32)>,
     buffer_size: usize,
     max_buffer_size: usize,
+    flush_interval: Option<Duration>,
+    last_flush: Instant,
+    bytes_written: u64,
+    output_limit: Option<u64>,
+    layout: OutputLayout,
+    month: String,
+    lowercase_names: bool,
+    /// Shard `PerPlayer` files by name prefix (see `player_path`).
+    shard: bool,
+    /// Threads compressing and appending the files of one flush.
+    write_threads: usize,
+    /// Subdirectories of `players_dir`, listed on first per-month delete.
+    subdirs: OnceCell<Vec<PathBuf>>,
+    /// Size of each file before this writer first appended to it (None if
+    /// it created the file), for `rollback`.
+    origins: HashMap<PathBuf, Option<u64>>,
+    /// Games in each file from this dataset: those resumed plus those
+    /// flushed since. Only kept with a journal.
+    file_games: HashMap<PathBuf, (String, u32)>,
+    /// `file_games` as of `resume`, restored by `rollback`.
+    resumed: HashMap<PathBuf, (String, u32)>,
+    journal: Option<&'a dyn FlushJournal>,
+    /// Per-player filters of the `Site` URLs already written, when
+    /// deduplicating (see `with_dedup`).
+    sites: Option<HashMap<String, SiteFilter>>,
+    /// Each changed filter as it was before this writer's first change
+    /// (None if it did not exist), for `rollback`.
+    sites_before: HashMap<String, Option<SiteFilter>>,
 }
 
-impl PlayerWriter {
+/// A player file's state at a flush boundary: the games of the current
+/// dataset it holds and its length in bytes once they are written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileCheckpoint {
+    pub path: PathBuf,
+    pub player: String,
+    pub games: u32,
+    pub len: u64,
+}
+
+/// Durable record of a writer's progress, for resuming an interrupted pass
+/// (see `PlayerWriter::with_journal`).
+pub trait FlushJournal {
+    /// Store `files`, replacing what was stored for the same paths.
+    fn record(&self, files: &[FileCheckpoint]) -> Result<()>;
+}
+
+impl<'a> PlayerWriter<'a> {
     pub fn new(players_dir: PathBuf, max_buffer_size: usize) -> Self {
         Self {
             players_dir,
-            buffer: HashMap::new(),
+            buffer: BTreeMap::new(),
             buffer_size: 0,
             max_buffer_size,
+            flush_interval: None,
+            last_flush: Instant::now(),
+            bytes_written: 0,
+            output_limit: None,
+            layout: OutputLayout::PerPlayer,
+            month: String::new(),
+            lowercase_names: false,
+            shard: true,
+            write_threads: 1,
+            subdirs: OnceCell::new(),
+            origins: HashMap::new(),
+            file_games: HashMap::new(),
+            resumed: HashMap::new(),
+            journal: None,
+            sites: None,
+            sites_before: HashMap::new(),
         }
     }
 
-    /// Get the filesystem path for a player's .pgn.zst file.
-    /// Sharded into subdirectories by the first 2 chars of the lowercase name.
-    fn player_path(&self, name: &str) -> PathBuf {
-        let lower = name.to_ascii_lowercase();
-        let prefix = if lower.len() >= 2 {
-            &lower[..2]
-        } else {
-            &lower
+    /// Skip games whose `Site` a player's file already holds, starting from
+    /// `sites` (the filters of earlier datasets). See `has_site` and
+    /// `first_copy`.
+    pub fn with_dedup(mut self, sites: HashMap<String, SiteFilter>) -> Self {
+        self.sites = Some(sites);
+        self
+    }
+
+    /// Whether `player`'s file already has a game from `site`, without
+    /// recording anything. Always false without `with_dedup` or a `Site`
+    /// header. A filter can rarely mistake a new site for a known one.
+    pub fn has_site(&self, player: &str, site: &str) -> bool {
+        match &self.sites {
+            Some(sites) if !site.is_empty() => sites.get(player).is_some_and(|filter| filter.contains(site)),
+            _ => false,
+        }
+    }
+
+    /// Whether `player` has no game from `site` yet, recording it if so.
+    /// Call it for games that are being written: a recorded site is
+    /// skipped in every later dataset. Always true without `with_dedup` or
+    /// a `Site` header.
+    pub fn first_copy(&mut self, player: &str, site: &str) -> bool {
+        let Some(sites) = &mut self.sites else {
+            return true;
         };
-        self.players_dir
-            .join(prefix)
-            .join(format!("{}.pgn.zst", name))
+        if site.is_empty() {
+            return true;
+        }
+        if !self.sites_before.contains_key(player) {
+            self.sites_before.insert(player.to_string(), sites.get(player).cloned());
+        }
+        match sites.get_mut(player) {
+            Some(filter) => filter.insert(site),
+            None => sites.entry(player.to_string()).or_default().insert(site),
+        }
+    }
+
+    /// The serialized site filters of the players this writer added sites
+    /// for, to store in the index.
+    pub fn changed_sites(&self) -> HashMap<String, Vec<u8>> {
+        let Some(sites) = &self.sites else {
+            return HashMap::new();
+        };
+        self.sites_before
+            .keys()
+            .filter_map(|player| sites.get(player).map(|filter| (player.clone(), filter.to_bytes())))
+            .collect()
+    }
+
+    /// Report every flush to `journal`: before appending to a file for the
+    /// first time, its current length (with no games); after the flush, the
+    /// games and length of each file it appended to. If the process dies in
+    /// between, the last record covers exactly the games that are complete
+    /// on disk, and `resume` cuts off anything after them.
+    pub fn with_journal(mut self, journal: &'a dyn FlushJournal) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// Continue from `files`, the journal's last record of an interrupted
+    /// run over the same input: cut each file back to its recorded length
+    /// (removing it when that is zero) and count its games as written. The
+    /// caller skips `resumed_games` before adding any more.
+    pub fn resume(&mut self, files: &[FileCheckpoint]) -> Result<()> {
+        for file in files {
+            if file.path.exists() {
+                match file.len {
+                    0 => fs::remove_file(&file.path)?,
+                    len => OpenOptions::new().write(true).open(&file.path)?.set_len(len)?,
+                }
+            }
+            self.resumed.insert(file.path.clone(), (file.player.clone(), file.games));
+        }
+        self.file_games = self.resumed.clone();
+        Ok(())
+    }
+
+    /// Games per player already on disk from the run `resume` continued.
+    pub fn resumed_games(&self) -> HashMap<String, u32> {
+        let mut games: HashMap<String, u32> = HashMap::new();
+        for (player, n) in self.resumed.values() {
+            *games.entry(player.clone()).or_insert(0) += n;
+        }
+        games
+    }
+
+    /// Also flush from `add_game` once `interval` has passed since the last
+    /// flush, bounding how long games sit in RAM. None = size-based only.
+    pub fn with_flush_interval(mut self, interval: Option<Duration>) -> Self {
+        self.flush_interval = interval;
+        self
+    }
+
+    /// Budget (bytes) of compressed output this writer may produce; see
+    /// `limit_reached`. None = unlimited.
+    pub fn with_output_limit(mut self, limit: Option<u64>) -> Self {
+        self.output_limit = limit;
+        self
+    }
+
+    /// Arrange files by `layout`; `month` (e.g. `2025-03`) is the directory
+    /// new games go to under `PerPlayerPerMonth`.
+    pub fn with_layout(mut self, layout: OutputLayout, month: &str) -> Self {
+        self.layout = layout;
+        self.month = month.to_string();
+        self
+    }
+
+    /// Name files by the lowercase player name. Lichess usernames are
+    /// unique ignoring case, so no two players share a file.
+    pub fn with_lowercase_names(mut self, lowercase: bool) -> Self {
+        self.lowercase_names = lowercase;
+        self
+    }
+
+    /// Shard `PerPlayer` files into subdirectories by name prefix (the
+    /// default), or with false put them directly in `players_dir`.
+    pub fn with_sharding(mut self, shard: bool) -> Self {
+        self.shard = shard;
+        self
+    }
+
+    /// Whether files go in subdirectories of `players_dir`, which may be
+    /// left empty after deletions.
+    pub fn uses_subdirs(&self) -> bool {
+        self.shard || self.layout != OutputLayout::PerPlayer
+    }
+
+    /// Compress and append the files of a flush on up to `threads` threads
+    /// (1 = on the caller's). Each file still gets one frame per flush and
+    /// the journal is written in name order, so the output does not depend
+    /// on the thread count.
+    pub fn with_write_threads(mut self, threads: usize) -> Self {
+        self.write_threads = threads.max(1);
+        self
+    }
+
+    /// Game text waiting in memory for the next flush, in bytes.
+    pub fn buffered_bytes(&self) -> usize {
+        self.buffer_size
+    }
+
+    /// Compressed bytes appended to player files so far.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// True once flushed output has reached the output limit. Buffered games
+    /// are not counted until flushed, so the limit can be overshot by up
+    /// to one buffer's worth of compressed data.
+    pub fn limit_reached(&self) -> bool {
+        self.output_limit.is_some_and(|limit| self.bytes_written >= limit)
+    }
+
+    /// Get the filesystem path for a player's .pgn.zst file. Per player,
+    /// sharded into subdirectories by the first 2 chars of the lowercase
+    /// name (unless sharding is off, see `with_sharding`); per month,
+    /// inside the month's directory. Elo buckets are per game, see
+    /// `bucket_path`.
+    ///
+    /// Panics for `ByEloBucket`, where a player has no single file: use
+    /// `player_paths`.
+    pub fn player_path(&self, name: &str) -> PathBuf {
+        let file_name = self.file_name(name);
+        match self.layout {
+            OutputLayout::PerPlayerPerMonth => return self.players_dir.join(&self.month).join(file_name),
+            OutputLayout::ByEloBucket { .. } => panic!("player_path has no single file per player for ByEloBucket"),
+            OutputLayout::PerPlayer if !self.shard => return self.players_dir.join(file_name),
+            OutputLayout::PerPlayer => {}
+        }
+        // By chars, not bytes: `split` and team names can be any UTF-8
+        let prefix: String = name
+            .chars()
+            .take(2)
+            .map(|c| if matches!(c, '/' | '\\' | '.') { '_' } else { c.to_ascii_lowercase() })
+            .collect();
+        self.players_dir.join(prefix).join(file_name)
+    }
+
+    /// Path of a player's file in an Elo bucket directory (`ByEloBucket`).
+    pub fn bucket_path(&self, bucket: &str, name: &str) -> PathBuf {
+        self.players_dir.join(bucket).join(self.file_name(name))
     }
 
-    /// Add a game's raw PGN text to the buffer for a given player.
-    /// Automatically flushes if the buffer exceeds `max_buffer_size`.
+    /// A player's file name. Path separators become `_`, so a name from an
+    /// arbitrary PGN header (see `split`) stays one file in its directory.
+    fn file_name(&self, name: &str) -> String {
+        let name = name.replace(['/', '\\'], "_");
+        if self.lowercase_names {
+            format!("{}.pgn.zst", name.to_ascii_lowercase())
+        } else {
+            format!("{}.pgn.zst", name)
+        }
+    }
+
+    /// Add a game's raw PGN text to the buffer for a given player, ending
+    /// it with exactly one blank line (the PGN game separator) whatever
+    /// trailing newlines it came with.
+    /// Automatically flushes if the buffer exceeds `max_buffer_size` or the
+    /// flush interval has elapsed.
     pub fn add_game(&mut self, player: &str, pgn: &str) -> Result<()> {
-        let entry = self.buffer.entry(player.to_string()).or_default();
+        let bucket = match self.layout {
+            OutputLayout::ByEloBucket { width } => elo_bucket(pgn, width),
+            _ => String::new(),
+        };
+        let (entry, games) = self.buffer.entry((bucket, player.to_string())).or_default();
+        *games += 1;
+        let before = entry.len();
         entry.extend_from_slice(pgn.as_bytes());
-        entry.push(b'\n');
-        self.buffer_size += pgn.len() + 1;
+        let eol: &[u8] = if pgn.ends_with("\r\n") { b"\r\n" } else { b"\n" };
+        let newlines = pgn
+            .bytes()
+            .rev()
+            .take_while(|b| matches!(b, b'\n' | b'\r'))
+            .filter(|&b| b == b'\n')
+            .count();
+        for _ in newlines..2 {
+            entry.extend_from_slice(eol);
+        }
+        self.buffer_size += entry.len() - before;
 
-        if self.buffer_size >= self.max_buffer_size {
+        let interval_due = self
+            .flush_interval
+            .is_some_and(|interval| self.last_flush.elapsed() >= interval);
+        if self.buffer_size >= self.max_buffer_size || interval_due {
             self.flush_all()?;
         }
         Ok(())
@@ -53,41 +331,632 @@ impl PlayerWriter {
 
     /// Flush all buffered data to disk as compressed zstd frames.
     pub fn flush_all(&mut self) -> Result<()> {
-        let entries: Vec<(String, Vec<u8>)> = self.buffer.drain().collect();
-        for (player, data) in entries {
+        let mut pending = Vec::with_capacity(self.buffer.len());
+        let mut first_appends = Vec::new();
+        for ((bucket, player), (data, games)) in std::mem::take(&mut self.buffer) {
             if data.is_empty() {
                 continue;
             }
-            self.write_compressed(&player, &data)?;
+            let path = match self.layout {
+                OutputLayout::ByEloBucket { .. } => self.bucket_path(&bucket, &player),
+                _ => self.player_path(&player),
+            };
+            if !self.origins.contains_key(&path) {
+                let len = fs::metadata(&path).ok().map(|meta| meta.len());
+                self.origins.insert(path.clone(), len);
+                let resumed = self.file_games.get(&path).map_or(0, |(_, n)| *n);
+                first_appends.push(FileCheckpoint { path: path.clone(), player: player.clone(), games: resumed, len: len.unwrap_or(0) });
+            }
+            pending.push((path, player, data, games));
+        }
+        if let Some(journal) = self.journal
+            && !first_appends.is_empty()
+        {
+            journal.record(&first_appends)?;
+        }
+
+        let appended = compress_all(&pending, self.write_threads)?;
+        let mut flushed = Vec::with_capacity(pending.len());
+        for ((path, player, _, games), appended) in pending.into_iter().zip(appended) {
+            self.bytes_written += appended;
+            if self.journal.is_some() {
+                let total = &mut self.file_games.entry(path.clone()).or_insert_with(|| (player.clone(), 0)).1;
+                *total += games;
+                flushed.push(FileCheckpoint { games: *total, len: fs::metadata(&path)?.len(), path, player });
+            }
+        }
+        if let Some(journal) = self.journal
+            && !flushed.is_empty()
+        {
+            journal.record(&flushed)?;
         }
         self.buffer_size = 0;
+        self.last_flush = Instant::now();
         Ok(())
     }
 
-    /// Compress `data` with zstd and append as a new frame to the player's file.
-    fn write_compressed(&self, player: &str, data: &[u8]) -> Result<()> {
-        let path = self.player_path(player);
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent)?;
+    /// Undo everything this writer has done: drop the buffered games and
+    /// cut each file back to its size before the first append, removing
+    /// the files it created. Used to restart a pass without duplicating
+    /// the games it already wrote. The files and the journal go back to
+    /// where `resume` left them, and the site filters to where they started.
+    pub fn rollback(&mut self) -> Result<()> {
+        self.buffer.clear();
+        self.buffer_size = 0;
+        let mut restored = Vec::with_capacity(self.origins.len());
+        for (path, len) in self.origins.drain() {
+            match len {
+                Some(len) => OpenOptions::new().write(true).open(&path)?.set_len(len)?,
+                None => fs::remove_file(&path)?,
+            }
+            if let Some((player, _)) = self.file_games.get(&path) {
+                let games = self.resumed.get(&path).map_or(0, |(_, n)| *n);
+                restored.push(FileCheckpoint { player: player.clone(), games, len: len.unwrap_or(0), path });
+            }
         }
-
-        let file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&path)?;
-
-        let mut encoder = zstd::stream::write::Encoder::new(file, 3)?;
-        encoder.write_all(data)?;
-        encoder.finish()?;
+        if let Some(journal) = self.journal {
+            journal.record(&restored)?;
+        }
+        if let Some(sites) = &mut self.sites {
+            for (player, before) in self.sites_before.drain() {
+                match before {
+                    Some(filter) => sites.insert(player, filter),
+                    None => sites.remove(&player),
+                };
+            }
+        }
+        self.file_games = self.resumed.clone();
+        self.bytes_written = 0;
         Ok(())
     }
 
-    /// Delete a player's .pgn.zst file.
+    /// A player's existing .pgn.zst files: per player the one at
+    /// `player_path`, per month (or Elo bucket) theirs in every month (or
+    /// bucket) directory.
+    pub fn player_paths(&self, name: &str) -> Result<Vec<PathBuf>> {
+        if self.layout == OutputLayout::PerPlayer {
+            let path = self.player_path(name);
+            return Ok(if path.exists() { vec![path] } else { Vec::new() });
+        }
+        let file_name = self.file_name(name);
+        Ok(self.subdirs()?.iter().map(|dir| dir.join(&file_name)).filter(|path| path.exists()).collect())
+    }
+
+    /// Delete a player's .pgn.zst files (see `player_paths`).
     pub fn delete_player(&self, name: &str) -> Result<()> {
-        let path = self.player_path(name);
-        if path.exists() {
+        for path in self.player_paths(name)? {
             fs::remove_file(path)?;
         }
         Ok(())
     }
+
+    fn subdirs(&self) -> Result<&[PathBuf]> {
+        if self.subdirs.get().is_none() {
+            let mut dirs = Vec::new();
+            if self.players_dir.is_dir() {
+                for entry in fs::read_dir(&self.players_dir)? {
+                    let path = entry?.path();
+                    if path.is_dir() {
+                        dirs.push(path);
+                    }
+                }
+            }
+            let _ = self.subdirs.set(dirs);
+        }
+        Ok(self.subdirs.get().unwrap())
+    }
+}
+
+/// `write_compressed` for each `(path, _, data, _)` of a flush, on up to
+/// `threads` threads that take the files in turn. Returns the compressed
+/// bytes appended to each, in the order of `jobs`. After an error the
+/// remaining files are left alone.
+fn compress_all(jobs: &[(PathBuf, String, Vec<u8>, u32)], threads: usize) -> Result<Vec<u64>> {
+    if threads <= 1 || jobs.len() <= 1 {
+        return jobs.iter().map(|(path, _, data, _)| write_compressed(path, data)).collect();
+    }
+    let next = AtomicUsize::new(0);
+    let mut appended = vec![0; jobs.len()];
+    let results: Vec<Result<Vec<(usize, u64)>>> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.min(jobs.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some((path, _, data, _)) = jobs.get(i) else {
+                            return Ok(done);
+                        };
+                        match write_compressed(path, data) {
+                            Ok(bytes) => done.push((i, bytes)),
+                            Err(e) => {
+                                next.store(jobs.len(), Ordering::Relaxed);
+                                return Err(e);
+                            }
+                        }
+                    }
+                })
+            })
+            .collect();
+        workers.into_iter().map(|worker| worker.join().expect("writer thread panicked")).collect()
+    });
+    for done in results {
+        for (i, bytes) in done? {
+            appended[i] = bytes;
+        }
+    }
+    Ok(appended)
+}
+
+/// Compress `data` with zstd and append as a new frame to the file at
+/// `path`. Returns the number of compressed bytes appended.
+fn write_compressed(path: &Path, data: &[u8]) -> Result<u64> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    let before = file.metadata()?.len();
+
+    let mut encoder = zstd::stream::write::Encoder::new(file, 3)?;
+    encoder.write_all(data)?;
+    let file = encoder.finish()?;
+    Ok(file.metadata()?.len() - before)
+}
+
+/// The rating band directory of a game for `ByEloBucket`: the average of
+/// its `WhiteElo` and `BlackElo` headers rounded down to a multiple of
+/// `width`, e.g. `1200-1399`. Games missing either rating go to `unrated`.
+pub fn elo_bucket(pgn: &str, width: u32) -> String {
+    let elo = |key: &str| {
+        pgn.lines()
+            .take_while(|line| line.starts_with('['))
+            .find_map(|line| line.strip_prefix(key)?.strip_prefix(" \"")?.strip_suffix("\"]")?.parse::<u32>().ok())
+    };
+    let (Some(white), Some(black)) = (elo("[WhiteElo"), elo("[BlackElo")) else {
+        return "unrated".into();
+    };
+    let width = u64::from(width.max(1));
+    let low = (u64::from(white) + u64::from(black)) / 2 / width * width;
+    format!("{}-{}", low, low + width - 1)
+}
+
+/// All `.pgn.zst` files under `dir`, at any depth (shards or month folders).
+pub fn player_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    collect_player_files(dir, &mut files)?;
+    Ok(files)
+}
+
+fn collect_player_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_player_files(&path, files)?;
+        } else if path.to_string_lossy().ends_with(".pgn.zst") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Append the zstd frames of player file `src` to `dst`, creating `dst`
+/// and its directory if needed. Concatenated frames decode as one stream,
+/// so nothing is recompressed. Returns the number of bytes appended.
+pub fn append_player_file(src: &Path, dst: &Path) -> Result<u64> {
+    if let Some(parent) = dst.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut from = fs::File::open(src)?;
+    let mut to = OpenOptions::new().create(true).append(true).open(dst)?;
+    Ok(io::copy(&mut from, &mut to)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_players_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rookt_test_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_flush_interval_writes_before_size_limit() {
+        let dir = temp_players_dir("flush_interval");
+        let mut writer = PlayerWriter::new(dir.clone(), usize::MAX);
+        writer.add_game("PlayerA", "1. e4 e5").unwrap();
+        assert!(!writer.player_path("PlayerA").exists());
+
+        let mut writer = writer.with_flush_interval(Some(Duration::ZERO));
+        writer.add_game("PlayerA", "1. d4 d5").unwrap();
+        assert!(writer.player_path("PlayerA").exists());
+        assert_eq!(writer.buffer_size, 0);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_appended_games_are_separated_and_reparse() {
+        let dir = temp_players_dir("separators");
+        let mut writer = PlayerWriter::new(dir.clone(), usize::MAX);
+        // Raw PGN as the parser yields it: with the trailing blank line, at
+        // EOF without it, and (verbatim) without even a final newline
+        writer.add_game("PlayerA", "[White \"A1\"]\n\n1. e4 e5 1-0\n\n").unwrap();
+        writer.add_game("PlayerA", "[White \"A2\"]\n\n1. d4 d5 0-1\n").unwrap();
+        writer.add_game("PlayerA", "[White \"A3\"]\n\n1. c4 c5 1/2-1/2").unwrap();
+        writer.flush_all().unwrap();
+        writer.add_game("PlayerA", "[White \"A4\"]\n\n1. Nf3 Nf6 1-0\n\n").unwrap();
+        writer.flush_all().unwrap();
+
+        let file = fs::File::open(writer.player_path("PlayerA")).unwrap();
+        let decoder = zstd::stream::read::Decoder::new(file).unwrap();
+        let mut parser = crate::parser::PgnParser::new(std::io::BufReader::new(decoder));
+        let mut whites = Vec::new();
+        while let Some(game) = parser.next_game().unwrap() {
+            assert!(game.raw_pgn.ends_with("\n\n"), "{:?}", game.raw_pgn);
+            whites.push(game.info.white);
+        }
+        assert_eq!(whites, ["A1", "A2", "A3", "A4"]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_rollback_restores_files() {
+        let dir = temp_players_dir("rollback");
+        let mut earlier = PlayerWriter::new(dir.clone(), usize::MAX);
+        earlier.add_game("PlayerA", "1. e4 e5").unwrap();
+        earlier.flush_all().unwrap();
+        let len = fs::metadata(earlier.player_path("PlayerA")).unwrap().len();
+
+        let mut writer = PlayerWriter::new(dir.clone(), 0);
+        writer.add_game("PlayerA", "1. d4 d5").unwrap();
+        writer.add_game("PlayerB", "1. c4 c5").unwrap();
+        writer.add_game("PlayerA", "1. Nf3 Nf6").unwrap();
+        assert!(fs::metadata(writer.player_path("PlayerA")).unwrap().len() > len);
+        writer.rollback().unwrap();
+        assert_eq!(fs::metadata(writer.player_path("PlayerA")).unwrap().len(), len);
+        assert!(!writer.player_path("PlayerB").exists(), "created by the rolled back writer");
+        assert_eq!(writer.bytes_written(), 0);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Keeps every record, in order.
+    #[derive(Default)]
+    struct RecordingJournal(std::cell::RefCell<Vec<Vec<FileCheckpoint>>>);
+
+    impl FlushJournal for RecordingJournal {
+        fn record(&self, files: &[FileCheckpoint]) -> Result<()> {
+            self.0.borrow_mut().push(files.to_vec());
+            Ok(())
+        }
+    }
+
+    impl RecordingJournal {
+        /// The latest record of each file, as the index would hold it.
+        fn latest(&self) -> Vec<FileCheckpoint> {
+            let mut latest: BTreeMap<PathBuf, FileCheckpoint> = BTreeMap::new();
+            for file in self.0.borrow().iter().flatten() {
+                latest.insert(file.path.clone(), file.clone());
+            }
+            latest.into_values().collect()
+        }
+    }
+
+    #[test]
+    fn test_journal_and_resume() {
+        let dir = temp_players_dir("journal");
+        let mut earlier = PlayerWriter::new(dir.clone(), usize::MAX);
+        earlier.add_game("PlayerA", "1. e4 e5").unwrap();
+        earlier.flush_all().unwrap();
+        let path_a = earlier.player_path("PlayerA");
+        let path_b = earlier.player_path("PlayerB");
+        let before = fs::metadata(&path_a).unwrap().len();
+
+        let journal = RecordingJournal::default();
+        let mut writer = PlayerWriter::new(dir.clone(), usize::MAX).with_journal(&journal);
+        writer.add_game("PlayerA", "1. d4 d5").unwrap();
+        writer.add_game("PlayerA", "1. c4 c5").unwrap();
+        writer.add_game("PlayerB", "1. Nf3 Nf6").unwrap();
+        writer.flush_all().unwrap();
+        let after_a = fs::metadata(&path_a).unwrap().len();
+        let after_b = fs::metadata(&path_b).unwrap().len();
+        {
+            let records = journal.0.borrow();
+            // The lengths before the first append are recorded before it
+            assert_eq!(records.len(), 2);
+            assert_eq!(records[0].iter().map(|f| (f.games, f.len)).collect::<Vec<_>>(), [(0, before), (0, 0)]);
+            assert_eq!(records[1].iter().map(|f| (f.games, f.len)).collect::<Vec<_>>(), [(2, after_a), (1, after_b)]);
+        }
+
+        // The process dies after appending to A's file again but before the
+        // journal heard of it
+        writer.add_game("PlayerA", "1. b3 e5").unwrap();
+        writer.flush_all().unwrap();
+        journal.0.borrow_mut().pop();
+        drop(writer);
+
+        let journal = RecordingJournal(journal.0.clone());
+        let mut resumed = PlayerWriter::new(dir.clone(), usize::MAX).with_journal(&journal);
+        resumed.resume(&journal.latest()).unwrap();
+        assert_eq!(fs::metadata(&path_a).unwrap().len(), after_a, "unrecorded frame cut off");
+        assert_eq!(resumed.resumed_games(), HashMap::from([("PlayerA".into(), 2), ("PlayerB".into(), 1)]));
+
+        // Games flushed after resuming add to the resumed ones, and a
+        // rollback goes back to the resumed state
+        resumed.add_game("PlayerA", "1. b3 e5").unwrap();
+        resumed.flush_all().unwrap();
+        assert_eq!(journal.latest()[0].games, 3);
+        resumed.rollback().unwrap();
+        assert_eq!(fs::metadata(&path_a).unwrap().len(), after_a);
+        assert_eq!((journal.latest()[0].games, journal.latest()[0].len), (2, after_a));
+
+        // A run that died before its first flush completed leaves only the
+        // lengths from before it: the new file goes, the old one is restored
+        let mut restart = PlayerWriter::new(dir.clone(), usize::MAX);
+        restart.resume(&journal.0.borrow()[0]).unwrap();
+        assert_eq!(fs::metadata(&path_a).unwrap().len(), before);
+        assert!(!path_b.exists());
+        assert!(restart.resumed_games().values().all(|&n| n == 0));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_first_copy_and_rollback() {
+        let dir = temp_players_dir("first_copy");
+        let mut plain = PlayerWriter::new(dir.clone(), usize::MAX);
+        assert!(plain.first_copy("PlayerA", "s1") && plain.first_copy("PlayerA", "s1"), "no dedup by default");
+
+        let mut earlier = SiteFilter::default();
+        earlier.insert("s1");
+        let mut writer = PlayerWriter::new(dir.clone(), usize::MAX).with_dedup(HashMap::from([("PlayerA".into(), earlier)]));
+        assert!(!writer.first_copy("PlayerA", "s1"), "written by an earlier dataset");
+        assert!(writer.first_copy("PlayerA", "s2"));
+        assert!(!writer.first_copy("PlayerA", "s2"));
+        assert!(writer.first_copy("PlayerB", "s2"), "per player");
+        assert!(writer.first_copy("PlayerB", "") && writer.first_copy("PlayerB", ""), "no Site, no dedup");
+        assert_eq!(writer.changed_sites().len(), 2);
+
+        // A restarted pass sees the sites as they were before it
+        writer.rollback().unwrap();
+        assert!(writer.changed_sites().is_empty());
+        assert!(!writer.first_copy("PlayerA", "s1"));
+        assert!(writer.first_copy("PlayerA", "s2"));
+        assert!(writer.first_copy("PlayerB", "s2"));
+    }
+
+    #[test]
+    fn test_lowercase_file_names() {
+        let dir = temp_players_dir("lowercase");
+        assert_eq!(
+            PlayerWriter::new(dir.clone(), 0).player_path("DrNykterstein"),
+            dir.join("dr").join("DrNykterstein.pgn.zst")
+        );
+
+        let mut writer = PlayerWriter::new(dir.clone(), 0).with_lowercase_names(true);
+        assert_eq!(writer.player_path("DrNykterstein"), dir.join("dr").join("drnykterstein.pgn.zst"));
+        let mut monthly = PlayerWriter::new(dir.clone(), 0)
+            .with_layout(OutputLayout::PerPlayerPerMonth, "2025-03")
+            .with_lowercase_names(true);
+        assert_eq!(monthly.player_path("DrNykterstein"), dir.join("2025-03").join("drnykterstein.pgn.zst"));
+        monthly.add_game("DrNykterstein", "1. e4 e5").unwrap();
+        let pruner = PlayerWriter::new(dir.clone(), 0)
+            .with_layout(OutputLayout::PerPlayerPerMonth, "")
+            .with_lowercase_names(true);
+        pruner.delete_player("DrNykterstein").unwrap();
+        assert!(!monthly.player_path("DrNykterstein").exists());
+
+        writer.add_game("DrNykterstein", "1. e4 e5").unwrap();
+        assert!(writer.player_path("drnykterstein").exists());
+        writer.delete_player("DrNykterstein").unwrap();
+        assert!(!writer.player_path("DrNykterstein").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_any_name_stays_inside_players_dir() {
+        let dir = temp_players_dir("odd_names");
+        let writer = PlayerWriter::new(dir.clone(), 0);
+        assert_eq!(writer.player_path("张三"), dir.join("张三").join("张三.pgn.zst"));
+        assert_eq!(writer.player_path("Ä"), dir.join("Ä").join("Ä.pgn.zst"));
+        assert_eq!(writer.player_path("../x"), dir.join("__").join(".._x.pgn.zst"));
+        assert_eq!(writer.player_path("a\\b"), dir.join("a_").join("a_b.pgn.zst"));
+        assert_eq!(writer.player_path(".."), dir.join("__").join("...pgn.zst"));
+        let flat = PlayerWriter::new(dir.clone(), 0).with_sharding(false);
+        assert_eq!(flat.player_path("/etc/passwd"), dir.join("_etc_passwd.pgn.zst"));
+    }
+
+    #[test]
+    fn test_append_player_file_concatenates_frames() {
+        let dir = temp_players_dir("append_file");
+        let mut blitz = PlayerWriter::new(dir.join("blitz"), usize::MAX);
+        blitz.add_game("PlayerA", "[White \"Blitz\"]\n\n1. e4 e5 1-0\n\n").unwrap();
+        blitz.flush_all().unwrap();
+        let mut rapid = PlayerWriter::new(dir.join("rapid"), usize::MAX);
+        rapid.add_game("PlayerA", "[White \"Rapid\"]\n\n1. d4 d5 0-1\n\n").unwrap();
+        rapid.flush_all().unwrap();
+
+        let dst = rapid.player_path("PlayerA");
+        append_player_file(&blitz.player_path("PlayerA"), &dst).unwrap();
+        // A missing destination (and its shard) is created
+        let fresh = dir.join("fresh").join("pl").join("PlayerA.pgn.zst");
+        append_player_file(&dst, &fresh).unwrap();
+
+        let decoder = zstd::stream::read::Decoder::new(fs::File::open(&fresh).unwrap()).unwrap();
+        let mut parser = crate::parser::PgnParser::new(std::io::BufReader::new(decoder));
+        let mut whites = Vec::new();
+        while let Some(game) = parser.next_game().unwrap() {
+            whites.push(game.info.white);
+        }
+        assert_eq!(whites, ["Rapid", "Blitz"]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_per_month_layout() {
+        let dir = temp_players_dir("per_month");
+        for month in ["2025-01", "2025-02"] {
+            let mut writer = PlayerWriter::new(dir.clone(), usize::MAX)
+                .with_layout(OutputLayout::PerPlayerPerMonth, month);
+            writer.add_game("Alice", "1. e4 e5").unwrap();
+            writer.add_game("Bob", "1. d4 d5").unwrap();
+            writer.flush_all().unwrap();
+        }
+        for path in ["2025-01/Alice.pgn.zst", "2025-01/Bob.pgn.zst", "2025-02/Alice.pgn.zst"] {
+            assert!(dir.join(path).is_file(), "{}", path);
+        }
+
+        // Pruning does not know the month: it removes the player everywhere
+        let pruner = PlayerWriter::new(dir.clone(), 0).with_layout(OutputLayout::PerPlayerPerMonth, "");
+        pruner.delete_player("Alice").unwrap();
+        assert!(!dir.join("2025-01/Alice.pgn.zst").exists());
+        assert!(!dir.join("2025-02/Alice.pgn.zst").exists());
+        assert!(dir.join("2025-02/Bob.pgn.zst").is_file());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_elo_bucket_layout() {
+        let game = |white: u32, black: &str| {
+            format!("[White \"A\"]\n[WhiteElo \"{}\"]\n[BlackElo \"{}\"]\n\n1. e4 e5 1-0\n", white, black)
+        };
+        assert_eq!(elo_bucket(&game(1250, "1500"), 200), "1200-1399", "average 1375");
+        assert_eq!(elo_bucket(&game(1390, "1410"), 200), "1400-1599", "average 1400");
+        assert_eq!(elo_bucket(&game(999, "1000"), 100), "900-999");
+        assert_eq!(elo_bucket(&game(1500, "?"), 200), "unrated");
+        assert_eq!(elo_bucket("1. e4 e5 1-0\n", 200), "unrated");
+        assert_eq!(elo_bucket(&game(4_000_000_000, "4000000000"), 1_000_000_000), "4000000000-4999999999", "no overflow");
+
+        let dir = temp_players_dir("elo_bucket");
+        let layout = OutputLayout::ByEloBucket { width: 200 };
+        let mut writer = PlayerWriter::new(dir.clone(), usize::MAX).with_layout(layout, "2025-01");
+        writer.add_game("Alice", &game(1250, "1500")).unwrap();
+        writer.add_game("Alice", &game(1800, "1850")).unwrap();
+        writer.add_game("Bob", &game(1250, "1500")).unwrap();
+        writer.flush_all().unwrap();
+        for path in ["1200-1399/Alice.pgn.zst", "1800-1999/Alice.pgn.zst", "1200-1399/Bob.pgn.zst"] {
+            assert!(dir.join(path).is_file(), "{}", path);
+        }
+        assert!(!dir.join("1800-1999/Bob.pgn.zst").exists());
+        let mut alice = writer.player_paths("Alice").unwrap();
+        alice.sort();
+        assert_eq!(alice, [dir.join("1200-1399/Alice.pgn.zst"), dir.join("1800-1999/Alice.pgn.zst")]);
+
+        // Pruning removes the player from every bucket
+        writer.delete_player("Alice").unwrap();
+        assert!(!dir.join("1200-1399/Alice.pgn.zst").exists());
+        assert!(!dir.join("1800-1999/Alice.pgn.zst").exists());
+        assert!(dir.join("1200-1399/Bob.pgn.zst").is_file());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_games_keep_read_order_across_flushes() {
+        let dir = temp_players_dir("read_order");
+        // Tiny buffer: nearly every game triggers a flush, interleaving frames
+        let mut writer = PlayerWriter::new(dir.clone(), 20);
+        for i in 0..10 {
+            writer.add_game("PlayerA", &format!("game A{}", i)).unwrap();
+            writer.add_game("PlayerB", &format!("game B{}", i)).unwrap();
+        }
+        writer.flush_all().unwrap();
+
+        for player in ["PlayerA", "PlayerB"] {
+            let file = fs::File::open(writer.player_path(player)).unwrap();
+            let mut text = String::new();
+            std::io::Read::read_to_string(&mut zstd::stream::read::Decoder::new(file).unwrap(), &mut text)
+                .unwrap();
+            let expected: String = (0..10)
+                .map(|i| format!("game {}{}\n\n", &player[6..], i))
+                .collect();
+            assert_eq!(text, expected);
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_threads_produce_the_same_output() {
+        let run = |threads: usize| {
+            let dir = temp_players_dir(&format!("write_threads_{}", threads));
+            let journal = RecordingJournal::default();
+            let mut writer = PlayerWriter::new(dir.clone(), usize::MAX)
+                .with_write_threads(threads)
+                .with_journal(&journal);
+            for flush in 0..3 {
+                for i in 0..40 {
+                    let game = format!("[Round \"{}\"]\n\n1. e4 e5 2. Nf3 {} 1-0", flush, i);
+                    writer.add_game(&format!("Player{:02}", i), &game).unwrap();
+                }
+                writer.flush_all().unwrap();
+            }
+            let files: Vec<Vec<u8>> = (0..40).map(|i| fs::read(writer.player_path(&format!("Player{:02}", i))).unwrap()).collect();
+            let records: Vec<Vec<(String, u32, u64)>> = journal
+                .0
+                .borrow()
+                .iter()
+                .map(|record| record.iter().map(|f| (f.player.clone(), f.games, f.len)).collect())
+                .collect();
+            let bytes = writer.bytes_written();
+            drop(writer);
+            fs::remove_dir_all(dir).unwrap();
+            (files, records, bytes)
+        };
+        let (files, records, bytes) = run(1);
+        assert_eq!(records.len(), 4, "first appends, then one record per flush");
+        assert_eq!(bytes, files.iter().map(|f| f.len() as u64).sum::<u64>());
+        assert_eq!(run(4), (files, records, bytes));
+
+        // A file that cannot be written fails the flush on any thread
+        let dir = temp_players_dir("write_threads_error");
+        let mut writer = PlayerWriter::new(dir.clone(), usize::MAX).with_write_threads(4);
+        fs::create_dir_all(writer.player_path("Blocked")).unwrap();
+        for player in ["Alice", "Blocked", "Bob"] {
+            writer.add_game(player, "1. e4 e5 1-0").unwrap();
+        }
+        assert!(writer.flush_all().is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Flush time of 1, 2 and 4 write threads over many players. Run with
+    /// `cargo test --release bench_write_threads -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_write_threads() {
+        for threads in [1, 2, 4] {
+            let dir = temp_players_dir(&format!("bench_write_threads_{}", threads));
+            let mut writer = PlayerWriter::new(dir.clone(), usize::MAX).with_write_threads(threads);
+            for game in 0..2_000 {
+                for player in 0..200 {
+                    let pgn = format!("[Site \"https://lichess.org/{:08x}\"]\n\n1. e4 e5 2. Nf3 Nc6 {} 1-0", game * 200 + player, game);
+                    writer.add_game(&format!("Player{:03}", player), &pgn).unwrap();
+                }
+            }
+            let buffered = writer.buffered_bytes();
+            let started = Instant::now();
+            writer.flush_all().unwrap();
+            println!(
+                "write_threads={}: {:.0} MB flushed in {:.2?} ({:.0} MB/s)",
+                threads,
+                buffered as f64 / 1_048_576.0,
+                started.elapsed(),
+                buffered as f64 / 1_048_576.0 / started.elapsed().as_secs_f64()
+            );
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
 }